@@ -0,0 +1,171 @@
+//! Module for ARP (Address Resolution Protocol) Packet Handling.
+//!
+//! This module defines the structure and functionalities for creating and parsing ARP packets
+//! for IPv4 over Ethernet, used to resolve IP addresses to MAC addresses on the local segment.
+
+use crate::ipv4::internet_packet::format_ipv4_address;
+use crate::mac_address::MacAddr;
+
+use std::fmt;
+
+/// ARP operation code of a request.
+pub const ARP_REQUEST: u16 = 1;
+/// ARP operation code of a reply.
+pub const ARP_REPLY: u16 = 2;
+
+/// Length of an ARP packet for IPv4 over Ethernet.
+pub const ARP_PACKET_LEN: usize = 28;
+
+/// Represents an ARP packet for IPv4 over Ethernet.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ARPPacket {
+    /// Hardware type, 1 for Ethernet.
+    pub hardware_type: u16,
+    /// Protocol type, 0x0800 for IPv4.
+    pub protocol_type: u16,
+    /// Length of a hardware address.
+    pub hardware_len: u8,
+    /// Length of a protocol address.
+    pub protocol_len: u8,
+    /// Operation, request or reply.
+    pub operation: u16,
+    /// MAC address of the sender.
+    pub sender_hardware_address: MacAddr,
+    /// IP address of the sender.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::ipv4_address"))]
+    pub sender_protocol_address: [u8; 4],
+    /// MAC address of the target, all zeros in a request.
+    pub target_hardware_address: MacAddr,
+    /// IP address of the target.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::ipv4_address"))]
+    pub target_protocol_address: [u8; 4],
+}
+
+impl ARPPacket {
+    /// Converts the ARP packet into bytes for transmission.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(ARP_PACKET_LEN);
+        bytes.extend_from_slice(&self.hardware_type.to_be_bytes());
+        bytes.extend_from_slice(&self.protocol_type.to_be_bytes());
+        bytes.push(self.hardware_len);
+        bytes.push(self.protocol_len);
+        bytes.extend_from_slice(&self.operation.to_be_bytes());
+        bytes.extend_from_slice(&self.sender_hardware_address.octets());
+        bytes.extend_from_slice(&self.sender_protocol_address);
+        bytes.extend_from_slice(&self.target_hardware_address.octets());
+        bytes.extend_from_slice(&self.target_protocol_address);
+        bytes
+    }
+
+    /// Converts a byte array into an ARP packet.
+    ///
+    /// Returns `None` if the bytes are too short to hold an IPv4 over Ethernet ARP packet.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ARPPacket> {
+        if bytes.len() < ARP_PACKET_LEN {
+            return None;
+        }
+        let mut sender_hardware_address = [0; 6];
+        let mut sender_protocol_address = [0; 4];
+        let mut target_hardware_address = [0; 6];
+        let mut target_protocol_address = [0; 4];
+        sender_hardware_address.copy_from_slice(&bytes[8..14]);
+        sender_protocol_address.copy_from_slice(&bytes[14..18]);
+        target_hardware_address.copy_from_slice(&bytes[18..24]);
+        target_protocol_address.copy_from_slice(&bytes[24..28]);
+
+        Some(ARPPacket {
+            hardware_type: u16::from_be_bytes([bytes[0], bytes[1]]),
+            protocol_type: u16::from_be_bytes([bytes[2], bytes[3]]),
+            hardware_len: bytes[4],
+            protocol_len: bytes[5],
+            operation: u16::from_be_bytes([bytes[6], bytes[7]]),
+            sender_hardware_address: MacAddr(sender_hardware_address),
+            sender_protocol_address,
+            target_hardware_address: MacAddr(target_hardware_address),
+            target_protocol_address,
+        })
+    }
+
+    /// Creates a new ARP request asking who has `target_ip`.
+    ///
+    /// # Arguments
+    /// * `sender_mac` - MAC address of the local interface.
+    /// * `sender_ip` - IP address of the local interface.
+    /// * `target_ip` - IP address to resolve.
+    pub fn new_request(sender_mac: MacAddr, sender_ip: [u8; 4], target_ip: [u8; 4]) -> ARPPacket {
+        ARPPacket {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            hardware_len: 6,
+            protocol_len: 4,
+            operation: ARP_REQUEST,
+            sender_hardware_address: sender_mac,
+            sender_protocol_address: sender_ip,
+            target_hardware_address: MacAddr::ZERO,
+            target_protocol_address: target_ip,
+        }
+    }
+
+    /// Creates the ARP reply answering a request, saying that its target IP is at `sender_mac`.
+    ///
+    /// # Arguments
+    /// * `request` - ARP request to answer.
+    /// * `sender_mac` - MAC address of the local interface.
+    pub fn new_reply(request: &ARPPacket, sender_mac: MacAddr) -> ARPPacket {
+        ARPPacket {
+            hardware_type: 1,
+            protocol_type: 0x0800,
+            hardware_len: 6,
+            protocol_len: 4,
+            operation: ARP_REPLY,
+            sender_hardware_address: sender_mac,
+            sender_protocol_address: request.target_protocol_address,
+            target_hardware_address: request.sender_hardware_address,
+            target_protocol_address: request.sender_protocol_address,
+        }
+    }
+
+    /// Creates a new gratuitous ARP packet announcing that `ip` is at `sender_mac`.
+    ///
+    /// Sender and target IP are both `ip`, so hosts update their caches without
+    /// resolving anything.
+    ///
+    /// # Arguments
+    /// * `sender_mac` - MAC address that now owns the IP.
+    /// * `ip` - IP address being announced.
+    /// * `operation` - `ARP_REQUEST` for an announcement, `ARP_REPLY` for an unsolicited reply.
+    pub fn new_gratuitous(sender_mac: MacAddr, ip: [u8; 4], operation: u16) -> ARPPacket {
+        let mut packet = ARPPacket::new_request(sender_mac, ip, ip);
+        packet.operation = operation;
+        packet
+    }
+}
+
+/// Implements the Display trait for ARPPacket.
+impl fmt::Display for ARPPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let operation = match self.operation {
+            ARP_REQUEST => "request",
+            ARP_REPLY => "reply",
+            _ => "unknown",
+        };
+        writeln!(f, "ARP: -----ARP Header-----")?;
+        writeln!(f, "ARP:")?;
+        writeln!(f, "ARP: Hardware type= {}", self.hardware_type)?;
+        writeln!(f, "ARP: Protocol type= 0x{:04x}", self.protocol_type)?;
+        writeln!(f, "ARP: Operation= {} ({})", self.operation, operation)?;
+        writeln!(
+            f,
+            "ARP: Sender= {} {}",
+            self.sender_hardware_address,
+            format_ipv4_address(&self.sender_protocol_address)
+        )?;
+        writeln!(
+            f,
+            "ARP: Target= {} {}",
+            self.target_hardware_address,
+            format_ipv4_address(&self.target_protocol_address)
+        )?;
+        writeln!(f, "ARP: -----ARP Header-----")
+    }
+}
@@ -0,0 +1,601 @@
+//! Command line of the tool.
+//!
+//! Holds the global options shared by every command, the table of commands the
+//! help and the shell completions are generated from, and helpers parsing the
+//! values of options.
+
+use rust_network::dns::{DnsClient, Resolver};
+use rust_network::ethernet_frame::VlanTag;
+use rust_network::interface::route::default_interface;
+use rust_network::transport::capture::PcapTransport;
+use rust_network::transport::raw_socket::RawSocketTransport;
+use rust_network::transport::simulated::SimulatedNetwork;
+use rust_network::transport::Transport;
+
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// How much the commands print.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Verbosity {
+    /// Only summaries, with `-q`.
+    Quiet,
+    /// One line per probe or packet.
+    Normal,
+    /// Full decodes, with `-v`.
+    Verbose,
+}
+
+/// Format the commands print their results in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    /// Lines for people to read.
+    Text,
+    /// A JSON array of records.
+    Json,
+    /// One JSON record per line.
+    Ndjson,
+    /// Comma-separated records under a header row.
+    Csv,
+}
+
+impl OutputFormat {
+    /// Parses the value of `--format`.
+    pub(crate) fn parse(s: &str) -> Option<OutputFormat> {
+        match s {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "ndjson" => Some(OutputFormat::Ndjson),
+            "csv" => Some(OutputFormat::Csv),
+            _ => None,
+        }
+    }
+}
+
+/// Represents a command of the tool, for the help and the shell completions.
+pub(crate) struct Command {
+    /// Name typed on the command line.
+    pub(crate) name: &'static str,
+    /// Arguments of the command, as shown in its usage.
+    pub(crate) usage: &'static str,
+    /// One-line description.
+    pub(crate) about: &'static str,
+    /// Options of the command and their descriptions.
+    pub(crate) options: &'static [(&'static str, &'static str)],
+    /// Whether the command writes records with `--format json`, `ndjson` or `csv`.
+    pub(crate) records: bool,
+}
+
+/// Commands of the tool.
+pub(crate) const COMMANDS: &[Command] = &[
+    Command {
+        name: "ping",
+        usage: "[host]",
+        about: "Send ICMP echo requests to a host",
+        options: &[
+            ("-c <count>", "Stop after this many probes"),
+            ("-i <seconds>", "Time between probes"),
+            ("-t <ttl>", "TTL of the echo requests"),
+            ("-W <seconds>", "How long to wait for each reply"),
+            ("-s <size>", "Bytes of data per echo request, timestamp included"),
+            ("-p <pattern>", "Fill of the data: hex bytes, zeros, incrementing or random"),
+            ("--timestamp <format>", "Send time in the data: nanos, timeval or none"),
+        ],
+        records: true,
+    },
+    Command {
+        name: "traceroute",
+        usage: "<host>",
+        about: "Show the routers on the path to a host",
+        options: &[
+            ("-m <hops>", "Largest TTL to try"),
+            ("--probes <count>", "Probes per hop"),
+            ("-W <seconds>", "How long to wait for each reply"),
+        ],
+        records: true,
+    },
+    Command {
+        name: "arping",
+        usage: "<ip>",
+        about: "Send ARP requests to a host on the local segment",
+        options: &[
+            ("-c <count>", "Stop after this many requests"),
+            ("-i <seconds>", "Time between requests"),
+            ("-b", "Keep broadcasting after the first reply"),
+            ("-U", "Announce the address with gratuitous ARP requests"),
+            ("-A", "Announce the address with unsolicited ARP replies"),
+        ],
+        records: true,
+    },
+    Command {
+        name: "arpscan",
+        usage: "",
+        about: "Find the hosts of the local subnet with ARP",
+        options: &[],
+        records: true,
+    },
+    Command {
+        name: "sweep",
+        usage: "<cidr>",
+        about: "Find the hosts of a range answering echo requests",
+        options: &[],
+        records: true,
+    },
+    Command {
+        name: "sniff",
+        usage: "",
+        about: "Print the packets received on the interface",
+        options: &[("-c <count>", "Stop after this many packets")],
+        records: true,
+    },
+    Command {
+        name: "decode",
+        usage: "[hex...]",
+        about: "Decode a packet given as hex or a hex dump, by default on stdin",
+        options: &[("--layer <layer>", "Layer the bytes start at: ether, ip or icmp")],
+        records: true,
+    },
+    Command {
+        name: "craft",
+        usage: "<host>",
+        about: "Send an ICMP packet with any header fields, or write it to a pcap file",
+        options: &[
+            ("--src-mac <mac>", "Source MAC address"),
+            ("--dst-mac <mac>", "Destination MAC address"),
+            ("--tos <byte>", "Whole TOS byte"),
+            ("--dscp <name|value>", "DSCP, e.g. EF or 46"),
+            ("--ecn <bits>", "ECN bits, 0 to 3"),
+            ("--ihl <words>", "IPv4 header length, whatever the options"),
+            ("--length <bytes>", "IPv4 total length, whatever the payload"),
+            ("--id <id>", "IPv4 identification"),
+            ("--flags <flags>", "Fragmentation flags: DF, MF, RS, comma separated, or none"),
+            ("--frag-offset <offset>", "Fragment offset, in units of 8 bytes"),
+            ("--ttl <ttl>", "Time to live"),
+            ("--protocol <number>", "Protocol of the IPv4 header"),
+            ("--ip-options <hex>", "IPv4 options"),
+            ("--ip-checksum <value>", "IPv4 header checksum instead of the correct one"),
+            ("--type <type>", "ICMP type"),
+            ("--code <code>", "ICMP code"),
+            ("--icmp-id <id>", "ICMP identifier"),
+            ("--seq <seq>", "ICMP sequence number"),
+            ("--icmp-checksum <value>", "ICMP checksum instead of the correct one"),
+            ("--payload <hex>", "Data following the ICMP header"),
+            ("--pattern <hex>", "Bytes repeated over the payload"),
+            ("--size <bytes>", "Payload size"),
+            ("-c <count>", "Send this many copies"),
+            ("-i <seconds>", "Time between copies"),
+            ("-w <file>", "Write the frames to a pcap file instead of sending them"),
+        ],
+        records: false,
+    },
+    Command {
+        name: "pmtu",
+        usage: "<host>",
+        about: "Discover the path MTU to a host",
+        options: &[("-W <seconds>", "How long to wait for each reply")],
+        records: true,
+    },
+    Command {
+        name: "responder",
+        usage: "[ip...]",
+        about: "Answer ARP and echo requests for addresses",
+        options: &[],
+        records: false,
+    },
+    Command {
+        name: "monitor",
+        usage: "<host...>",
+        about: "Ping hosts continuously and report when they go up or down",
+        options: &[
+            ("-c <count>", "Stop after this many probes to each host"),
+            ("-i <seconds>", "Time between probes to each host"),
+            ("-W <seconds>", "How long to wait for each reply"),
+            ("--report <seconds>", "Time between statistics tables"),
+        ],
+        records: true,
+    },
+    Command {
+        name: "interfaces",
+        usage: "",
+        about: "List the interfaces and routes",
+        options: &[],
+        records: false,
+    },
+    Command {
+        name: "lookup",
+        usage: "<name|ip>",
+        about: "Resolve a name or an address",
+        options: &[],
+        records: false,
+    },
+    Command {
+        name: "completions",
+        usage: "<bash|zsh|fish>",
+        about: "Print a shell completion script",
+        options: &[],
+        records: false,
+    },
+    Command {
+        name: "help",
+        usage: "[command]",
+        about: "Show the help of the tool or of a command",
+        options: &[],
+        records: false,
+    },
+];
+
+/// Options valid with every command, and their descriptions.
+pub(crate) const GLOBAL_OPTIONS: &[(&str, &str)] = &[
+    ("-I <interface>", "Interface to use instead of the default route's"),
+    ("--tap <device>", "Use a TAP device (Linux)"),
+    ("--tun <device>", "Use a TUN device (Linux)"),
+    ("--raw", "Use raw IP sockets, letting the kernel route"),
+    ("--icmp", "Use an unprivileged ICMP socket (Linux)"),
+    ("--sim <seed>", "Use a simulated lab network"),
+    ("--source <ip>", "Address to send from"),
+    ("--vlan <stack>", "Tag echo requests, e.g. 100 or 200.100"),
+    ("--dns <server>", "Resolve names by asking this server"),
+    ("-n", "Do not resolve names"),
+    ("--format <format>", "Output format: text, json, ndjson or csv"),
+    ("-v", "Print full decodes"),
+    ("-q", "Print only summaries"),
+    ("-h", "Show the help"),
+];
+
+/// Options valid with every command, removed from the command line.
+pub(crate) struct GlobalOptions {
+    /// Interface to capture on, by default the default route's.
+    pub(crate) interface: Option<String>,
+    /// TAP device to use instead of an interface.
+    pub(crate) tap: Option<String>,
+    /// TUN device to use instead of an interface.
+    pub(crate) tun: Option<String>,
+    /// Use raw IP sockets.
+    pub(crate) raw: bool,
+    /// Use an ICMP datagram socket.
+    pub(crate) icmp: bool,
+    /// Seed of the simulated network to use instead of an interface.
+    pub(crate) sim: Option<u64>,
+    /// Address to send from.
+    pub(crate) source: Option<[u8; 4]>,
+    /// VLAN stack the echo requests are tagged with.
+    pub(crate) vlan_tags: Vec<VlanTag>,
+    /// How names are resolved.
+    pub(crate) resolver: Resolver,
+    /// Format of the output.
+    pub(crate) format: OutputFormat,
+    /// How much is printed.
+    pub(crate) verbosity: Verbosity,
+}
+
+impl GlobalOptions {
+    /// Parses the global options, removing them from `args` wherever they appear.
+    pub(crate) fn from_args(args: &mut Vec<String>) -> Result<GlobalOptions, String> {
+        let interface = take_option(args, "-I")?;
+        let tap = take_option(args, "--tap")?;
+        let tun = take_option(args, "--tun")?;
+        let raw = take_flag(args, "--raw");
+        let icmp = take_flag(args, "--icmp");
+        let sim = match take_option(args, "--sim")? {
+            Some(seed) => Some(seed.parse().map_err(|_| format!("Invalid simulation seed: {}", seed))?),
+            None => None,
+        };
+        let source = match take_option(args, "--source")? {
+            Some(ip) => Some(parse_ipv4(&ip)?),
+            None => None,
+        };
+        // `--vlan 100` tags VLAN 100 of a trunk, `--vlan 200.100` adds a QinQ service tag.
+        let vlan_tags = match take_option(args, "--vlan")? {
+            Some(stack) => VlanTag::parse_stack(&stack)
+                .ok_or_else(|| format!("Invalid VLAN stack {}, expected e.g. 100 or 200.100", stack))?,
+            None => Vec::new(),
+        };
+        let resolver = match (take_flag(args, "-n"), take_option(args, "--dns")?) {
+            (true, _) => Resolver::Disabled,
+            (false, Some(server)) => Resolver::Native(DnsClient::new(parse_ipv4(&server)?)),
+            (false, None) => Resolver::System,
+        };
+        let format = match take_option(args, "--format")? {
+            Some(format) => OutputFormat::parse(&format).ok_or_else(|| format!("Unknown format: {}", format))?,
+            None => OutputFormat::Text,
+        };
+        let verbosity = match (take_flag(args, "-q"), take_flag(args, "-v")) {
+            (true, true) => return Err("-q and -v exclude each other".to_string()),
+            (true, false) => Verbosity::Quiet,
+            (false, true) => Verbosity::Verbose,
+            (false, false) => Verbosity::Normal,
+        };
+        Ok(GlobalOptions {
+            interface,
+            tap,
+            tun,
+            raw,
+            icmp,
+            sim,
+            source,
+            vlan_tags,
+            resolver,
+            format,
+            verbosity,
+        })
+    }
+
+    /// Opens the transport selected by the options.
+    pub(crate) fn open_transport(&self) -> io::Result<Arc<dyn Transport>> {
+        if let Some(seed) = self.sim {
+            return Ok(Arc::new(SimulatedNetwork::lab(seed)));
+        }
+        if self.raw {
+            return Ok(Arc::new(RawSocketTransport::open()?));
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            use rust_network::transport::icmp_socket::IcmpSocketTransport;
+            use rust_network::transport::tuntap::TunTapTransport;
+            if self.icmp {
+                return Ok(Arc::new(IcmpSocketTransport::open()?));
+            }
+            if let Some(name) = &self.tap {
+                return Ok(Arc::new(TunTapTransport::open_tap(name)?));
+            }
+            if let Some(name) = &self.tun {
+                return Ok(Arc::new(TunTapTransport::open_tun(name)?));
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        if self.icmp || self.tap.is_some() || self.tun.is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ICMP socket and TUN/TAP transports are only available on Linux",
+            ));
+        }
+
+        let interface_name = match self.interface.clone().or_else(default_interface) {
+            Some(interface_name) => interface_name,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "no default route, pick an interface with -I",
+                ))
+            }
+        };
+        match PcapTransport::open(&interface_name) {
+            Ok(transport) => Ok(Arc::new(transport)),
+            // Without raw access, ping through an unprivileged ICMP socket instead.
+            #[cfg(target_os = "linux")]
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                match rust_network::transport::icmp_socket::IcmpSocketTransport::open() {
+                    Ok(transport) => {
+                        eprintln!(
+                            "No raw access to {} ({}), using an unprivileged ICMP socket",
+                            interface_name, e
+                        );
+                        Ok(Arc::new(transport))
+                    }
+                    Err(icmp_error) => Err(io::Error::new(
+                        io::ErrorKind::PermissionDenied,
+                        format!(
+                            "no raw access to {} ({}) and ICMP sockets are not permitted ({}); \
+                             run as root, grant CAP_NET_RAW, or add your group to net.ipv4.ping_group_range",
+                            interface_name, e, icmp_error
+                        ),
+                    )),
+                }
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Prints the usage of the tool with its commands and global options.
+pub(crate) fn print_help(program: &str) {
+    println!("Usage: {} [options] <command> [arguments]\n", program);
+    println!("Commands:");
+    for command in COMMANDS {
+        println!("  {:<12} {}", command.name, command.about);
+    }
+    println!("\nGlobal options:");
+    for (option, about) in GLOBAL_OPTIONS {
+        println!("  {:<18} {}", option, about);
+    }
+    println!("\nRun `{} help <command>` for the options of a command.", program);
+}
+
+/// Prints the usage of a command with its options.
+pub(crate) fn print_command_help(program: &str, name: &str) -> Result<(), String> {
+    let command = find_command(name).ok_or_else(|| format!("Unknown command: {}", name))?;
+    println!("{}\n", command.about);
+    println!("Usage: {} [options] {} {}", program, command.name, command.usage);
+    if !command.options.is_empty() {
+        println!("\nOptions:");
+        for (option, about) in command.options {
+            println!("  {:<18} {}", option, about);
+        }
+    }
+    Ok(())
+}
+
+/// Looks up a command by name.
+pub(crate) fn find_command(name: &str) -> Option<&'static Command> {
+    COMMANDS.iter().find(|command| command.name == name)
+}
+
+/// Generates the completion script of a shell for the tool.
+pub(crate) fn completions(shell: &str, program: &str) -> Result<String, String> {
+    let names: Vec<&str> = COMMANDS.iter().map(|command| command.name).collect();
+    let global_flags: Vec<&str> = GLOBAL_OPTIONS.iter().map(|(option, _)| flag_of(option)).collect();
+    let function = program.replace('-', "_");
+
+    let mut script = String::new();
+    match shell {
+        "bash" => {
+            script += &format!("_{}() {{\n", function);
+            script += "    local cur=\"${COMP_WORDS[COMP_CWORD]}\" command=\"\" word\n";
+            script += "    for word in \"${COMP_WORDS[@]:1:COMP_CWORD-1}\"; do\n";
+            script += &format!(
+                "        case \"$word\" in {}) command=\"$word\"; break;; esac\n",
+                names.join("|")
+            );
+            script += "    done\n";
+            script += &format!("    local options=\"{}\"\n", global_flags.join(" "));
+            script += "    case \"$command\" in\n";
+            for command in COMMANDS.iter().filter(|command| !command.options.is_empty()) {
+                let flags: Vec<&str> = command.options.iter().map(|(option, _)| flag_of(option)).collect();
+                script += &format!("        {}) options=\"$options {}\";;\n", command.name, flags.join(" "));
+            }
+            script += "    esac\n";
+            script += "    if [[ -z \"$command\" && \"$cur\" != -* ]]; then\n";
+            script += &format!("        COMPREPLY=($(compgen -W \"{}\" -- \"$cur\"))\n", names.join(" "));
+            script += "    else\n";
+            script += "        COMPREPLY=($(compgen -W \"$options\" -- \"$cur\"))\n";
+            script += "    fi\n";
+            script += "}\n";
+            script += &format!("complete -F _{} {}\n", function, program);
+        }
+        "zsh" => {
+            script += &format!("#compdef {}\n\n", program);
+            script += &format!("_{}() {{\n", function);
+            script += "    local -a commands options\n";
+            script += "    commands=(\n";
+            for command in COMMANDS {
+                script += &format!("        '{}:{}'\n", command.name, quote(command.about));
+            }
+            script += "    )\n";
+            script += "    options=(\n";
+            for (option, about) in GLOBAL_OPTIONS {
+                script += &format!("        '{}[{}]'\n", flag_of(option), quote(about));
+            }
+            script += "    )\n";
+            script += "    if (( CURRENT == 2 )); then\n";
+            script += "        _describe 'command' commands\n";
+            script += "        _arguments $options\n";
+            script += "        return\n";
+            script += "    fi\n";
+            script += "    case \"${words[2]}\" in\n";
+            for command in COMMANDS.iter().filter(|command| !command.options.is_empty()) {
+                let specs: Vec<String> = command
+                    .options
+                    .iter()
+                    .map(|(option, about)| format!("'{}[{}]'", flag_of(option), quote(about)))
+                    .collect();
+                script += &format!("        {}) options+=({});;\n", command.name, specs.join(" "));
+            }
+            script += "    esac\n";
+            script += "    _arguments $options '*:argument:_default'\n";
+            script += "}\n\n";
+            script += &format!("compdef _{} {}\n", function, program);
+        }
+        "fish" => {
+            let no_command = format!("not __fish_seen_subcommand_from {}", names.join(" "));
+            for command in COMMANDS {
+                script += &format!(
+                    "complete -c {} -f -n '{}' -a {} -d '{}'\n",
+                    program,
+                    no_command,
+                    command.name,
+                    quote(command.about)
+                );
+            }
+            for (option, about) in GLOBAL_OPTIONS {
+                script += &format!("complete -c {} {} -d '{}'\n", program, fish_flag(option), quote(about));
+            }
+            for command in COMMANDS {
+                for (option, about) in command.options {
+                    script += &format!(
+                        "complete -c {} -n '__fish_seen_subcommand_from {}' {} -d '{}'\n",
+                        program,
+                        command.name,
+                        fish_flag(option),
+                        quote(about)
+                    );
+                }
+            }
+        }
+        _ => return Err(format!("Unknown shell {}, expected bash, zsh or fish", shell)),
+    }
+    Ok(script)
+}
+
+/// Removes an option and its value from `args`, returning the value.
+///
+/// Fails if the option is the last argument, without a value.
+pub(crate) fn take_option(args: &mut Vec<String>, option: &str) -> Result<Option<String>, String> {
+    let i = match args.iter().position(|arg| arg == option) {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+    if i + 1 >= args.len() {
+        return Err(format!("Missing value for {}", option));
+    }
+    let value = args.remove(i + 1);
+    args.remove(i);
+    Ok(Some(value))
+}
+
+/// Removes a flag from `args`, returning whether it was present.
+pub(crate) fn take_flag(args: &mut Vec<String>, flag: &str) -> bool {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns the value following an option, or an error naming the option.
+pub(crate) fn next_value<'a>(args: &mut std::slice::Iter<'a, String>, option: &str) -> Result<&'a str, String> {
+    args.next()
+        .map(String::as_str)
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+/// Parses a positive count, e.g. the value of `-c`.
+pub(crate) fn parse_count(s: &str) -> Result<u32, String> {
+    match s.parse() {
+        Ok(count) if count > 0 => Ok(count),
+        _ => Err(format!("Invalid count {}, expected a positive number", s)),
+    }
+}
+
+/// Parses a positive number of seconds, e.g. the value of `-i`.
+pub(crate) fn parse_seconds(s: &str) -> Result<Duration, String> {
+    match s.parse::<f64>().ok().and_then(|seconds| Duration::try_from_secs_f64(seconds).ok()) {
+        Some(duration) if !duration.is_zero() => Ok(duration),
+        _ => Err(format!("Invalid duration {}, expected a number of seconds", s)),
+    }
+}
+
+/// Parses a dotted quad IPv4 address.
+pub(crate) fn parse_ipv4(s: &str) -> Result<[u8; 4], String> {
+    s.parse::<Ipv4Addr>()
+        .map(|ip| ip.octets())
+        .map_err(|_| format!("Invalid IP address: {}", s))
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Returns the flag of an option as listed in the help, e.g. `-c` for `-c <count>`.
+fn flag_of(option: &str) -> &str {
+    option.split(' ').next().unwrap_or(option)
+}
+
+/// Builds the fish arguments matching an option, e.g. `-s c -r` for `-c <count>`.
+fn fish_flag(option: &str) -> String {
+    let flag = flag_of(option);
+    let kind = if flag.len() < option.len() { " -r" } else { "" };
+    match flag.strip_prefix("--") {
+        Some(long) => format!("-l {}{}", long, kind),
+        None if flag.len() == 2 => format!("-s {}{}", &flag[1..], kind),
+        // Single dash options longer than a letter.
+        None => format!("-o {}{}", &flag[1..], kind),
+    }
+}
+
+/// Escapes single quotes for the completion scripts.
+fn quote(text: &str) -> String {
+    text.replace('\'', "'\\''")
+}
@@ -0,0 +1,240 @@
+//! ARP ping.
+//!
+//! Checks layer 2 reachability of a host by sending it ARP requests and timing
+//! the replies, or announces an address with gratuitous ARP to test failover of
+//! virtual IPs.
+
+use crate::cli::next_value;
+use crate::commands::ping::PingStatistics;
+use crate::output::{milliseconds, Output, Record};
+use rust_network::arp::{ARPPacket, ARP_REPLY, ARP_REQUEST};
+use rust_network::ethernet_frame::EthernetFrame;
+use rust_network::interface::lookup_interface;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::mac_address::MacAddr;
+use rust_network::transport::Transport;
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+/// Kind of ARP packets sent by arping.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum ArpingMode {
+    /// ARP requests to the target, timing the replies.
+    Request,
+    /// Gratuitous ARP requests announcing the target as our own address.
+    Announce,
+    /// Unsolicited ARP replies announcing the target as our own address.
+    UnsolicitedReply,
+}
+
+/// Options of an arping session.
+pub(crate) struct ArpingOptions {
+    /// Address to resolve, or to announce.
+    pub(crate) target: [u8; 4],
+    /// Number of packets to send, `None` to run until interrupted.
+    pub(crate) count: Option<u32>,
+    /// Time between two packets.
+    pub(crate) interval: Duration,
+    /// Keep broadcasting instead of switching to unicast after the first reply.
+    pub(crate) broadcast_only: bool,
+    /// Kind of ARP packets to send.
+    pub(crate) mode: ArpingMode,
+}
+
+impl ArpingOptions {
+    /// Parses the arping options from the command line arguments following `arping`.
+    ///
+    /// Usage: `<ip> [-c count] [-i interval] [-b] [-U | -A]`
+    pub(crate) fn from_args(args: &[String]) -> Result<ArpingOptions, String> {
+        let mut target = None;
+        let mut options = ArpingOptions {
+            target: [0; 4],
+            count: None,
+            interval: Duration::from_secs(1),
+            broadcast_only: false,
+            mode: ArpingMode::Request,
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" => {
+                    let count = next_value(&mut args, arg)?;
+                    options.count = Some(count.parse().map_err(|_| format!("Invalid count: {}", count))?);
+                }
+                "-i" => {
+                    let interval = next_value(&mut args, arg)?;
+                    let seconds: f64 = interval
+                        .parse()
+                        .map_err(|_| format!("Invalid interval: {}", interval))?;
+                    options.interval = Duration::try_from_secs_f64(seconds)
+                        .map_err(|_| format!("Invalid interval: {}", interval))?;
+                }
+                "-b" => options.broadcast_only = true,
+                "-U" => options.mode = ArpingMode::Announce,
+                "-A" => options.mode = ArpingMode::UnsolicitedReply,
+                _ if target.is_none() => {
+                    let ip: Ipv4Addr = arg.parse().map_err(|_| format!("Invalid IP address: {}", arg))?;
+                    target = Some(ip.octets());
+                }
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        options.target = target.ok_or("Missing target IP address")?;
+        Ok(options)
+    }
+}
+
+/// Runs an arping session and prints its summary.
+///
+/// # Arguments
+/// * `transport` - Link layer transport to send on.
+/// * `options` - Options of the session.
+/// * `source_ip` - Address to send from, instead of the interface's own, e.g. on a TAP link.
+/// * `output` - Output of the replies and the summary, or of a record per request and a summary.
+pub(crate) fn arping(
+    transport: &dyn Transport,
+    options: &ArpingOptions,
+    source_ip: Option<[u8; 4]>,
+    output: &mut Output,
+) {
+    let interface = match lookup_interface(transport.name()) {
+        Some(interface) => interface,
+        None => {
+            eprintln!("No such interface: {}", transport.name());
+            return;
+        }
+    };
+    let mac = match transport.mac_address() {
+        Some(mac) => mac,
+        None => {
+            eprintln!("Interface {} has no MAC address", interface.name);
+            return;
+        }
+    };
+    let source_ip = match (options.mode, source_ip.or(interface.ipv4)) {
+        (ArpingMode::Request, Some(ip)) => ip,
+        (ArpingMode::Request, None) => {
+            eprintln!("Interface {} has no IPv4 address", interface.name);
+            return;
+        }
+        (_, _) => options.target,
+    };
+
+    let target = format_ipv4_address(&options.target);
+    if output.is_text() {
+        println!(
+            "ARPING {} from {} {}",
+            target,
+            format_ipv4_address(&source_ip),
+            interface.name
+        );
+    }
+
+    let mut statistics = PingStatistics::new(target.clone());
+    let mut destination = MacAddr::BROADCAST;
+    let mut sent = 0;
+    while options.count.is_none_or(|count| sent < count) {
+        let packet = match options.mode {
+            ArpingMode::Request => ARPPacket::new_request(mac, source_ip, options.target),
+            ArpingMode::Announce => ARPPacket::new_gratuitous(mac, options.target, ARP_REQUEST),
+            ArpingMode::UnsolicitedReply => ARPPacket::new_gratuitous(mac, options.target, ARP_REPLY),
+        };
+        let mut frame = EthernetFrame::new_arp(packet, destination);
+        let sent_at = Instant::now();
+        if let Err(e) = transport.send_frame(&mut frame) {
+            eprintln!("Failed to send to {}: {}", target, e);
+        }
+        statistics.record_sent();
+        sent += 1;
+
+        let deadline = sent_at + options.interval;
+        let broadcast = destination == MacAddr::BROADCAST;
+        let mut answered = false;
+        loop {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            let frame = match transport.recv_frame(deadline - now) {
+                Ok(Some(frame)) => frame,
+                Ok(None) => continue,
+                Err(e) => {
+                    eprintln!("Receive failed: {}", e);
+                    return;
+                }
+            };
+            let reply = match frame.arp() {
+                Some(arp) if is_reply_to(arp, options, source_ip, mac) => arp,
+                _ => continue,
+            };
+            let rtt = sent_at.elapsed();
+            statistics.record_reply(rtt);
+            answered = true;
+            if !output.is_text() {
+                output.event(request_record(&target, sent - 1, broadcast, Some((reply.sender_hardware_address, rtt))));
+            } else {
+                println!(
+                    "{} reply from {} [{}]  {:.3} ms",
+                    if broadcast { "Broadcast" } else { "Unicast" },
+                    target,
+                    reply.sender_hardware_address,
+                    rtt.as_secs_f64() * 1000.0
+                );
+            }
+            if options.mode == ArpingMode::Request && !options.broadcast_only {
+                destination = reply.sender_hardware_address;
+            }
+            // One reply per request, later ones would skew the RTT of the next request.
+            break;
+        }
+        if !answered && !output.is_text() {
+            output.event(request_record(&target, sent - 1, broadcast, None));
+        }
+
+        let now = Instant::now();
+        if now < deadline {
+            std::thread::sleep(deadline - now);
+        }
+    }
+
+    if output.is_text() {
+        print!("\n{}", statistics);
+    } else {
+        output.summary(statistics.to_record());
+        output.finish();
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Builds the record of a request: the MAC address and round trip time of its reply,
+/// or a timeout.
+fn request_record(target: &str, sequence: u32, broadcast: bool, reply: Option<(MacAddr, Duration)>) -> Record {
+    Record::new("event")
+        .field("target", target)
+        .field("seq", sequence)
+        .field("broadcast", broadcast)
+        .field("mac", reply.map(|(mac, _)| mac.to_string()))
+        .field("rtt_ms", reply.map(|(_, rtt)| milliseconds(rtt)))
+        .field("status", if reply.is_some() { "reply" } else { "timeout" })
+}
+
+/// Checks whether an ARP packet answers our request.
+///
+/// In the gratuitous modes any reply for the announced address from another MAC
+/// means another host claims it, which is what failover tests look for.
+fn is_reply_to(arp: &ARPPacket, options: &ArpingOptions, source_ip: [u8; 4], mac: MacAddr) -> bool {
+    if arp.operation != ARP_REPLY
+        || arp.sender_protocol_address != options.target
+        || arp.sender_hardware_address == mac
+    {
+        return false;
+    }
+    match options.mode {
+        ArpingMode::Request => arp.target_protocol_address == source_ip,
+        ArpingMode::Announce | ArpingMode::UnsolicitedReply => true,
+    }
+}
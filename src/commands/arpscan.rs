@@ -0,0 +1,165 @@
+//! ARP scan of the local segment.
+//!
+//! Broadcasts an ARP request for every address of the interface's subnet and
+//! lists the hosts that answered. Unlike ICMP, ARP cannot be filtered by hosts on
+//! the same segment, so this also finds hosts that drop echo requests.
+
+use crate::output::{Output, Record};
+use rust_network::arp::{ARPPacket, ARP_REPLY};
+use rust_network::ethernet_frame::EthernetFrame;
+use rust_network::interface::lookup_interface;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::ipv4::subnet::{hosts_in_subnet, netmask_to_prefix, MIN_SCAN_PREFIX};
+use rust_network::mac_address::MacAddr;
+use rust_network::transport::Transport;
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// ARP requests sent per second.
+const REQUESTS_PER_SECOND: u64 = 200;
+
+/// How long to keep listening for replies after the last request was sent.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Read timeout of the receiver, so it can notice the scan is over.
+const RECV_POLL: Duration = Duration::from_millis(100);
+
+/// Scans the subnet of the transport's interface and prints the hosts that replied.
+///
+/// # Arguments
+/// * `transport` - Link layer transport to scan on.
+/// * `source_ip` - Address to send from, instead of the interface's own, e.g. on a TAP link.
+/// * `output` - Output of the hosts and the summary, or of a record per answer and a summary.
+pub(crate) fn arpscan(transport: Arc<dyn Transport>, source_ip: Option<[u8; 4]>, output: &mut Output) {
+    let interface = match lookup_interface(transport.name()) {
+        Some(interface) => interface,
+        None => {
+            eprintln!("No such interface: {}", transport.name());
+            return;
+        }
+    };
+    let (mac, ip, netmask) = match (transport.mac_address(), source_ip.or(interface.ipv4), interface.netmask) {
+        (Some(mac), Some(ip), Some(netmask)) => (mac, ip, netmask),
+        _ => {
+            eprintln!("Interface {} has no MAC or IPv4 address", interface.name);
+            return;
+        }
+    };
+    let prefix = netmask_to_prefix(netmask);
+    let hosts = match hosts_in_subnet(ip, prefix) {
+        Some(hosts) => hosts,
+        None => {
+            eprintln!(
+                "Subnet {}/{} is too large to scan, the limit is /{}",
+                format_ipv4_address(&ip),
+                prefix,
+                MIN_SCAN_PREFIX
+            );
+            return;
+        }
+    };
+    let subnet = format!("{}/{}", format_ipv4_address(&ip), prefix);
+    if output.is_text() {
+        println!("Scanning {} on {} ({} hosts)", subnet, interface.name, hosts.len());
+    }
+
+    // Every MAC that answered for an IP, in the order they were first seen.
+    let replies: Arc<Mutex<HashMap<[u8; 4], Vec<MacAddr>>>> = Arc::new(Mutex::new(HashMap::new()));
+    let done = Arc::new(AtomicBool::new(false));
+
+    let receiver = {
+        let transport = Arc::clone(&transport);
+        let replies = Arc::clone(&replies);
+        let done = Arc::clone(&done);
+        thread::spawn(move || {
+            while !done.load(Ordering::Relaxed) {
+                let frame = match transport.recv_frame(RECV_POLL) {
+                    Ok(Some(frame)) => frame,
+                    Ok(None) => continue,
+                    Err(e) => {
+                        eprintln!("Receive failed: {}", e);
+                        break;
+                    }
+                };
+                let arp = match frame.arp() {
+                    Some(arp) if arp.operation == ARP_REPLY && arp.target_protocol_address == ip => arp,
+                    _ => continue,
+                };
+                let mut replies = replies.lock().unwrap();
+                let macs = replies.entry(arp.sender_protocol_address).or_default();
+                if !macs.contains(&arp.sender_hardware_address) {
+                    macs.push(arp.sender_hardware_address);
+                }
+            }
+        })
+    };
+
+    let interval = Duration::from_nanos(1_000_000_000 / REQUESTS_PER_SECOND);
+    for host in hosts.iter().filter(|host| **host != ip) {
+        let request = ARPPacket::new_request(mac, ip, *host);
+        let mut frame = EthernetFrame::new_arp(request, MacAddr::BROADCAST);
+        if let Err(e) = transport.send_frame(&mut frame) {
+            eprintln!("Failed to send to {}: {}", format_ipv4_address(host), e);
+        }
+        thread::sleep(interval);
+    }
+
+    thread::sleep(REPLY_TIMEOUT);
+    done.store(true, Ordering::Relaxed);
+    receiver.join().unwrap();
+
+    let replies = replies.lock().unwrap();
+    let mut ips: Vec<&[u8; 4]> = replies.keys().collect();
+    ips.sort();
+    if !output.is_text() {
+        for ip in ips.iter() {
+            let macs = &replies[*ip];
+            for mac in macs.iter() {
+                output.event(
+                    Record::new("event")
+                        .field("target", format_ipv4_address(ip))
+                        .field("mac", mac.to_string())
+                        .field("vendor", mac.describe())
+                        .field("conflict", macs.len() > 1),
+                );
+            }
+        }
+        output.summary(
+            Record::new("summary")
+                .field("subnet", subnet)
+                .field("hosts", hosts.len())
+                .field("answered", ips.len())
+                .field("conflicts", ips.iter().filter(|ip| replies[**ip].len() > 1).count()),
+        );
+        output.finish();
+        return;
+    }
+    println!("\n{:<15} {:<17} Vendor", "IP", "MAC");
+    for ip in ips.iter() {
+        for mac in replies[*ip].iter() {
+            println!(
+                "{:<15} {:<17} {}",
+                format_ipv4_address(ip),
+                mac,
+                mac.describe()
+            );
+        }
+    }
+    println!("{} hosts answered", ips.len());
+
+    for ip in ips.iter() {
+        let macs = &replies[*ip];
+        if macs.len() > 1 {
+            let macs: Vec<String> = macs.iter().map(MacAddr::to_string).collect();
+            println!(
+                "WARNING: IP conflict on {}: answered by {}",
+                format_ipv4_address(ip),
+                macs.join(", ")
+            );
+        }
+    }
+}
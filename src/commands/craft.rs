@@ -0,0 +1,425 @@
+//! Crafting of arbitrary ICMP packets.
+//!
+//! Every field of the Ethernet, IPv4 and ICMP headers can be set from the command
+//! line, including values no well-behaved stack would send: reserved flags, bogus
+//! lengths and deliberately wrong checksums, to see how firewalls and middleboxes
+//! treat them. Crafted frames are sent on the transport or written to a pcap file.
+//!
+//! Raw IP sockets let the kernel fill in some fields, e.g. the IPv4 checksum on
+//! Linux; use a link layer transport to send the header exactly as crafted.
+
+use crate::cli::{next_value, parse_count, parse_seconds};
+use crate::commands::decode::parse_hex_dump;
+use crate::commands::sniff::summarize;
+use rust_network::ethernet_frame::EthernetFrame;
+use rust_network::interface::route::next_hop;
+use rust_network::ipv4::fields::{Dscp, Ecn, FragmentFlags, IpProtocol};
+use rust_network::ipv4::icmp::{ICMPPacket, ECHO_REQUEST};
+use rust_network::ipv4::internet_packet::IPV4;
+use rust_network::mac_address::MacAddr;
+use rust_network::pcap_file::{PcapWriter, LINKTYPE_ETHERNET};
+use rust_network::pinger::PingerOptions;
+use rust_network::transport::{Layer, Transport};
+
+use std::time::{Duration, SystemTime};
+
+/// Options of a crafted packet, and of how it is sent.
+///
+/// Fields left `None` get the value a regular packet would have.
+pub(crate) struct CraftOptions {
+    /// Host the packet is sent to, a name or an address.
+    pub(crate) target: String,
+    /// Source MAC address, by default the interface's.
+    pub(crate) source_mac: Option<MacAddr>,
+    /// Destination MAC address, by default the next hop's.
+    pub(crate) destination_mac: Option<MacAddr>,
+    /// Differentiated Services Code Point.
+    pub(crate) dscp: Dscp,
+    /// Explicit Congestion Notification bits.
+    pub(crate) ecn: Ecn,
+    /// Header length in 32-bit words, by default the length of the header built.
+    pub(crate) header_len: Option<u8>,
+    /// Total length, by default the length of the packet built.
+    pub(crate) total_length: Option<u16>,
+    /// Identification.
+    pub(crate) identification: u16,
+    /// Fragmentation flags.
+    pub(crate) flags: FragmentFlags,
+    /// Fragment offset, in units of 8 bytes.
+    pub(crate) fragment_offset: u16,
+    /// Time to live.
+    pub(crate) ttl: u8,
+    /// Protocol announced by the IPv4 header.
+    pub(crate) protocol: IpProtocol,
+    /// IPv4 options, padded to whole words.
+    pub(crate) ip_options: Option<Vec<u8>>,
+    /// IPv4 header checksum, by default the correct one.
+    pub(crate) ip_checksum: Option<u16>,
+    /// ICMP type.
+    pub(crate) icmp_type: u8,
+    /// ICMP code.
+    pub(crate) icmp_code: u8,
+    /// ICMP identifier.
+    pub(crate) identifier: u16,
+    /// ICMP sequence number.
+    pub(crate) sequence: u16,
+    /// ICMP checksum, by default the correct one.
+    pub(crate) icmp_checksum: Option<u16>,
+    /// Data following the ICMP header.
+    pub(crate) payload: Vec<u8>,
+    /// Number of copies to send.
+    pub(crate) count: u32,
+    /// Time between two copies.
+    pub(crate) interval: Duration,
+    /// Pcap file to write the frames to instead of sending them.
+    pub(crate) write: Option<String>,
+}
+
+impl CraftOptions {
+    /// Parses the craft options from the command line arguments following `craft`.
+    ///
+    /// Usage: `<host> [--src-mac mac] [--dst-mac mac] [--tos byte] [--dscp name|value] [--ecn bits]
+    /// [--ihl words] [--length bytes] [--id id] [--flags DF,MF,RS|none] [--frag-offset offset]
+    /// [--ttl ttl] [--protocol number] [--ip-options hex] [--ip-checksum value] [--type type]
+    /// [--code code] [--icmp-id id] [--seq seq] [--icmp-checksum value] [--payload hex]
+    /// [--pattern hex] [--size bytes] [-c count] [-i seconds] [-w file]`
+    pub(crate) fn from_args(args: &[String]) -> Result<CraftOptions, String> {
+        let mut options = CraftOptions {
+            target: String::new(),
+            source_mac: None,
+            destination_mac: None,
+            dscp: Dscp::CS0,
+            ecn: Ecn::NotEct,
+            header_len: None,
+            total_length: None,
+            identification: 0,
+            flags: FragmentFlags::NONE,
+            fragment_offset: 0,
+            ttl: 64,
+            protocol: IpProtocol::ICMP,
+            ip_options: None,
+            ip_checksum: None,
+            icmp_type: ECHO_REQUEST,
+            icmp_code: 0,
+            identifier: std::process::id() as u16,
+            sequence: 1,
+            icmp_checksum: None,
+            payload: Vec::new(),
+            count: 1,
+            interval: Duration::from_secs(1),
+            write: None,
+        };
+        let mut target = None;
+        let mut pattern = None;
+        let mut size = None;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--src-mac" => options.source_mac = Some(parse_mac(next_value(&mut args, arg)?)?),
+                "--dst-mac" => options.destination_mac = Some(parse_mac(next_value(&mut args, arg)?)?),
+                "--tos" => {
+                    let tos = parse_number(next_value(&mut args, arg)?, arg, 0xff)? as u8;
+                    options.dscp = Dscp(tos >> 2);
+                    options.ecn = Ecn::from_bits(tos);
+                }
+                "--dscp" => options.dscp = parse_dscp(next_value(&mut args, arg)?)?,
+                "--ecn" => options.ecn = Ecn::from_bits(parse_number(next_value(&mut args, arg)?, arg, 3)? as u8),
+                "--ihl" => options.header_len = Some(parse_number(next_value(&mut args, arg)?, arg, 15)? as u8),
+                "--length" => options.total_length = Some(parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16),
+                "--id" => options.identification = parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16,
+                "--flags" => options.flags = parse_flags(next_value(&mut args, arg)?)?,
+                "--frag-offset" => {
+                    options.fragment_offset = parse_number(next_value(&mut args, arg)?, arg, 0x1fff)? as u16
+                }
+                "--ttl" => options.ttl = parse_number(next_value(&mut args, arg)?, arg, 0xff)? as u8,
+                "--protocol" => options.protocol = IpProtocol(parse_number(next_value(&mut args, arg)?, arg, 0xff)? as u8),
+                "--ip-options" => {
+                    let bytes = parse_hex_dump(next_value(&mut args, arg)?)?;
+                    if bytes.len() > 40 {
+                        return Err(format!("IPv4 options of {} bytes, at most 40 fit in the header", bytes.len()));
+                    }
+                    options.ip_options = Some(bytes);
+                }
+                "--ip-checksum" => options.ip_checksum = Some(parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16),
+                "--type" => options.icmp_type = parse_number(next_value(&mut args, arg)?, arg, 0xff)? as u8,
+                "--code" => options.icmp_code = parse_number(next_value(&mut args, arg)?, arg, 0xff)? as u8,
+                "--icmp-id" => options.identifier = parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16,
+                "--seq" => options.sequence = parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16,
+                "--icmp-checksum" => {
+                    options.icmp_checksum = Some(parse_number(next_value(&mut args, arg)?, arg, 0xffff)? as u16)
+                }
+                "--payload" => options.payload = parse_hex_dump(next_value(&mut args, arg)?)?,
+                "--pattern" => pattern = Some(parse_hex_dump(next_value(&mut args, arg)?)?),
+                "--size" => size = Some(parse_number(next_value(&mut args, arg)?, arg, 65507)? as usize),
+                "-c" => options.count = parse_count(next_value(&mut args, arg)?)?,
+                "-i" => options.interval = parse_seconds(next_value(&mut args, arg)?)?,
+                "-w" => options.write = Some(next_value(&mut args, arg)?.to_string()),
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ if target.is_none() => target = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        // A pattern is repeated over the payload size, by default once; a size alone gives zeros.
+        if pattern.is_some() || size.is_some() {
+            let pattern = pattern.filter(|pattern| !pattern.is_empty()).unwrap_or_else(|| vec![0]);
+            let size = size.unwrap_or(pattern.len());
+            options.payload = pattern.iter().copied().cycle().take(size).collect();
+        }
+        options.target = target.ok_or("Missing target host")?;
+        Ok(options)
+    }
+}
+
+/// Crafts a frame and sends it, or writes it to a pcap file.
+///
+/// # Arguments
+/// * `transport` - Transport to send the frame on, `None` when writing it to a file.
+/// * `target` - Destination address of the packet.
+/// * `options` - Fields of the packet and how to send it.
+/// * `probe_options` - Source address, next hop MAC address and VLAN stack used by default.
+pub(crate) fn craft(
+    transport: Option<&dyn Transport>,
+    target: [u8; 4],
+    options: &CraftOptions,
+    probe_options: PingerOptions,
+) -> Result<(), String> {
+    let mut frame = build_frame(options, transport, target, probe_options);
+    let bytes = frame.to_bytes();
+
+    match (&options.write, transport) {
+        (Some(path), _) => {
+            let mut writer =
+                PcapWriter::create(path, LINKTYPE_ETHERNET).map_err(|e| format!("Failed to create {}: {}", path, e))?;
+            // Copies are stamped an interval apart, as if they had been sent.
+            let start = SystemTime::now();
+            for i in 0..options.count {
+                writer
+                    .write(&bytes, start + options.interval * i)
+                    .map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            }
+            writer.flush().map_err(|e| format!("Failed to write {}: {}", path, e))?;
+            println!("{}", summarize(&frame, Layer::Link));
+            println!("wrote {} frames of {} bytes to {}", options.count, bytes.len(), path);
+        }
+        (None, Some(transport)) => {
+            println!("{}", summarize(&frame, transport.layer()));
+            for i in 0..options.count {
+                if i > 0 {
+                    std::thread::sleep(options.interval);
+                }
+                transport.send_frame(&mut frame).map_err(|e| format!("Failed to send: {}", e))?;
+            }
+            println!("sent {} frames of {} bytes on {}", options.count, bytes.len(), transport.name());
+        }
+        (None, None) => return Err("No transport to send on".to_string()),
+    }
+    Ok(())
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Builds the Ethernet frame, with the MAC addresses of the transport and next hop unless overridden.
+fn build_frame(
+    options: &CraftOptions,
+    transport: Option<&dyn Transport>,
+    target: [u8; 4],
+    probe_options: PingerOptions,
+) -> EthernetFrame {
+    let source_mac = options
+        .source_mac
+        .or_else(|| transport.and_then(|transport| transport.mac_address()))
+        .or_else(|| next_hop(target).and_then(|route| route.interface.mac))
+        .unwrap_or(MacAddr::ZERO);
+    let mut frame = EthernetFrame::new_ether(build_packet(options, probe_options.source, target))
+        .with_vlan_tags(probe_options.vlan_tags);
+    frame.source_address = source_mac;
+    frame.destination_address = options.destination_mac.unwrap_or(probe_options.destination_mac);
+    frame
+}
+
+/// Builds the IPv4 packet, filling in the lengths and checksums not overridden.
+fn build_packet(options: &CraftOptions, source: [u8; 4], target: [u8; 4]) -> IPV4 {
+    let mut icmp = ICMPPacket {
+        packet_type: options.icmp_type,
+        code: options.icmp_code,
+        checksum: 0,
+        identifier: options.identifier,
+        sequence: options.sequence,
+        data: options.payload.clone(),
+    };
+    match options.icmp_checksum {
+        Some(checksum) => icmp.checksum = checksum,
+        None => icmp.update_checksum(),
+    }
+
+    let mut packet = IPV4::new(
+        icmp,
+        options.protocol,
+        options.ttl,
+        source,
+        target,
+        options.ip_options.clone(),
+    );
+    packet.dscp = options.dscp;
+    packet.ecn = options.ecn;
+    packet.identification = options.identification;
+    packet.flags = options.flags;
+    packet.fragment_offset = options.fragment_offset;
+    if let Some(header_len) = options.header_len {
+        packet.version_header_len = 0x40 | header_len;
+    }
+    if let Some(total_length) = options.total_length {
+        packet.total_length = total_length;
+    }
+
+    // The checksum covers the header actually built, whatever length it claims.
+    match options.ip_checksum {
+        Some(checksum) => packet.header_checksum = checksum,
+        None => packet.update_checksum(),
+    }
+    packet
+}
+
+/// Parses a number, decimal or hex with a `0x` prefix, no larger than `max`.
+fn parse_number(s: &str, option: &str, max: u32) -> Result<u32, String> {
+    let value = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    match value {
+        Ok(value) if value <= max => Ok(value),
+        _ => Err(format!("Invalid value {} for {}, expected a number up to {}", s, option, max)),
+    }
+}
+
+/// Parses a MAC address.
+fn parse_mac(s: &str) -> Result<MacAddr, String> {
+    s.parse().map_err(|_| format!("Invalid MAC address: {}", s))
+}
+
+/// Parses a DSCP, by name such as `EF` or `AF41`, or by value.
+fn parse_dscp(s: &str) -> Result<Dscp, String> {
+    (0..64)
+        .map(Dscp)
+        .find(|dscp| dscp.name().is_some_and(|name| name.eq_ignore_ascii_case(s)))
+        .map_or_else(|| parse_number(s, "--dscp", 63).map(|value| Dscp(value as u8)), Ok)
+}
+
+/// Parses fragmentation flags, a comma separated list of `DF`, `MF` and `RS`, or `none`.
+fn parse_flags(s: &str) -> Result<FragmentFlags, String> {
+    if s.eq_ignore_ascii_case("none") {
+        return Ok(FragmentFlags::NONE);
+    }
+    s.split(',').try_fold(FragmentFlags::NONE, |flags, flag| {
+        let flag = match flag.to_ascii_uppercase().as_str() {
+            "DF" => FragmentFlags::DF,
+            "MF" => FragmentFlags::MF,
+            "RS" => FragmentFlags::RESERVED,
+            _ => return Err(format!("Unknown flag {}, expected DF, MF, RS or none", flag)),
+        };
+        Ok(flags | flag)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_network::ipv4::checksum::calculate_checksum;
+
+    /// Offset of the IPv4 header in an untagged frame.
+    const IPV4_AT: usize = 14;
+
+    /// Crafts a frame to 10.0.0.1 from 10.0.0.2 with the given options, returning its bytes.
+    fn craft_bytes(args: &[&str]) -> Vec<u8> {
+        let mut args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        args.insert(0, "10.0.0.1".to_string());
+        // A source MAC spares looking up the interface of the next hop.
+        if !args.iter().any(|arg| arg == "--src-mac") {
+            args.extend(["--src-mac".to_string(), "02:00:00:00:00:02".to_string()]);
+        }
+        let options = CraftOptions::from_args(&args).unwrap();
+        let probe_options = PingerOptions {
+            source: [10, 0, 0, 2],
+            ..PingerOptions::default()
+        };
+        build_frame(&options, None, [10, 0, 0, 1], probe_options).to_bytes()
+    }
+
+    #[test]
+    fn every_header_field_reaches_the_frame() {
+        let bytes = craft_bytes(&[
+            "--src-mac", "52:54:00:12:34:56", "--dst-mac", "00:11:22:33:44:55", "--dscp", "EF", "--ecn", "1",
+            "--id", "0x1234", "--flags", "DF,RS", "--frag-offset", "3", "--ttl", "7", "--protocol", "253",
+            "--ip-options", "94040000", "--type", "13", "--code", "2", "--icmp-id", "0xbeef", "--seq", "513",
+            "--payload", "de ad be ef",
+        ]);
+        assert_eq!(bytes[..6], [0x00, 0x11, 0x22, 0x33, 0x44, 0x55]);
+        assert_eq!(bytes[6..12], [0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+        assert_eq!(bytes[12..14], [0x08, 0x00]);
+
+        let ip = &bytes[IPV4_AT..];
+        assert_eq!(ip[0], 0x46, "version and header length with the options");
+        assert_eq!(ip[1], 46 << 2 | 1, "DSCP EF and ECT(1)");
+        assert_eq!(ip[2..4], [0, 36]);
+        assert_eq!(ip[4..6], [0x12, 0x34]);
+        assert_eq!(ip[6..8], [0xc0, 0x03], "DF and reserved flags, fragment offset 3");
+        assert_eq!(ip[8], 7);
+        assert_eq!(ip[9], 253);
+        assert_eq!(ip[12..20], [10, 0, 0, 2, 10, 0, 0, 1]);
+        assert_eq!(ip[20..24], [0x94, 0x04, 0x00, 0x00]);
+        assert_eq!(calculate_checksum(&ip[..24]), 0);
+
+        let icmp = &ip[24..];
+        assert_eq!(icmp[..2], [13, 2]);
+        assert_eq!(icmp[4..8], [0xbe, 0xef, 0x02, 0x01]);
+        assert_eq!(icmp[8..], [0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(calculate_checksum(icmp), 0);
+    }
+
+    #[test]
+    fn tos_sets_dscp_and_ecn_together() {
+        let bytes = craft_bytes(&["--tos", "0xb9"]);
+        assert_eq!(bytes[IPV4_AT + 1], 0xb9);
+        // A later --ecn overrides the ECN bits of the TOS only.
+        let bytes = craft_bytes(&["--tos", "0xb9", "--ecn", "2"]);
+        assert_eq!(bytes[IPV4_AT + 1], 0xba);
+    }
+
+    #[test]
+    fn bogus_lengths_are_sent_as_given() {
+        let bytes = craft_bytes(&["--ihl", "15", "--length", "20"]);
+        let ip = &bytes[IPV4_AT..];
+        assert_eq!(ip[0], 0x4f);
+        assert_eq!(ip[2..4], [0, 20]);
+        // The checksum covers the 20 bytes of header built, not the 60 claimed.
+        assert_eq!(calculate_checksum(&ip[..20]), 0);
+        assert_eq!(bytes.len(), IPV4_AT + 28);
+    }
+
+    #[test]
+    fn checksum_overrides_replace_the_computed_checksums() {
+        let bytes = craft_bytes(&[]);
+        let ip = &bytes[IPV4_AT..];
+        assert_eq!(calculate_checksum(&ip[..20]), 0);
+        assert_eq!(calculate_checksum(&ip[20..]), 0);
+
+        let bytes = craft_bytes(&["--ip-checksum", "0xdead"]);
+        assert_eq!(bytes[IPV4_AT + 10..IPV4_AT + 12], [0xde, 0xad]);
+        assert_eq!(calculate_checksum(&bytes[IPV4_AT + 20..]), 0, "the ICMP checksum is still computed");
+
+        let bytes = craft_bytes(&["--icmp-checksum", "0"]);
+        assert_eq!(bytes[IPV4_AT + 22..IPV4_AT + 24], [0, 0]);
+        assert_eq!(calculate_checksum(&bytes[IPV4_AT..IPV4_AT + 20]), 0, "the IPv4 checksum is still computed");
+    }
+
+    #[test]
+    fn pattern_and_size_fill_the_payload() {
+        let payload_at = IPV4_AT + 28;
+        assert_eq!(craft_bytes(&["--pattern", "abcd", "--size", "5"])[payload_at..], [0xab, 0xcd, 0xab, 0xcd, 0xab]);
+        assert_eq!(craft_bytes(&["--pattern", "abcd"])[payload_at..], [0xab, 0xcd]);
+        assert_eq!(craft_bytes(&["--size", "3"])[payload_at..], [0, 0, 0]);
+        assert_eq!(craft_bytes(&[]).len(), payload_at);
+    }
+}
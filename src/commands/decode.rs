@@ -0,0 +1,725 @@
+//! Offline decoding of packets given as hex, e.g. copied from a capture, a switch or a log.
+//!
+//! Accepts plain hex digits as well as the dumps of `tcpdump -xx`, Wireshark's
+//! "Copy as Hex Dump" and `hexdump -C`, whose offset and ASCII columns are skipped.
+//! Every field is printed with the offset of its first byte, and the IPv4, ICMP, UDP
+//! and TCP checksums are verified against the bytes they cover.
+
+use crate::cli::next_value;
+use crate::output::{Output, Record};
+use rust_network::arp::{ARP_PACKET_LEN, ARP_REPLY, ARP_REQUEST};
+use rust_network::ethernet_frame::{EtherType, VlanTag};
+use rust_network::ipv4::checksum::{pseudo_header, verify, verify_segment, ChecksumStatus};
+use rust_network::ipv4::fields::{Dscp, Ecn, FragmentFlags, IpProtocol};
+use rust_network::ipv4::icmp::{
+    CODE_FRAGMENTATION_NEEDED, DESTINATION_UNREACHABLE, ECHO_REPLY, ECHO_REQUEST, TIME_EXCEEDED,
+};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::mac_address::MacAddr;
+
+use std::io::Read;
+
+/// Length of an Ethernet header without VLAN tags.
+const ETHERNET_HEADER_LEN: usize = 14;
+
+/// Length of an IPv4 header without options.
+const IPV4_HEADER_LEN: usize = 20;
+
+/// Length of an ICMP header.
+const ICMP_HEADER_LEN: usize = 8;
+
+/// Length of a UDP header.
+const UDP_HEADER_LEN: usize = 8;
+
+/// Length of a TCP header without options.
+const TCP_HEADER_LEN: usize = 20;
+
+/// Names of the TCP flags, from the lowest bit.
+const TCP_FLAGS: [&str; 8] = ["FIN", "SYN", "RST", "PSH", "ACK", "URG", "ECE", "CWR"];
+
+/// Layer the decoded bytes start at.
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum StartLayer {
+    /// An Ethernet frame.
+    Ethernet,
+    /// An IPv4 packet, without link layer header.
+    Ipv4,
+    /// An ICMP message, without IPv4 header.
+    Icmp,
+}
+
+/// Options of a decode.
+pub(crate) struct DecodeOptions {
+    /// Hex given on the command line, `None` to read it from stdin.
+    pub(crate) hex: Option<String>,
+    /// Layer the bytes start at.
+    pub(crate) layer: StartLayer,
+}
+
+impl DecodeOptions {
+    /// Parses the decode options from the command line arguments following `decode`.
+    ///
+    /// Usage: `[hex...|-] [--layer ether|ip|icmp]`
+    pub(crate) fn from_args(args: &[String]) -> Result<DecodeOptions, String> {
+        let mut hex = Vec::new();
+        let mut layer = StartLayer::Ethernet;
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--layer" => {
+                    layer = match next_value(&mut args, arg)? {
+                        "ether" | "ethernet" => StartLayer::Ethernet,
+                        "ip" | "ipv4" => StartLayer::Ipv4,
+                        "icmp" => StartLayer::Icmp,
+                        other => return Err(format!("Unknown layer {}, expected ether, ip or icmp", other)),
+                    }
+                }
+                "-" => {}
+                _ => hex.push(arg.as_str()),
+            }
+        }
+
+        Ok(DecodeOptions {
+            hex: if hex.is_empty() { None } else { Some(hex.join("\n")) },
+            layer,
+        })
+    }
+}
+
+/// A decoded field, with the offset of its first byte.
+struct Field {
+    /// Layer of the field, e.g. `IP`.
+    layer: &'static str,
+    /// Offset of the field from the start of the bytes.
+    offset: usize,
+    /// Name of the field.
+    name: &'static str,
+    /// Decoded value.
+    value: String,
+}
+
+/// Walks the layers of a packet, collecting its fields.
+struct Dissector<'a> {
+    /// Bytes being decoded.
+    bytes: &'a [u8],
+    /// Fields decoded so far.
+    fields: Vec<Field>,
+    /// Whether every checksum verified so far was right.
+    checksums_valid: bool,
+}
+
+/// Decodes a packet given as hex and prints its fields with their offsets.
+///
+/// # Arguments
+/// * `options` - Options of the decode.
+/// * `output` - Output of the decode, a record per field in the machine formats.
+pub(crate) fn decode(options: &DecodeOptions, output: &mut Output) -> Result<(), String> {
+    let hex = match &options.hex {
+        Some(hex) => hex.clone(),
+        None => {
+            let mut hex = String::new();
+            std::io::stdin()
+                .read_to_string(&mut hex)
+                .map_err(|e| format!("Failed to read stdin: {}", e))?;
+            hex
+        }
+    };
+    let bytes = parse_hex_dump(&hex)?;
+    if bytes.is_empty() {
+        return Err("No bytes to decode".to_string());
+    }
+
+    let dissector = dissect(&bytes, options.layer);
+
+    if output.is_text() {
+        let mut layer = "";
+        for field in dissector.fields.iter() {
+            let prefix = format!("{}:", field.layer);
+            if field.layer != layer {
+                println!("{:<6} -----{} Header-----", prefix, field.layer);
+                layer = field.layer;
+            }
+            println!("{:<6} {:04x}  {:<17}= {}", prefix, field.offset, field.name, field.value);
+        }
+        println!(
+            "\n{} bytes, checksums {}",
+            bytes.len(),
+            if dissector.checksums_valid { "valid" } else { "INVALID" }
+        );
+    } else {
+        for field in dissector.fields {
+            output.event(
+                Record::new("field")
+                    .field("layer", field.layer)
+                    .field("offset", field.offset)
+                    .field("name", field.name)
+                    .field("value", field.value),
+            );
+        }
+        output.summary(
+            Record::new("summary")
+                .field("length", bytes.len())
+                .field("checksums_valid", dissector.checksums_valid),
+        );
+        output.finish();
+    }
+    Ok(())
+}
+
+/// Parses a hex dump into bytes.
+///
+/// Lines may start with an offset, `0x0010:` as printed by tcpdump or `0010` followed by
+/// two spaces as printed by Wireshark and hexdump, and end with an ASCII column. Bytes
+/// may be written alone, in groups, or separated by colons.
+pub(crate) fn parse_hex_dump(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes: Vec<u8> = Vec::new();
+    for line in text.lines() {
+        let mut rest = line.trim();
+        if let Some((offset, after)) = split_offset(rest, bytes.len()) {
+            if offset > bytes.len() {
+                return Err(format!("Missing bytes before offset 0x{:04x}", offset));
+            }
+            // A smaller offset means the ASCII column of the previous line was taken for hex.
+            bytes.truncate(offset);
+            // The ASCII column follows the hex after three spaces or more.
+            rest = after.trim_start().split("   ").next().unwrap_or("");
+        }
+
+        for token in rest.split_whitespace() {
+            let digits = token.strip_prefix("0x").unwrap_or(token).replace([':', '-'], "");
+            if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                // The ASCII column, or a comment.
+                break;
+            }
+            if !digits.len().is_multiple_of(2) {
+                return Err(format!("Odd number of hex digits in {}", token));
+            }
+            for i in (0..digits.len()).step_by(2) {
+                bytes.push(u8::from_str_radix(&digits[i..i + 2], 16).map_err(|e| e.to_string())?);
+            }
+        }
+    }
+    Ok(bytes)
+}
+
+impl Dissector<'_> {
+    /// Decodes an Ethernet frame starting at `at`, with its VLAN tags and payload.
+    fn ethernet(&mut self, at: usize) {
+        let end = self.bytes.len();
+        if !self.require("ETHER", at, ETHERNET_HEADER_LEN, end) {
+            return;
+        }
+        let destination = self.mac(at);
+        let source = self.mac(at + 6);
+        self.field("ETHER", at, "Destination", format!("{} ({})", destination, destination.describe()));
+        self.field("ETHER", at + 6, "Source", format!("{} ({})", source, source.describe()));
+
+        let mut offset = at + 12;
+        let mut ether_type = EtherType(self.u16(offset));
+        while ether_type.is_vlan_tag() {
+            if !self.require("VLAN", offset, 6, end) {
+                return;
+            }
+            let tag = VlanTag::from_tci(ether_type, self.u16(offset + 2));
+            let value = format!("{}, VID {}, PCP {}, DEI {}", tag.tpid, tag.vid, tag.pcp, tag.dei as u8);
+            self.field("VLAN", offset, "Tag", value);
+            offset += 4;
+            ether_type = EtherType(self.u16(offset));
+        }
+        self.field("ETHER", offset, "Ethertype", ether_type.to_string());
+
+        let payload = offset + 2;
+        let payload_end = match ether_type {
+            EtherType::IPV4 => self.ipv4(payload, end, true),
+            EtherType::ARP => self.arp(payload),
+            _ => {
+                self.data("ETHER", payload, end);
+                end
+            }
+        };
+        // Frames shorter than 60 bytes are padded up to it.
+        if payload_end < end {
+            self.field("ETHER", payload_end, "Padding", format!("{} bytes", end - payload_end));
+        }
+    }
+
+    /// Decodes an ARP packet starting at `at`, returning its end.
+    fn arp(&mut self, at: usize) -> usize {
+        if !self.require("ARP", at, ARP_PACKET_LEN, self.bytes.len()) {
+            return self.bytes.len();
+        }
+        let operation = self.u16(at + 6);
+        let operation_name = match operation {
+            ARP_REQUEST => "request",
+            ARP_REPLY => "reply",
+            _ => "unknown",
+        };
+        self.field("ARP", at, "Hardware type", self.u16(at).to_string());
+        self.field("ARP", at + 2, "Protocol type", format!("0x{:04x}", self.u16(at + 2)));
+        self.field("ARP", at + 4, "Hardware length", self.bytes[at + 4].to_string());
+        self.field("ARP", at + 5, "Protocol length", self.bytes[at + 5].to_string());
+        self.field("ARP", at + 6, "Operation", format!("{} ({})", operation, operation_name));
+        self.field("ARP", at + 8, "Sender MAC", self.mac(at + 8).to_string());
+        self.field("ARP", at + 14, "Sender IP", self.ipv4_address(at + 14));
+        self.field("ARP", at + 18, "Target MAC", self.mac(at + 18).to_string());
+        self.field("ARP", at + 24, "Target IP", self.ipv4_address(at + 24));
+        at + ARP_PACKET_LEN
+    }
+
+    /// Decodes an IPv4 packet starting at `at` and ending at `end` at the latest.
+    ///
+    /// With `quotes`, the packet quoted by an ICMP error is decoded in turn; a quote inside
+    /// a quote is not. Returns the end of the packet by its total length.
+    fn ipv4(&mut self, at: usize, end: usize, quotes: bool) -> usize {
+        if !self.require("IP", at, IPV4_HEADER_LEN, end) {
+            return end;
+        }
+        let header_len = (self.bytes[at] & 0x0f) as usize * 4;
+        let total_length = self.u16(at + 2) as usize;
+        let flags_offset = self.u16(at + 6);
+        let protocol = IpProtocol(self.bytes[at + 9]);
+        let tos = format!("{} / {}", Dscp(self.bytes[at + 1] >> 2), Ecn::from_bits(self.bytes[at + 1]));
+
+        self.field("IP", at, "Version", (self.bytes[at] >> 4).to_string());
+        self.field("IP", at, "Header length", format!("{} bytes", header_len));
+        self.field("IP", at + 1, "DSCP / ECN", tos);
+        self.field("IP", at + 2, "Total length", total_length.to_string());
+        self.field("IP", at + 4, "Identification", format!("0x{:04x}", self.u16(at + 4)));
+        self.field("IP", at + 6, "Flags", FragmentFlags((flags_offset >> 13) as u8).to_string());
+        self.field("IP", at + 6, "Fragment offset", ((flags_offset & 0x1fff) * 8).to_string());
+        self.field("IP", at + 8, "TTL", self.bytes[at + 8].to_string());
+        self.field("IP", at + 9, "Protocol", protocol.to_string());
+        let checksum = if header_len < IPV4_HEADER_LEN || at + header_len > end {
+            self.checksums_valid = false;
+            format!("0x{:04x} (header length invalid)", self.u16(at + 10))
+        } else {
+            self.verify_checksum(at, at + header_len, at + 10)
+        };
+        self.field("IP", at + 10, "Checksum", checksum);
+        self.field("IP", at + 12, "Source", self.ipv4_address(at + 12));
+        self.field("IP", at + 16, "Destination", self.ipv4_address(at + 16));
+        if header_len < IPV4_HEADER_LEN || at + header_len > end {
+            return end;
+        }
+        if header_len > IPV4_HEADER_LEN {
+            let options = hex(&self.bytes[at + IPV4_HEADER_LEN..at + header_len]);
+            self.field("IP", at + IPV4_HEADER_LEN, "Options", options);
+        }
+
+        let payload = at + header_len;
+        let complete = at + total_length <= end;
+        let packet_end = if total_length < header_len {
+            end
+        } else if !complete {
+            let present = format!("{} of {} bytes present", end - at, total_length);
+            self.field("IP", payload, "Truncated", present);
+            end
+        } else {
+            at + total_length
+        };
+        // Only the first fragment holds the header of the protocol carried.
+        if flags_offset & 0x1fff != 0 {
+            self.data("IP", payload, packet_end);
+            return packet_end;
+        }
+        // The UDP and TCP checksums cover the whole datagram, which a fragment does not hold.
+        let whole = complete && flags_offset & 0x2000 == 0;
+        let pseudo_header = pseudo_header(
+            self.ipv4_octets(at + 12),
+            self.ipv4_octets(at + 16),
+            protocol,
+            (packet_end - payload) as u16,
+        );
+        match protocol {
+            IpProtocol::ICMP => self.icmp(payload, packet_end, quotes, complete),
+            IpProtocol::UDP => self.udp(payload, packet_end, whole.then_some(&pseudo_header)),
+            IpProtocol::TCP => self.tcp(payload, packet_end, whole.then_some(&pseudo_header)),
+            _ => self.data("IP", payload, packet_end),
+        }
+        packet_end
+    }
+
+    /// Decodes an ICMP message starting at `at` and ending at `end`.
+    ///
+    /// The checksum of an incomplete message, e.g. quoted by an ICMP error, cannot be verified.
+    fn icmp(&mut self, at: usize, end: usize, quotes: bool, complete: bool) {
+        if !self.require("ICMP", at, ICMP_HEADER_LEN, end) {
+            return;
+        }
+        let packet_type = self.bytes[at];
+        let code = self.bytes[at + 1];
+        let type_name = match packet_type {
+            ECHO_REPLY => "echo reply",
+            DESTINATION_UNREACHABLE => "destination unreachable",
+            ECHO_REQUEST => "echo request",
+            TIME_EXCEEDED => "time exceeded",
+            _ => "unknown",
+        };
+        self.field("ICMP", at, "Type", format!("{} ({})", packet_type, type_name));
+        self.field("ICMP", at + 1, "Code", code.to_string());
+        let checksum = if complete {
+            self.verify_checksum(at, end, at + 2)
+        } else {
+            format!("0x{:04x} (not verified, message truncated)", self.u16(at + 2))
+        };
+        self.field("ICMP", at + 2, "Checksum", checksum);
+
+        match packet_type {
+            ECHO_REQUEST | ECHO_REPLY => {
+                self.field("ICMP", at + 4, "Identifier", format!("0x{:04x}", self.u16(at + 4)));
+                self.field("ICMP", at + 6, "Sequence", self.u16(at + 6).to_string());
+                self.data("ICMP", at + ICMP_HEADER_LEN, end);
+            }
+            DESTINATION_UNREACHABLE | TIME_EXCEEDED => {
+                if packet_type == DESTINATION_UNREACHABLE && code == CODE_FRAGMENTATION_NEEDED {
+                    self.field("ICMP", at + 6, "Next-hop MTU", self.u16(at + 6).to_string());
+                }
+                if quotes {
+                    // The header and first bytes of the packet the error is about.
+                    self.ipv4(at + ICMP_HEADER_LEN, end, false);
+                } else {
+                    self.data("ICMP", at + ICMP_HEADER_LEN, end);
+                }
+            }
+            _ => {
+                self.field("ICMP", at + 4, "Rest of header", hex(&self.bytes[at + 4..at + 8]));
+                self.data("ICMP", at + ICMP_HEADER_LEN, end);
+            }
+        }
+    }
+
+    /// Decodes a UDP datagram starting at `at` and ending at `end`.
+    ///
+    /// The checksum is verified with the given pseudo-header, `None` if the datagram is incomplete.
+    fn udp(&mut self, at: usize, end: usize, pseudo_header: Option<&[u8; 12]>) {
+        if !self.require("UDP", at, UDP_HEADER_LEN, end) {
+            return;
+        }
+        self.field("UDP", at, "Source port", self.u16(at).to_string());
+        self.field("UDP", at + 2, "Destination port", self.u16(at + 2).to_string());
+        self.field("UDP", at + 4, "Length", self.u16(at + 4).to_string());
+        let checksum = match pseudo_header {
+            // The sender may leave the checksum out.
+            _ if self.u16(at + 6) == 0 => "0x0000 (none)".to_string(),
+            Some(pseudo_header) => self.verify_segment(pseudo_header, at, end, 6),
+            None => format!("0x{:04x} (not verified, datagram incomplete)", self.u16(at + 6)),
+        };
+        self.field("UDP", at + 6, "Checksum", checksum);
+        self.data("UDP", at + UDP_HEADER_LEN, end);
+    }
+
+    /// Decodes a TCP segment starting at `at` and ending at `end`.
+    ///
+    /// The checksum is verified with the given pseudo-header, `None` if the segment is incomplete.
+    fn tcp(&mut self, at: usize, end: usize, pseudo_header: Option<&[u8; 12]>) {
+        if !self.require("TCP", at, TCP_HEADER_LEN, end) {
+            return;
+        }
+        let header_len = (self.bytes[at + 12] >> 4) as usize * 4;
+        let flags: Vec<&str> = (0..8)
+            .filter(|bit| self.bytes[at + 13] & (1 << bit) != 0)
+            .map(|bit| TCP_FLAGS[bit])
+            .collect();
+        self.field("TCP", at, "Source port", self.u16(at).to_string());
+        self.field("TCP", at + 2, "Destination port", self.u16(at + 2).to_string());
+        self.field("TCP", at + 4, "Sequence", self.u32(at + 4).to_string());
+        self.field("TCP", at + 8, "Acknowledgment", self.u32(at + 8).to_string());
+        self.field("TCP", at + 12, "Header length", format!("{} bytes", header_len));
+        self.field("TCP", at + 13, "Flags", if flags.is_empty() { "None".to_string() } else { flags.join(", ") });
+        self.field("TCP", at + 14, "Window", self.u16(at + 14).to_string());
+        let checksum = match pseudo_header {
+            Some(pseudo_header) => self.verify_segment(pseudo_header, at, end, 16),
+            None => format!("0x{:04x} (not verified, segment incomplete)", self.u16(at + 16)),
+        };
+        self.field("TCP", at + 16, "Checksum", checksum);
+        self.field("TCP", at + 18, "Urgent pointer", self.u16(at + 18).to_string());
+        if header_len < TCP_HEADER_LEN || at + header_len > end {
+            self.field("TCP", at + 12, "Truncated", "header length invalid".to_string());
+            return;
+        }
+        if header_len > TCP_HEADER_LEN {
+            self.field("TCP", at + TCP_HEADER_LEN, "Options", hex(&self.bytes[at + TCP_HEADER_LEN..at + header_len]));
+        }
+        self.data("TCP", at + header_len, end);
+    }
+
+    /// Adds a field.
+    fn field(&mut self, layer: &'static str, offset: usize, name: &'static str, value: String) {
+        self.fields.push(Field {
+            layer,
+            offset,
+            name,
+            value,
+        });
+    }
+
+    /// Adds the payload between `at` and `end` as a data field, if there is any.
+    fn data(&mut self, layer: &'static str, at: usize, end: usize) {
+        if at < end {
+            self.field(layer, at, "Data", format!("{} bytes", end - at));
+        }
+    }
+
+    /// Checks that a header of `len` bytes fits between `at` and `end`, adding a field saying
+    /// the layer is truncated if not.
+    fn require(&mut self, layer: &'static str, at: usize, len: usize, end: usize) -> bool {
+        if at + len <= end {
+            return true;
+        }
+        let present = format!("{} of {} header bytes present", end.saturating_sub(at), len);
+        self.field(layer, at, "Truncated", present);
+        false
+    }
+
+    /// Verifies the checksum at `checksum_at` covering the bytes from `start` to `end`, and describes it.
+    fn verify_checksum(&mut self, start: usize, end: usize, checksum_at: usize) -> String {
+        let status = verify(&self.bytes[start..end], checksum_at - start);
+        self.describe_checksum(checksum_at, status)
+    }
+
+    /// Verifies the checksum at `checksum_at` of the UDP or TCP segment between `start` and `end`,
+    /// and describes it.
+    fn verify_segment(&mut self, pseudo_header: &[u8; 12], start: usize, end: usize, checksum_at: usize) -> String {
+        let status = verify_segment(pseudo_header, &self.bytes[start..end], checksum_at);
+        self.describe_checksum(start + checksum_at, status)
+    }
+
+    /// Describes the checksum at `checksum_at` with the outcome of its verification.
+    ///
+    /// Offloaded checksums are not counted as invalid, as the dump may have been captured
+    /// on the host that sent the packet.
+    fn describe_checksum(&mut self, checksum_at: usize, status: ChecksumStatus) -> String {
+        if status.is_bad(true) {
+            self.checksums_valid = false;
+        }
+        format!("0x{:04x} ({})", self.u16(checksum_at), status)
+    }
+
+    /// Reads a big endian 16-bit value at `at`.
+    fn u16(&self, at: usize) -> u16 {
+        u16::from_be_bytes([self.bytes[at], self.bytes[at + 1]])
+    }
+
+    /// Reads a big endian 32-bit value at `at`.
+    fn u32(&self, at: usize) -> u32 {
+        u32::from_be_bytes([self.bytes[at], self.bytes[at + 1], self.bytes[at + 2], self.bytes[at + 3]])
+    }
+
+    /// Reads an IPv4 address at `at`.
+    fn ipv4_octets(&self, at: usize) -> [u8; 4] {
+        [self.bytes[at], self.bytes[at + 1], self.bytes[at + 2], self.bytes[at + 3]]
+    }
+
+    /// Reads a MAC address at `at`.
+    fn mac(&self, at: usize) -> MacAddr {
+        let mut octets = [0; 6];
+        octets.copy_from_slice(&self.bytes[at..at + 6]);
+        MacAddr(octets)
+    }
+
+    /// Reads and formats an IPv4 address at `at`.
+    fn ipv4_address(&self, at: usize) -> String {
+        format_ipv4_address(&self.ipv4_octets(at))
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Decodes bytes starting at the given layer.
+fn dissect(bytes: &[u8], layer: StartLayer) -> Dissector<'_> {
+    let mut dissector = Dissector {
+        bytes,
+        fields: Vec::new(),
+        checksums_valid: true,
+    };
+    match layer {
+        StartLayer::Ethernet => dissector.ethernet(0),
+        StartLayer::Ipv4 => {
+            dissector.ipv4(0, bytes.len(), true);
+        }
+        StartLayer::Icmp => dissector.icmp(0, bytes.len(), true, true),
+    }
+    dissector
+}
+
+/// Splits the offset column off a line of a hex dump, if it has one.
+///
+/// An offset ends with a colon, or is four hex digits or more followed by two spaces or
+/// a tab and at most the number of bytes read so far. The line hexdump ends its dump
+/// with, eight digits or more giving the number of bytes read, is an offset too.
+fn split_offset(line: &str, read: usize) -> Option<(usize, &str)> {
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let (token, after) = line.split_at(end);
+    let (digits, explicit) = match token.strip_suffix(':') {
+        Some(digits) => (digits, true),
+        None => (token, false),
+    };
+    let digits = digits.strip_prefix("0x").unwrap_or(digits);
+    let offset = usize::from_str_radix(digits, 16).ok()?;
+    let column = digits.len() >= 4 && (after.starts_with("  ") || after.starts_with('\t')) && offset <= read;
+    let length = digits.len() >= 8 && after.is_empty() && offset == read;
+    if explicit || column || length {
+        Some((offset, after))
+    } else {
+        None
+    }
+}
+
+/// Formats bytes as hex digits.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_network::ipv4::checksum::calculate_checksum;
+
+    /// An echo request from 10.0.0.2 to 10.0.0.1 in an Ethernet frame, with valid checksums.
+    const ECHO_FRAME: &str = "00112233445552540012345608004500002412344000400114a30a0000020a000001\
+                              08002427424200016162636465666768";
+
+    /// Offset of the IPv4 header in `ECHO_FRAME`.
+    const IPV4_AT: usize = 14;
+
+    /// Offset of the ICMP header in `ECHO_FRAME`.
+    const ICMP_AT: usize = 34;
+
+    /// Returns the bytes of `ECHO_FRAME`.
+    fn echo_frame() -> Vec<u8> {
+        parse_hex_dump(ECHO_FRAME).unwrap()
+    }
+
+    /// Returns the value of the first field of a layer with the given name.
+    fn value<'a>(dissector: &'a Dissector, layer: &str, name: &str) -> &'a str {
+        let field = dissector.fields.iter().find(|field| field.layer == layer && field.name == name);
+        &field.unwrap_or_else(|| panic!("no {} {} field", layer, name)).value
+    }
+
+    /// Builds an IPv4 packet from 10.0.0.2 to 10.0.0.1 with a valid header checksum.
+    fn ipv4_packet(protocol: IpProtocol, payload: &[u8]) -> Vec<u8> {
+        let mut packet = vec![0x45, 0, 0, 0, 0x12, 0x34, 0x40, 0, 64, protocol.0, 0, 0, 10, 0, 0, 2, 10, 0, 0, 1];
+        packet[2..4].copy_from_slice(&((IPV4_HEADER_LEN + payload.len()) as u16).to_be_bytes());
+        let checksum = calculate_checksum(&packet);
+        packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+
+    #[test]
+    fn tcpdump_dump_parses_with_its_offsets() {
+        let dump = "\t0x0000:  0011 2233 4455 5254 0012 3456 0800 4500\n\
+                    \t0x0010:  0024 1234 4000 4001 14a3 0a00 0002 0a00\n\
+                    \t0x0020:  0001 0800 2427 4242 0001 6162 6364 6566\n\
+                    \t0x0030:  6768\n";
+        assert_eq!(parse_hex_dump(dump).unwrap(), echo_frame());
+    }
+
+    #[test]
+    fn wireshark_dump_parses_without_its_offsets_and_ascii() {
+        let dump = "0000  00 11 22 33 44 55 52 54 00 12 34 56 08 00 45 00   ..\"3DURT..4V..E.\n\
+                    0010  00 24 12 34 40 00 40 01 14 a3 0a 00 00 02 0a 00   .$.4@.@.........\n\
+                    0020  00 01 08 00 24 27 42 42 00 01 61 62 63 64 65 66   ....$'BB..abcdef\n\
+                    0030  67 68                                             gh\n";
+        assert_eq!(parse_hex_dump(dump).unwrap(), echo_frame());
+    }
+
+    #[test]
+    fn hexdump_dump_parses_without_its_offsets_and_ascii() {
+        let dump = "00000000  00 11 22 33 44 55 52 54  00 12 34 56 08 00 45 00  |..\"3DURT..4V..E.|\n\
+                    00000010  00 24 12 34 40 00 40 01  14 a3 0a 00 00 02 0a 00  |.$.4@.@.........|\n\
+                    00000020  00 01 08 00 24 27 42 42  00 01 61 62 63 64 65 66  |....$'BB..abcdef|\n\
+                    00000030  67 68                                             |gh|\n\
+                    00000032\n";
+        assert_eq!(parse_hex_dump(dump).unwrap(), echo_frame());
+    }
+
+    #[test]
+    fn hex_without_offsets_parses_in_any_grouping() {
+        let frame = echo_frame();
+        let colons = frame.iter().map(|byte| format!("{:02x}", byte)).collect::<Vec<_>>().join(":");
+        assert_eq!(parse_hex_dump(&colons).unwrap(), frame);
+        let prefixed = "0x0800 0x2427\n0x4242 0x0001";
+        assert_eq!(parse_hex_dump(prefixed).unwrap(), &frame[ICMP_AT..ICMP_AT + 8]);
+        // Without the two spaces of a column, four hex digits at the start of a line are bytes.
+        assert_eq!(parse_hex_dump("0000 0800").unwrap(), [0, 0, 8, 0]);
+    }
+
+    #[test]
+    fn malformed_dumps_are_rejected() {
+        assert_eq!(parse_hex_dump("45 0").unwrap_err(), "Odd number of hex digits in 0");
+        let gap = "0x0000:  4500 0024\n0x0010:  4001 14a3";
+        assert_eq!(parse_hex_dump(gap).unwrap_err(), "Missing bytes before offset 0x0010");
+    }
+
+    #[test]
+    fn decoding_starts_at_each_layer() {
+        let frame = echo_frame();
+
+        let ethernet = dissect(&frame, StartLayer::Ethernet);
+        assert_eq!(ethernet.fields[0].layer, "ETHER");
+        assert_eq!(value(&ethernet, "ETHER", "Ethertype"), EtherType::IPV4.to_string());
+        let source = ethernet.fields.iter().find(|field| field.layer == "IP" && field.name == "Source");
+        assert_eq!(source.unwrap().offset, IPV4_AT + 12);
+
+        let ipv4 = dissect(&frame[IPV4_AT..], StartLayer::Ipv4);
+        assert_eq!((ipv4.fields[0].layer, ipv4.fields[0].offset), ("IP", 0));
+        assert_eq!(value(&ipv4, "IP", "Source"), "10.0.0.2");
+        assert_eq!(value(&ipv4, "IP", "Destination"), "10.0.0.1");
+        assert!(!ipv4.fields.iter().any(|field| field.layer == "ETHER"));
+
+        let icmp = dissect(&frame[ICMP_AT..], StartLayer::Icmp);
+        assert!(icmp.fields.iter().all(|field| field.layer == "ICMP"));
+        assert_eq!(value(&icmp, "ICMP", "Type"), "8 (echo request)");
+        assert_eq!(value(&icmp, "ICMP", "Identifier"), "0x4242");
+        assert_eq!(value(&icmp, "ICMP", "Sequence"), "1");
+        assert_eq!(value(&icmp, "ICMP", "Data"), "8 bytes");
+
+        for dissector in [ethernet, ipv4, icmp] {
+            assert_eq!(value(&dissector, "ICMP", "Checksum"), "0x2427 (correct)");
+            assert!(dissector.checksums_valid);
+        }
+    }
+
+    #[test]
+    fn wrong_checksums_are_reported_invalid() {
+        let mut frame = echo_frame();
+        frame[ICMP_AT + 8] ^= 0xff;
+        let dissector = dissect(&frame, StartLayer::Ethernet);
+        assert_eq!(value(&dissector, "IP", "Checksum"), "0x14a3 (correct)");
+        assert!(value(&dissector, "ICMP", "Checksum").contains("INCORRECT"));
+        assert!(!dissector.checksums_valid);
+
+        let mut frame = echo_frame();
+        frame[IPV4_AT + 8] -= 1;
+        let dissector = dissect(&frame, StartLayer::Ethernet);
+        assert!(value(&dissector, "IP", "Checksum").contains("INCORRECT"));
+        assert!(!dissector.checksums_valid);
+    }
+
+    #[test]
+    fn unverifiable_checksums_are_not_reported_invalid() {
+        // The ICMP checksum of a truncated message covers bytes that are missing.
+        let frame = echo_frame();
+        let dissector = dissect(&frame[IPV4_AT..frame.len() - 2], StartLayer::Ipv4);
+        assert_eq!(value(&dissector, "ICMP", "Checksum"), "0x2427 (not verified, message truncated)");
+        assert!(dissector.checksums_valid);
+
+        // A UDP checksum left out by the sender.
+        let datagram = [0xd4, 0x31, 0, 53, 0, 8, 0, 0];
+        let packet = ipv4_packet(IpProtocol::UDP, &datagram);
+        let dissector = dissect(&packet, StartLayer::Ipv4);
+        assert_eq!(value(&dissector, "UDP", "Checksum"), "0x0000 (none)");
+        assert!(dissector.checksums_valid);
+
+        // A UDP checksum left for the network card, as in a capture on the sending host.
+        let mut datagram = [0xd4, 0x31, 0, 53, 0, 8, 0, 0];
+        let pseudo_header = pseudo_header([10, 0, 0, 2], [10, 0, 0, 1], IpProtocol::UDP, 8);
+        datagram[6..8].copy_from_slice(&(!calculate_checksum(&pseudo_header)).to_be_bytes());
+        let packet = ipv4_packet(IpProtocol::UDP, &datagram);
+        let dissector = dissect(&packet, StartLayer::Ipv4);
+        assert!(value(&dissector, "UDP", "Checksum").contains("unless offloaded"));
+        assert!(dissector.checksums_valid);
+    }
+}
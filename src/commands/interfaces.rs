@@ -0,0 +1,55 @@
+//! Lists the local interfaces and the IPv4 routing table.
+//!
+//! Shows what the other commands pick by default: the interface of the default
+//! route, and for every destination the route, source address and gateway.
+
+use rust_network::interface::list_interfaces;
+use rust_network::interface::route::routing_table;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::ipv4::subnet::netmask_to_prefix;
+
+use std::net::Ipv6Addr;
+
+/// Prints the interfaces with their addresses, then the routes.
+pub(crate) fn interfaces() {
+    for interface in list_interfaces() {
+        let mut flags = vec![if interface.up { "UP" } else { "DOWN" }];
+        if interface.loopback {
+            flags.push("LOOPBACK");
+        }
+        print!("{}: {} <{}>", interface.index, interface.name, flags.join(","));
+        if let Some(mtu) = interface.mtu {
+            print!(" mtu {}", mtu);
+        }
+        println!();
+        if let Some(mac) = interface.mac {
+            println!("    ether {}", mac);
+        }
+        if let Some(ipv4) = interface.ipv4 {
+            let prefix = interface.netmask.map_or(32, netmask_to_prefix);
+            println!("    inet {}/{}", format_ipv4_address(&ipv4), prefix);
+        }
+        for ipv6 in &interface.ipv6 {
+            println!("    inet6 {}", Ipv6Addr::from(*ipv6));
+        }
+    }
+
+    println!();
+    for route in routing_table() {
+        let destination = if route.prefix == 0 {
+            "default".to_string()
+        } else {
+            format!("{}/{}", format_ipv4_address(&route.destination), route.prefix)
+        };
+        match route.gateway {
+            Some(gateway) => println!(
+                "{} via {} dev {} metric {}",
+                destination,
+                format_ipv4_address(&gateway),
+                route.interface,
+                route.metric
+            ),
+            None => println!("{} dev {} metric {}", destination, route.interface, route.metric),
+        }
+    }
+}
@@ -0,0 +1,30 @@
+//! Name lookups, to check what the other commands will resolve a target to.
+
+use rust_network::dns::Resolver;
+
+use std::net::Ipv4Addr;
+
+/// Prints the addresses of a name, or the name of an IPv4 address.
+///
+/// # Arguments
+/// * `resolver` - Resolver to ask, the system's or our own DNS client.
+/// * `target` - Name to resolve, or address to look up in `in-addr.arpa`.
+pub(crate) fn lookup(resolver: &Resolver, target: &str) {
+    if let Ok(address) = target.parse::<Ipv4Addr>() {
+        match resolver.reverse(address.octets()) {
+            Some(name) => println!("{} is {}", address, name),
+            None => println!("{} has no name", address),
+        }
+        return;
+    }
+    match resolver.lookup(target) {
+        Ok(addresses) if addresses.is_empty() => println!("{} has no address", target),
+        Ok(addresses) => {
+            for address in addresses {
+                let family = if address.is_ipv4() { "address" } else { "IPv6 address" };
+                println!("{} has {} {}", target, family, address);
+            }
+        }
+        Err(e) => eprintln!("lookup: {}", e),
+    }
+}
@@ -1 +1,13 @@
-pub mod ping;
\ No newline at end of file
+pub mod arping;
+pub mod arpscan;
+pub mod craft;
+pub mod decode;
+pub mod interfaces;
+pub mod lookup;
+pub mod monitor;
+pub mod ping;
+pub mod pmtu;
+pub mod responder;
+pub mod sniff;
+pub mod sweep;
+pub mod traceroute;
\ No newline at end of file
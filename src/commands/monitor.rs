@@ -0,0 +1,283 @@
+//! Continuous reachability monitoring.
+//!
+//! Pings a set of hosts forever, like a small smokeping, and reports when a host
+//! goes down after several lost probes in a row or comes back with its first reply.
+//! A table of the statistics of every host is printed periodically.
+
+use crate::cli::{next_value, parse_count, parse_seconds, Verbosity};
+use crate::commands::ping::{damage, unreachable_reason, PingStatistics};
+use crate::interrupt::stop_on_interrupt;
+use crate::output::{event_record, Output, Record};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+use rust_network::transport::Transport;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Probes lost in a row before a host is reported down.
+const LOSSES_BEFORE_DOWN: u32 = 3;
+
+/// Options of a monitoring session.
+pub(crate) struct MonitorOptions {
+    /// Hosts to monitor, names or addresses.
+    pub(crate) targets: Vec<String>,
+    /// Probes per host, `None` to run until interrupted.
+    pub(crate) count: Option<u32>,
+    /// Time between two probes to the same host.
+    pub(crate) interval: Duration,
+    /// How long to wait for each reply.
+    pub(crate) timeout: Duration,
+    /// Time between two statistics tables.
+    pub(crate) report_interval: Duration,
+}
+
+impl MonitorOptions {
+    /// Parses the monitor options from the command line arguments following `monitor`.
+    ///
+    /// Usage: `<host...> [-c count] [-i seconds] [-W seconds] [--report seconds]`
+    pub(crate) fn from_args(args: &[String]) -> Result<MonitorOptions, String> {
+        let mut options = MonitorOptions {
+            targets: Vec::new(),
+            count: None,
+            interval: Duration::from_secs(1),
+            timeout: Duration::from_secs(2),
+            report_interval: Duration::from_secs(60),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" => options.count = Some(parse_count(next_value(&mut args, arg)?)?),
+                "-i" => options.interval = parse_seconds(next_value(&mut args, arg)?)?,
+                "-W" => options.timeout = parse_seconds(next_value(&mut args, arg)?)?,
+                "--report" => options.report_interval = parse_seconds(next_value(&mut args, arg)?)?,
+                _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+                _ => options.targets.push(arg.clone()),
+            }
+        }
+
+        if options.targets.is_empty() {
+            return Err("Missing target hosts".to_string());
+        }
+        Ok(options)
+    }
+}
+
+/// State of a monitored host.
+struct Host {
+    /// Address pinged.
+    address: [u8; 4],
+    /// Statistics of the host since the start.
+    statistics: PingStatistics,
+    /// Probes lost in a row.
+    losses: u32,
+    /// Whether the host answers, `None` until its first reply or its going down.
+    up: Option<bool>,
+}
+
+/// Monitors hosts, printing when they go up or down and a periodic table of their statistics.
+///
+/// # Arguments
+/// * `transport` - Transport to send the echo requests on.
+/// * `targets` - Addresses to monitor with their names.
+/// * `options` - Options of the session.
+/// * `probe_options` - Source, next hop and VLAN stack of the probes.
+/// * `output` - Output of the session; `-q` prints only the tables, `-v` every probe too.
+///   Records are written for every probe and state change, and summaries instead of the tables.
+pub(crate) fn monitor(
+    transport: Arc<dyn Transport>,
+    targets: &[([u8; 4], String)],
+    options: &MonitorOptions,
+    probe_options: PingerOptions,
+    output: &mut Output,
+) {
+    let mut hosts: Vec<Host> = targets
+        .iter()
+        .map(|(address, name)| Host {
+            address: *address,
+            statistics: PingStatistics::new(name.clone()),
+            losses: 0,
+            up: None,
+        })
+        .collect();
+
+    let pinger = Pinger::new(
+        Arc::clone(&transport),
+        PingerOptions {
+            count: options.count,
+            interval: options.interval,
+            timeout: options.timeout,
+            ..probe_options
+        },
+    );
+    let mut pinger = match pinger {
+        Ok(pinger) => pinger,
+        Err(e) => {
+            eprintln!("Failed to create pinger: {}", e);
+            return;
+        }
+    };
+    for host in hosts.iter() {
+        pinger.add_target(host.address);
+    }
+    // Ctrl-C ends the session, which still prints the last table.
+    stop_on_interrupt(pinger.stop_flag());
+    if output.is_text() {
+        println!("monitoring {} hosts", hosts.len());
+    }
+
+    let start = transport.now();
+    let mut last_report = start;
+    let result = pinger.run(|event| {
+        let now = transport.now();
+        let host = match hosts.iter_mut().find(|host| host.address == event.target()) {
+            Some(host) => host,
+            None => return,
+        };
+        if !output.is_text() {
+            output.event(event_record(&event));
+        } else if output.verbosity == Verbosity::Verbose {
+            println!("[{}] {}", elapsed(start, now), describe(&event));
+        }
+        // A duplicate reply repeats an event already counted.
+        if let PingEvent::Duplicate { .. } = event {
+            host.statistics.record_duplicate();
+            return;
+        }
+        host.statistics.record_sent();
+
+        let was_up = host.up;
+        if let PingEvent::Reply { rtt, .. } = event {
+            // A corrupted reply still shows the host is up.
+            if event.is_corrupted() {
+                host.statistics.record_corrupted();
+            } else {
+                host.statistics.record_reply(rtt);
+            }
+            host.losses = 0;
+            host.up = Some(true);
+        } else {
+            host.losses += 1;
+            if host.losses >= LOSSES_BEFORE_DOWN {
+                host.up = Some(false);
+            }
+        }
+        if host.up != was_up {
+            let state = if host.up == Some(true) { "up" } else { "down" };
+            if !output.is_text() {
+                output.event(
+                    Record::new("state")
+                        .field("target", host.statistics.target.clone())
+                        .field("state", state),
+                );
+            } else if output.verbosity != Verbosity::Quiet {
+                println!("[{}] {} is {}", elapsed(start, now), host.statistics.target, state);
+            }
+        }
+
+        if now.duration_since(last_report) >= options.report_interval {
+            report(&hosts, output);
+            last_report = now;
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("monitor: {}", e);
+    }
+    report(&hosts, output);
+    output.finish();
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Formats the time since the start of the session, e.g. `00:01:05`.
+fn elapsed(start: Instant, now: Instant) -> String {
+    let seconds = now.duration_since(start).as_secs();
+    format!("{:02}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+}
+
+/// Describes the outcome of a probe in one line.
+fn describe(event: &PingEvent) -> String {
+    let target = format_ipv4_address(&event.target());
+    match *event {
+        PingEvent::Reply {
+            sequence,
+            rtt,
+            corrupted,
+            wrong_byte,
+            ..
+        } => format!(
+            "{} icmp_seq={} time={:.3} ms{}",
+            target,
+            sequence,
+            rtt.as_secs_f64() * 1000.0,
+            damage(corrupted, wrong_byte)
+        ),
+        PingEvent::Duplicate { sequence, rtt, .. } => format!(
+            "{} icmp_seq={} time={:.3} ms (DUP!)",
+            target,
+            sequence,
+            rtt.as_secs_f64() * 1000.0
+        ),
+        PingEvent::Timeout { sequence, .. } => format!("{} icmp_seq={} timeout", target, sequence),
+        PingEvent::Unreachable {
+            sequence, from, code, ..
+        } => format!(
+            "{} icmp_seq={} Destination {} from {}",
+            target,
+            sequence,
+            unreachable_reason(code),
+            format_ipv4_address(&from)
+        ),
+        PingEvent::TtlExceeded { sequence, from, .. } => format!(
+            "{} icmp_seq={} Time to live exceeded at {}",
+            target,
+            sequence,
+            format_ipv4_address(&from)
+        ),
+        PingEvent::SendFailed {
+            sequence, ref error, ..
+        } => format!("{} icmp_seq={} send failed: {}", target, sequence, error),
+    }
+}
+
+/// Reports the statistics of every host, as a table or as summary records.
+fn report(hosts: &[Host], output: &mut Output) {
+    if !output.is_text() {
+        for host in hosts {
+            output.summary(host.statistics.to_record());
+        }
+        return;
+    }
+
+    println!(
+        "\n{:<24} {:>5} {:>6} {:>6} {:>6} {:>9} {:>9}",
+        "host", "state", "sent", "recv", "loss", "min ms", "max ms"
+    );
+    for host in hosts {
+        let statistics = &host.statistics;
+        let state = match host.up {
+            Some(true) => "up",
+            Some(false) => "down",
+            None => "?",
+        };
+        let (min, max) = if statistics.received > 0 {
+            (
+                format!("{:.3}", statistics.rtt_min.as_secs_f64() * 1000.0),
+                format!("{:.3}", statistics.rtt_max.as_secs_f64() * 1000.0),
+            )
+        } else {
+            ("-".to_string(), "-".to_string())
+        };
+        println!(
+            "{:<24} {:>5} {:>6} {:>6} {:>5.1}% {:>9} {:>9}",
+            statistics.target,
+            state,
+            statistics.transmitted,
+            statistics.received,
+            statistics.loss_percent(),
+            min,
+            max
+        );
+    }
+}
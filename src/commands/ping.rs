@@ -1,82 +1,386 @@
-use crate::ethernet_frame::EthernetFrame;
-
-extern crate libc;
-use crate::ipv4::icmp::ICMPPacket;
-use crate::ipv4::internet_packet::IPV4;
-use libc::{ifreq, ioctl};
-use std::os::unix::io::{AsRawFd, RawFd};
-use std::{io::Write, mem};
-
-/// This function will print the ping data.
-pub(crate) fn print_ping(frame: &[u8]){
-    let mut frame = EthernetFrame::from_bytes(frame);
-    println!("{:?}", frame.packet.source_add);
-    let ether_len = frame.to_bytes().len();
-    print!("{}: ", ether_len);
-}
-
-/// Sends an ICMP echo request to the specified IP address.
-pub(crate) fn send_icmp_echo_request(sequence: u16, source_ip_add: [u8; 4]) {
-    let icmp_req = ICMPPacket::new_echo_request(sequence);
-        
-    let ipv4_packet = IPV4::new_icmp_from_ip(icmp_req, 64, source_ip_add);
+use crate::cli::{next_value, parse_count, parse_seconds, Verbosity};
+use crate::commands::decode::parse_hex_dump;
+use crate::interrupt::stop_on_interrupt;
+use crate::output::{event_record, milliseconds, Output, Record};
+use rust_network::ipv4::icmp::{CODE_FRAGMENTATION_NEEDED, CODE_HOST_UNREACHABLE};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::pinger::payload::{PayloadPattern, TimestampFormat};
+use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+use rust_network::transport::Transport;
 
-    let mut ether_frame = EthernetFrame::new_ether(ipv4_packet);
+use std::sync::Arc;
+use std::time::Duration;
 
-    let pack = ether_frame.to_bytes();
-    println!("{:?}", pack.len());
+/// Largest echo request data, filling an IPv4 packet with the ICMP header.
+const MAX_PAYLOAD_SIZE: usize = 65507;
 
-    let mut bpf_device = std::fs::OpenOptions::new()
-        .write(true)
-        .open("/dev/bpf243")
-        .expect("Failed to open BPF device");
-    let bpf_fd: RawFd = bpf_device.as_raw_fd();
+/// Options of a ping session given on the command line.
+pub(crate) struct PingOptions {
+    /// Host to ping, a name or an address, `None` for the default target.
+    pub(crate) target: Option<String>,
+    /// Number of echo requests to send, `None` to run until interrupted.
+    pub(crate) count: Option<u32>,
+    /// Time between two echo requests.
+    pub(crate) interval: Duration,
+    /// TTL of the echo requests.
+    pub(crate) ttl: u8,
+    /// How long to wait for each reply.
+    pub(crate) timeout: Duration,
+    /// Length of the echo request data, `None` for just the timestamp.
+    pub(crate) payload_size: Option<usize>,
+    /// Fill of the echo request data after the timestamp.
+    pub(crate) pattern: PayloadPattern,
+    /// Encoding of the send time in the echo request data.
+    pub(crate) timestamp: TimestampFormat,
+}
 
-    // Bind to an interface
-    bind_bpf_to_interface(bpf_fd, "en0");
+impl PingOptions {
+    /// Parses the ping options from the command line arguments following `ping`.
+    ///
+    /// Usage: `[host] [-c count] [-i seconds] [-t ttl] [-W seconds] [-s size] [-p pattern] [--timestamp format]`
+    ///
+    /// The pattern is hex bytes, or `zeros`, `incrementing` or `random`; the timestamp
+    /// format `nanos`, `timeval` or `none`.
+    pub(crate) fn from_args(args: &[String]) -> Result<PingOptions, String> {
+        let defaults = PingerOptions::default();
+        let mut options = PingOptions {
+            target: None,
+            count: None,
+            interval: defaults.interval,
+            ttl: defaults.ttl,
+            timeout: defaults.timeout,
+            payload_size: defaults.payload_size,
+            pattern: defaults.pattern,
+            timestamp: defaults.timestamp,
+        };
 
-    bpf_device.write_all(&pack).expect("Failed to write packet");
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-c" => options.count = Some(parse_count(next_value(&mut args, arg)?)?),
+                "-i" => options.interval = parse_seconds(next_value(&mut args, arg)?)?,
+                "-t" => {
+                    let ttl = next_value(&mut args, arg)?;
+                    options.ttl = match ttl.parse() {
+                        Ok(ttl) if ttl > 0 => ttl,
+                        _ => return Err(format!("Invalid TTL: {}", ttl)),
+                    };
+                }
+                "-W" => options.timeout = parse_seconds(next_value(&mut args, arg)?)?,
+                "-s" => {
+                    let size = next_value(&mut args, arg)?;
+                    options.payload_size = match size.parse() {
+                        Ok(size) if size <= MAX_PAYLOAD_SIZE => Some(size),
+                        _ => return Err(format!("Invalid payload size: {}", size)),
+                    };
+                }
+                "-p" => options.pattern = parse_pattern(next_value(&mut args, arg)?)?,
+                "--timestamp" => {
+                    options.timestamp = match next_value(&mut args, arg)? {
+                        "nanos" => TimestampFormat::Nanos,
+                        "timeval" => TimestampFormat::Timeval,
+                        "none" => TimestampFormat::Omitted,
+                        format => return Err(format!("Unknown timestamp format: {}", format)),
+                    };
+                }
+                _ if options.target.is_none() => options.target = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+        Ok(options)
+    }
 }
 
-/// Receives an ICMP echo response.
-pub(crate) fn recv_icmp_response() {
-    let device = pcap::Device::lookup()
-        .expect("device lookup failed")
-        .expect("no device available");
-
-    let mut cap = pcap::Capture::from_device(device)
-        .unwrap()
-        .immediate_mode(true)
-        .open()
-        .unwrap();
-    
-    // Filter to read the ICMP echo response
-    cap.filter("icmp[icmptype] == icmp-echoreply", false).unwrap();
-
-    let data = cap.next_packet().unwrap().data;
-    // let data = cap.filter(, optimize)
-
-    print_ping(data);
+/// Statistics of a ping session, printed as the summary when it ends.
+pub(crate) struct PingStatistics {
+    /// Target shown in the summary header.
+    pub(crate) target: String,
+    /// Number of requests sent.
+    pub(crate) transmitted: u64,
+    /// Number of replies received.
+    pub(crate) received: u64,
+    /// Number of replies received with a bad checksum or altered data, counted apart from `received`.
+    pub(crate) corrupted: u64,
+    /// Number of further copies of replies already received, counted apart from `received`.
+    pub(crate) duplicates: u64,
+    /// Shortest round trip time seen.
+    pub(crate) rtt_min: Duration,
+    /// Longest round trip time seen.
+    pub(crate) rtt_max: Duration,
+    /// Sum of the round trip times in seconds, for the average.
+    rtt_sum: f64,
+    /// Sum of the squared round trip times in seconds, for the deviation.
+    rtt_sum_squares: f64,
 }
 
-/// Binds the BPF device to the specified interface.    
-pub(crate) fn bind_bpf_to_interface(fd: RawFd, interface_name: &str) {
+impl PingStatistics {
+    /// Creates empty statistics for the given target.
+    pub(crate) fn new(target: String) -> PingStatistics {
+        PingStatistics {
+            target,
+            transmitted: 0,
+            received: 0,
+            corrupted: 0,
+            duplicates: 0,
+            rtt_min: Duration::MAX,
+            rtt_max: Duration::ZERO,
+            rtt_sum: 0.0,
+            rtt_sum_squares: 0.0,
+        }
+    }
 
-    unsafe {
-        let mut ifr = ifreq {
-            ifr_name: [0; libc::IFNAMSIZ],
-            ifr_ifru: mem::zeroed(),
-        };
+    /// Records a sent request.
+    pub(crate) fn record_sent(&mut self) {
+        self.transmitted += 1;
+    }
 
-        // Copy the interface name into the ifreq structure
-        let bytes = interface_name.as_bytes();
-        for (i, &byte) in bytes.iter().enumerate() {
-            ifr.ifr_name[i] = byte as i8;
+    /// Records a received reply and its round trip time.
+    pub(crate) fn record_reply(&mut self, rtt: Duration) {
+        self.received += 1;
+        self.rtt_min = self.rtt_min.min(rtt);
+        self.rtt_max = self.rtt_max.max(rtt);
+        let rtt = rtt.as_secs_f64();
+        self.rtt_sum += rtt;
+        self.rtt_sum_squares += rtt * rtt;
+    }
+
+    /// Records a reply that failed its checksum or altered our data.
+    pub(crate) fn record_corrupted(&mut self) {
+        self.corrupted += 1;
+    }
+
+    /// Records a further copy of a reply already received.
+    pub(crate) fn record_duplicate(&mut self) {
+        self.duplicates += 1;
+    }
+
+    /// Percentage of requests that got no reply, corrupted replies not counting as lost.
+    pub(crate) fn loss_percent(&self) -> f64 {
+        if self.transmitted == 0 {
+            return 0.0;
+        }
+        let answered = self.received + self.corrupted;
+        100.0 * (self.transmitted.saturating_sub(answered)) as f64 / self.transmitted as f64
+    }
+
+    /// Average and standard deviation of the round trip times in seconds, `None` without replies.
+    pub(crate) fn rtt_avg_mdev(&self) -> Option<(f64, f64)> {
+        if self.received == 0 {
+            return None;
         }
+        let count = self.received as f64;
+        let avg = self.rtt_sum / count;
+        let mdev = (self.rtt_sum_squares / count - avg * avg).max(0.0).sqrt();
+        Some((avg, mdev))
+    }
+
+    /// Builds the summary record of the statistics.
+    pub(crate) fn to_record(&self) -> Record {
+        let replied = self.received > 0;
+        let avg_mdev = self.rtt_avg_mdev();
+        Record::new("summary")
+            .field("target", self.target.clone())
+            .field("transmitted", self.transmitted)
+            .field("received", self.received)
+            .field("corrupted", self.corrupted)
+            .field("duplicates", self.duplicates)
+            .field("loss_percent", self.loss_percent())
+            .field("rtt_min_ms", replied.then(|| milliseconds(self.rtt_min)))
+            .field("rtt_avg_ms", avg_mdev.map(|(avg, _)| avg * 1000.0))
+            .field("rtt_max_ms", replied.then(|| milliseconds(self.rtt_max)))
+            .field("rtt_mdev_ms", avg_mdev.map(|(_, mdev)| mdev * 1000.0))
+    }
+}
 
-        // Perform the ioctl operation to bind the BPF device to the interface
-        if ioctl(fd, libc::BIOCSETIF, &ifr) == -1 {
-            panic!("Failed to bind BPF device to interface");
+/// Implements the Display trait for PingStatistics.
+impl std::fmt::Display for PingStatistics {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "--- {} statistics ---", self.target)?;
+        write!(f, "{} packets transmitted, {} packets received, ", self.transmitted, self.received)?;
+        if self.corrupted > 0 {
+            write!(f, "+{} corrupted, ", self.corrupted)?;
+        }
+        if self.duplicates > 0 {
+            write!(f, "+{} duplicates, ", self.duplicates)?;
+        }
+        writeln!(f, "{:.1}% packet loss", self.loss_percent())?;
+        if let Some((avg, mdev)) = self.rtt_avg_mdev() {
+            writeln!(
+                f,
+                "round-trip min/avg/max/mdev = {:.3}/{:.3}/{:.3}/{:.3} ms",
+                self.rtt_min.as_secs_f64() * 1000.0,
+                avg * 1000.0,
+                self.rtt_max.as_secs_f64() * 1000.0,
+                mdev * 1000.0
+            )?;
         }
+        Ok(())
     }
 }
+
+/// Pings a target, printing the outcome of every probe and then the statistics.
+///
+/// # Arguments
+/// * `transport` - Transport to send the echo requests on.
+/// * `target` - Address to ping.
+/// * `target_name` - Name of the target shown in the output, its address if it has none.
+/// * `options` - Options of the probes.
+/// * `output` - Output of the probes and statistics; `-q` prints only the statistics.
+pub(crate) fn ping(
+    transport: Arc<dyn Transport>,
+    target: [u8; 4],
+    target_name: String,
+    options: PingerOptions,
+    output: &mut Output,
+) {
+    let mut pinger = match Pinger::new(transport, options) {
+        Ok(pinger) => pinger,
+        Err(e) => {
+            eprintln!("Failed to create pinger: {}", e);
+            return;
+        }
+    };
+    pinger.add_target(target);
+    // Ctrl-C ends the run, which still prints the statistics.
+    stop_on_interrupt(pinger.stop_flag());
+    if output.is_text() {
+        println!("PING {} ({})", target_name, format_ipv4_address(&target));
+    }
+    let (handle, events) = pinger.spawn();
+
+    let mut statistics = PingStatistics::new(target_name.clone());
+    for event in events {
+        // A duplicate reply repeats an event already counted.
+        if !matches!(event, PingEvent::Duplicate { .. }) {
+            statistics.record_sent();
+        }
+        match event {
+            PingEvent::Reply { .. } if event.is_corrupted() => statistics.record_corrupted(),
+            PingEvent::Reply { rtt, .. } => statistics.record_reply(rtt),
+            PingEvent::Duplicate { .. } => statistics.record_duplicate(),
+            _ => {}
+        }
+        if !output.is_text() {
+            output.event(event_record(&event));
+            continue;
+        }
+        if output.verbosity == Verbosity::Quiet {
+            continue;
+        }
+        match event {
+            PingEvent::Reply {
+                sequence,
+                rtt,
+                ttl,
+                bytes,
+                corrupted,
+                wrong_byte,
+                ..
+            } => {
+                println!(
+                    "{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms{}",
+                    bytes,
+                    target_name,
+                    sequence,
+                    ttl,
+                    rtt.as_secs_f64() * 1000.0,
+                    damage(corrupted, wrong_byte)
+                );
+            }
+            PingEvent::Duplicate {
+                sequence,
+                rtt,
+                ttl,
+                bytes,
+                ..
+            } => println!(
+                "{} bytes from {}: icmp_seq={} ttl={} time={:.3} ms (DUP!)",
+                bytes,
+                target_name,
+                sequence,
+                ttl,
+                rtt.as_secs_f64() * 1000.0
+            ),
+            PingEvent::Timeout { sequence, .. } => println!("Request timeout for icmp_seq {}", sequence),
+            PingEvent::Unreachable {
+                sequence,
+                from,
+                code,
+                next_hop_mtu,
+                ..
+            } => match next_hop_mtu {
+                Some(mtu) => println!(
+                    "From {}: icmp_seq={} Frag needed and DF set (mtu = {})",
+                    format_ipv4_address(&from),
+                    sequence,
+                    mtu
+                ),
+                None => println!(
+                    "From {}: icmp_seq={} Destination {}",
+                    format_ipv4_address(&from),
+                    sequence,
+                    unreachable_reason(code)
+                ),
+            },
+            PingEvent::TtlExceeded { sequence, from, .. } => println!(
+                "From {}: icmp_seq={} Time to live exceeded",
+                format_ipv4_address(&from),
+                sequence
+            ),
+            PingEvent::SendFailed { sequence, error, .. } => {
+                eprintln!("ping: icmp_seq={} send failed: {}", sequence, error)
+            }
+        }
+    }
+    if let Err(e) = handle.join().unwrap() {
+        eprintln!("ping: {}", e);
+    }
+
+    if output.is_text() {
+        print!("\n{}", statistics);
+    } else {
+        output.summary(statistics.to_record());
+        output.finish();
+    }
+}
+
+/// Describes what is wrong with a reply, e.g. ` (wrong data byte #12)`, empty if nothing.
+pub(crate) fn damage(corrupted: bool, wrong_byte: Option<usize>) -> String {
+    let mut damage = String::new();
+    if corrupted {
+        damage += " (BAD CHECKSUM)";
+    }
+    if let Some(offset) = wrong_byte {
+        damage += &format!(" (wrong data byte #{})", offset);
+    }
+    damage
+}
+
+/// Describes the code of a Destination Unreachable message.
+pub(crate) fn unreachable_reason(code: u8) -> &'static str {
+    match code {
+        0 => "Net Unreachable",
+        CODE_HOST_UNREACHABLE => "Host Unreachable",
+        2 => "Protocol Unreachable",
+        3 => "Port Unreachable",
+        CODE_FRAGMENTATION_NEEDED => "Fragmentation Needed",
+        9 | 10 | 13 => "Administratively Prohibited",
+        _ => "Unreachable",
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Parses the fill pattern of the echo request data: hex bytes, or `zeros`,
+/// `incrementing` or `random`.
+fn parse_pattern(text: &str) -> Result<PayloadPattern, String> {
+    Ok(match text {
+        "zeros" => PayloadPattern::Zeros,
+        "incrementing" => PayloadPattern::Incrementing,
+        "random" => PayloadPattern::Random,
+        _ => match parse_hex_dump(text)? {
+            bytes if bytes.is_empty() => return Err(format!("Invalid pattern: {}", text)),
+            bytes => PayloadPattern::Repeat(bytes),
+        },
+    })
+}
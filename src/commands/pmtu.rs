@@ -0,0 +1,312 @@
+//! Path MTU discovery.
+//!
+//! Sends echo requests with Don't Fragment set, starting at the MTU of our own
+//! link. A router that cannot forward a probe answers with Fragmentation Needed and
+//! the MTU of its next link, which the next probe shrinks to. Routers that drop
+//! the probes silently are searched around by halving the range of sizes still in
+//! question.
+
+use crate::cli::{next_value, parse_seconds};
+use crate::output::{Output, Record};
+use rust_network::interface::lookup_interface;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+use rust_network::transport::Transport;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Smallest MTU every IPv4 link must carry.
+const MIN_MTU: usize = 68;
+
+/// MTU assumed for links that do not report theirs.
+const DEFAULT_MTU: usize = 1500;
+
+/// Length of the IPv4 and ICMP headers of a probe.
+const HEADERS_LEN: usize = 28;
+
+/// Options of a path MTU discovery.
+pub(crate) struct PmtuOptions {
+    /// Host to discover the path MTU to, a name or an address.
+    pub(crate) target: String,
+    /// How long to wait for the answer to a probe.
+    pub(crate) timeout: Duration,
+}
+
+impl PmtuOptions {
+    /// Parses the pmtu options from the command line arguments following `pmtu`.
+    ///
+    /// Usage: `<host> [-W seconds]`
+    pub(crate) fn from_args(args: &[String]) -> Result<PmtuOptions, String> {
+        let mut target = None;
+        let mut timeout = Duration::from_secs(2);
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-W" => timeout = parse_seconds(next_value(&mut args, arg)?)?,
+                _ if target.is_none() => target = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        Ok(PmtuOptions {
+            target: target.ok_or("Missing target host")?,
+            timeout,
+        })
+    }
+}
+
+/// Outcome of a probe of a given size.
+enum Probe {
+    /// The target answered, the size fits the path.
+    Fits,
+    /// A router reported the size too large, with the MTU of its next link.
+    TooLarge(usize),
+    /// No answer, or the probe could not even be sent.
+    Lost,
+    /// The target cannot be reached at all.
+    Unreachable(String),
+}
+
+/// Discovers the largest packet reaching the target unfragmented and prints it.
+///
+/// # Arguments
+/// * `transport` - Transport to send the probes on.
+/// * `target` - Address to discover the path MTU to.
+/// * `options` - Options of the discovery.
+/// * `probe_options` - Source, next hop and VLAN stack of the probes.
+/// * `output` - Output of the probes and the path MTU, or of a record per probe and a summary.
+pub(crate) fn pmtu(
+    transport: Arc<dyn Transport>,
+    target: [u8; 4],
+    options: &PmtuOptions,
+    probe_options: PingerOptions,
+    output: &mut Output,
+) {
+    let link_mtu = lookup_interface(transport.name())
+        .and_then(|interface| interface.mtu)
+        .map_or(DEFAULT_MTU, |mtu| mtu as usize);
+    if output.is_text() {
+        println!(
+            "pmtu to {} ({}), starting at {} bytes",
+            options.target,
+            format_ipv4_address(&target),
+            link_mtu
+        );
+    }
+
+    let mut unreachable = false;
+    let path_mtu = discover(&transport, target, link_mtu, options.timeout, &probe_options, |size, outcome| {
+        unreachable |= matches!(outcome, Probe::Unreachable(_));
+        if !output.is_text() {
+            output.event(probe_record(&options.target, size, outcome));
+            return;
+        }
+        match outcome {
+            Probe::Fits => println!("{:>5} bytes: reply", size),
+            Probe::TooLarge(mtu) => println!("{:>5} bytes: fragmentation needed, next hop mtu {}", size, mtu),
+            Probe::Lost => println!("{:>5} bytes: no reply", size),
+            Probe::Unreachable(reason) => println!("{:>5} bytes: {}", size, reason),
+        }
+    });
+    if !output.is_text() {
+        output.summary(
+            Record::new("summary")
+                .field("target", options.target.clone())
+                .field("link_mtu", link_mtu)
+                .field("path_mtu", path_mtu),
+        );
+        output.finish();
+        return;
+    }
+    match path_mtu {
+        Some(mtu) => println!("path mtu to {} is {} bytes", format_ipv4_address(&target), mtu),
+        None if unreachable => {}
+        None => println!("no reply from {}, even at {} bytes", format_ipv4_address(&target), MIN_MTU),
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Searches the largest packet reaching the target unfragmented, starting at the MTU of
+/// our link, and hands the size and outcome of every probe to `on_probe`.
+///
+/// Returns `None` if no size got a reply, or once the target turns out unreachable.
+fn discover(
+    transport: &Arc<dyn Transport>,
+    target: [u8; 4],
+    link_mtu: usize,
+    timeout: Duration,
+    probe_options: &PingerOptions,
+    mut on_probe: impl FnMut(usize, &Probe),
+) -> Option<usize> {
+    // Largest size known to fit, and smallest size known not to.
+    let mut fits = None;
+    let mut too_large = link_mtu + 1;
+    let mut size = link_mtu;
+    // Whether the size is an MTU a router reported, which ends the search if it fits.
+    let mut reported = false;
+    loop {
+        let outcome = probe(transport, target, size, timeout, probe_options);
+        on_probe(size, &outcome);
+        match outcome {
+            Probe::Fits => {
+                fits = Some(size);
+                if reported {
+                    break;
+                }
+            }
+            Probe::TooLarge(mtu) => {
+                too_large = size;
+                // Trust a plausible reported MTU, it saves the search.
+                if (MIN_MTU..size).contains(&mtu) && fits.is_none_or(|fits| mtu > fits) {
+                    size = mtu;
+                    reported = true;
+                    continue;
+                }
+            }
+            Probe::Lost => {
+                too_large = size;
+                reported = false;
+            }
+            Probe::Unreachable(_) => return None,
+        }
+
+        let low = fits.unwrap_or(MIN_MTU - 1);
+        if too_large - low <= 1 {
+            break;
+        }
+        size = (low + too_large) / 2;
+    }
+    fits
+}
+
+/// Builds the record of a probe: its size and outcome, with the MTU a router reported
+/// or the reason the target is unreachable.
+fn probe_record(target: &str, size: usize, outcome: &Probe) -> Record {
+    let (status, next_hop_mtu, reason) = match outcome {
+        Probe::Fits => ("reply", None, None),
+        Probe::TooLarge(mtu) => ("fragmentation_needed", Some(*mtu), None),
+        Probe::Lost => ("timeout", None, None),
+        Probe::Unreachable(reason) => ("unreachable", None, Some(reason.clone())),
+    };
+    Record::new("event")
+        .field("target", target)
+        .field("size", size)
+        .field("status", status)
+        .field("next_hop_mtu", next_hop_mtu)
+        .field("reason", reason)
+}
+
+/// Sends one probe of `size` bytes with Don't Fragment set and waits for its outcome.
+fn probe(
+    transport: &Arc<dyn Transport>,
+    target: [u8; 4],
+    size: usize,
+    timeout: Duration,
+    probe_options: &PingerOptions,
+) -> Probe {
+    let options = PingerOptions {
+        count: Some(1),
+        timeout,
+        payload_size: Some(size - HEADERS_LEN),
+        dont_fragment: true,
+        ..probe_options.clone()
+    };
+    let mut pinger = match Pinger::new(Arc::clone(transport), options) {
+        Ok(pinger) => pinger,
+        Err(e) => return Probe::Unreachable(format!("failed to create pinger: {}", e)),
+    };
+    pinger.add_target(target);
+
+    let mut outcome = Probe::Lost;
+    let result = pinger.run(|event| {
+        outcome = match event {
+            PingEvent::Reply { .. } => Probe::Fits,
+            PingEvent::Unreachable {
+                next_hop_mtu: Some(mtu),
+                ..
+            } => Probe::TooLarge(mtu as usize),
+            PingEvent::Unreachable { from, code, .. } => Probe::Unreachable(format!(
+                "{} reported {} unreachable (code {})",
+                format_ipv4_address(&from),
+                format_ipv4_address(&target),
+                code
+            )),
+            PingEvent::TtlExceeded { from, .. } => {
+                Probe::Unreachable(format!("time to live exceeded at {}", format_ipv4_address(&from)))
+            }
+            PingEvent::Timeout { .. } => Probe::Lost,
+            PingEvent::SendFailed { error, .. } => Probe::Unreachable(format!("send failed: {}", error)),
+            // The reply it repeats already settled the probe.
+            PingEvent::Duplicate { .. } => return,
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Receive failed: {}", e);
+    }
+    outcome
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_network::transport::simulated::SimulatedNetwork;
+
+    /// Discovers the path MTU to a host of the lab network, returning it, the size and
+    /// outcome of every probe, and the virtual time the search took.
+    fn discover_lab(target: [u8; 4]) -> (Option<usize>, Vec<(usize, String)>, Duration) {
+        let network = Arc::new(SimulatedNetwork::lab(1));
+        let transport: Arc<dyn Transport> = network.clone();
+        let probe_options = PingerOptions {
+            source: [10, 0, 0, 2],
+            ..PingerOptions::default()
+        };
+        let start = network.now();
+        let mut probes = Vec::new();
+        let timeout = Duration::from_secs(1);
+        let path_mtu = discover(&transport, target, DEFAULT_MTU, timeout, &probe_options, |size, outcome| {
+            let outcome = match outcome {
+                Probe::Fits => "fits".to_string(),
+                Probe::TooLarge(mtu) => format!("too large, {}", mtu),
+                Probe::Lost => "lost".to_string(),
+                Probe::Unreachable(_) => "unreachable".to_string(),
+            };
+            probes.push((size, outcome));
+        });
+        (path_mtu, probes, network.now() - start)
+    }
+
+    #[test]
+    fn reported_mtu_ends_the_search() {
+        let (path_mtu, probes, elapsed) = discover_lab([10, 0, 1, 10]);
+
+        assert_eq!(path_mtu, Some(1400));
+        assert_eq!(probes, [(1500, "too large, 1400".to_string()), (1400, "fits".to_string())]);
+        // Two round trips to the core router, no timeout.
+        assert!(elapsed < Duration::from_millis(50), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn silent_target_is_searched_down_to_the_minimum() {
+        let (path_mtu, probes, elapsed) = discover_lab([203, 0, 113, 11]);
+
+        assert_eq!(path_mtu, None);
+        // The core router still reports its MTU, then the target stays silent at every size.
+        assert_eq!(probes[0], (1500, "too large, 1400".to_string()));
+        assert!(probes[1..].iter().all(|(_, outcome)| outcome == "lost"));
+        assert_eq!(probes.last().unwrap().0, MIN_MTU);
+        // Every lost probe waits out its timeout of a second on the virtual clock.
+        let lost = probes.len() as u64 - 1;
+        assert!(elapsed >= Duration::from_secs(lost) && elapsed < Duration::from_secs(lost + 1), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn unreachable_target_stops_the_search() {
+        let (path_mtu, probes, _) = discover_lab([203, 0, 113, 99]);
+
+        assert_eq!(path_mtu, None);
+        assert_eq!(probes, [(1500, "too large, 1400".to_string()), (1400, "unreachable".to_string())]);
+    }
+}
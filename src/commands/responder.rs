@@ -0,0 +1,97 @@
+//! ICMP echo responder.
+//!
+//! The other side of ping: answers ARP requests for the configured addresses and
+//! ICMP echo requests sent to them, so a TAP device or a spare interface can stand
+//! in for a host in lab setups and when testing the other commands.
+
+use rust_network::arp::{ARPPacket, ARP_REQUEST};
+use rust_network::ethernet_frame::EthernetFrame;
+use rust_network::ipv4::fields::IpProtocol;
+use rust_network::ipv4::icmp::{ICMPPacket, ECHO_REQUEST};
+use rust_network::ipv4::internet_packet::{format_ipv4_address, IPV4};
+use rust_network::transport::{Layer, Transport};
+
+use std::time::Duration;
+
+/// TTL of the echo replies.
+const REPLY_TTL: u8 = 64;
+
+/// Read timeout of the receive loop.
+const RECV_POLL: Duration = Duration::from_secs(1);
+
+/// Answers ARP and ICMP echo requests for the given addresses until interrupted.
+///
+/// # Arguments
+/// * `transport` - Transport to listen on; ARP is only answered on link layer transports.
+/// * `addresses` - Addresses to answer for.
+pub(crate) fn responder(transport: &dyn Transport, addresses: &[[u8; 4]]) {
+    let mac = transport.mac_address();
+    if transport.layer() == Layer::Link && mac.is_none() {
+        eprintln!("Interface {} has no MAC address", transport.name());
+        return;
+    }
+    let listed: Vec<String> = addresses.iter().map(format_ipv4_address).collect();
+    match mac {
+        Some(mac) => println!("Answering for {} at {} on {}", listed.join(", "), mac, transport.name()),
+        None => println!("Answering for {} on {}", listed.join(", "), transport.name()),
+    }
+
+    loop {
+        let request = match transport.recv_frame(RECV_POLL) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Receive failed: {}", e);
+                return;
+            }
+        };
+
+        let reply = if let Some(arp) = request.arp() {
+            let mac = match mac {
+                Some(mac) if arp.sender_hardware_address != mac => mac,
+                _ => continue,
+            };
+            if arp.operation != ARP_REQUEST || !addresses.contains(&arp.target_protocol_address) {
+                continue;
+            }
+            println!(
+                "ARP reply to {} [{}]: {} is at {}",
+                format_ipv4_address(&arp.sender_protocol_address),
+                arp.sender_hardware_address,
+                format_ipv4_address(&arp.target_protocol_address),
+                mac
+            );
+            EthernetFrame::new_arp(ARPPacket::new_reply(arp, mac), arp.sender_hardware_address)
+        } else if let Some(packet) = request.ipv4() {
+            if packet.protocol != IpProtocol::ICMP
+                || packet.datagram.packet_type != ECHO_REQUEST
+                || !addresses.contains(&packet.destination_add)
+            {
+                continue;
+            }
+            println!(
+                "Echo reply to {}: icmp_seq={} bytes={}",
+                format_ipv4_address(&packet.source_add),
+                packet.datagram.sequence,
+                packet.datagram.data.len() + 8
+            );
+            let echo_reply = ICMPPacket::new_echo_reply(&packet.datagram);
+            let ipv4_packet = IPV4::new_icmp_from_ip(echo_reply, REPLY_TTL, packet.destination_add, packet.source_add);
+            let mut frame = EthernetFrame::new_ether(ipv4_packet);
+            // Answer straight to the sender, rather than through ARP, as hosts do for replies.
+            frame.destination_address = request.source_address;
+            if let Some(mac) = mac {
+                frame.source_address = mac;
+            }
+            frame
+        } else {
+            continue;
+        };
+
+        // Replies go out on the VLAN the request came in on.
+        let mut reply = reply.with_vlan_tags(request.vlan_tags.clone());
+        if let Err(e) = transport.send_frame(&mut reply) {
+            eprintln!("Failed to send reply: {}", e);
+        }
+    }
+}
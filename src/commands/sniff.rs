@@ -0,0 +1,179 @@
+//! Packet capture.
+//!
+//! Prints every frame received on the transport, one line each in the style of
+//! tcpdump, or the full layered decode with `-v`. Packets failing their IPv4, ICMP,
+//! UDP or TCP checksums are flagged and counted.
+
+use crate::cli::{next_value, parse_count, OutputFormat, Verbosity};
+use crate::output::{Output, Record};
+use rust_network::arp::{ARP_REPLY, ARP_REQUEST};
+use rust_network::ethernet_frame::{EtherPayload, EthernetFrame};
+use rust_network::interface::lookup_interface;
+use rust_network::ipv4::fields::IpProtocol;
+use rust_network::ipv4::icmp::{DESTINATION_UNREACHABLE, ECHO_REPLY, ECHO_REQUEST, TIME_EXCEEDED};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::transport::{Layer, Transport};
+
+use std::time::Duration;
+
+/// Read timeout of the capture loop.
+const RECV_POLL: Duration = Duration::from_secs(1);
+
+/// Parses the sniff options from the command line arguments following `sniff`, the packet count.
+///
+/// Usage: `[-c count]`
+pub(crate) fn count_from_args(args: &[String]) -> Result<Option<u32>, String> {
+    let mut count = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => count = Some(parse_count(next_value(&mut args, arg)?)?),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+    Ok(count)
+}
+
+/// Prints the frames received on the transport.
+///
+/// # Arguments
+/// * `transport` - Transport to capture on.
+/// * `count` - Number of frames to capture, `None` to run until interrupted.
+/// * `output` - `-v` prints full decodes, `-q` only the final count. Records hold the one-line
+///   summary of each frame, and its full decode in JSON with the `serde` feature.
+pub(crate) fn sniff(transport: &dyn Transport, count: Option<u32>, output: &mut Output) {
+    if output.is_text() {
+        println!("listening on {}", transport.name());
+    }
+    // Packets we send may be captured before the network card fills in their checksums.
+    let local = transport
+        .source_address()
+        .or_else(|| lookup_interface(transport.name()).and_then(|interface| interface.ipv4));
+    let mut captured = 0;
+    let mut bad_checksums: u32 = 0;
+    while count.is_none_or(|count| captured < count) {
+        let frame = match transport.recv_frame(RECV_POLL) {
+            Ok(Some(frame)) => frame,
+            Ok(None) => continue,
+            Err(e) => {
+                eprintln!("Receive failed: {}", e);
+                break;
+            }
+        };
+        captured += 1;
+        let valid = checksums_valid(&frame, local);
+        if !valid {
+            bad_checksums += 1;
+        }
+        if !output.is_text() {
+            let record = frame_record("packet", &frame, transport.layer(), output).field("checksums_valid", valid);
+            output.event(record);
+            continue;
+        }
+        match output.verbosity {
+            Verbosity::Quiet => {}
+            Verbosity::Normal if valid => println!("{}", summarize(&frame, transport.layer())),
+            Verbosity::Normal => println!("{} [bad checksum]", summarize(&frame, transport.layer())),
+            Verbosity::Verbose => println!("{}", frame),
+        }
+    }
+    if output.is_text() {
+        match bad_checksums {
+            0 => println!("{} packets captured", captured),
+            _ => println!("{} packets captured, {} with bad checksums", captured, bad_checksums),
+        }
+    } else {
+        output.summary(
+            Record::new("summary")
+                .field("interface", transport.name())
+                .field("captured", captured)
+                .field("bad_checksums", bad_checksums),
+        );
+        output.finish();
+    }
+}
+
+/// Checks the IPv4 header and payload checksums of a frame, if it carries an IPv4 packet.
+///
+/// Checksums left for the network card to fill in are fine on packets sent from `local`.
+fn checksums_valid(frame: &EthernetFrame, local: Option<[u8; 4]>) -> bool {
+    match frame.ipv4() {
+        Some(packet) => {
+            let sent_locally = local == Some(packet.source_add);
+            !packet.header_checksum_status().is_bad(sent_locally)
+                && !packet.payload_checksum_status().is_bad(sent_locally)
+        }
+        None => true,
+    }
+}
+
+/// Builds the record of a frame, of the given type: its one-line summary, and its
+/// full decode as JSON with the `serde` feature, except in CSV.
+pub(crate) fn frame_record(record_type: &'static str, frame: &EthernetFrame, layer: Layer, output: &Output) -> Record {
+    let record = Record::new(record_type).field("summary", summarize(frame, layer));
+    if output.format == OutputFormat::Csv {
+        return record;
+    }
+    #[cfg(feature = "serde")]
+    if let Ok(json) = serde_json::to_string(frame) {
+        return record.field("frame", crate::output::Value::Json(json));
+    }
+    record
+}
+
+/// Describes a frame in one line, e.g. `IP 10.0.0.2 > 10.0.0.1: ICMP echo request, id 7, seq 1, ttl 64, length 84`.
+///
+/// The MAC addresses are left out on network layer transports, whose frames have none.
+pub(crate) fn summarize(frame: &EthernetFrame, layer: Layer) -> String {
+    let mut line = String::new();
+    if layer == Layer::Link {
+        line += &format!("{} > {}, ", frame.source_address, frame.destination_address);
+    }
+    for tag in frame.vlan_tags.iter() {
+        line += &format!("vlan {}, ", tag.vid);
+    }
+
+    match &frame.packet {
+        EtherPayload::Ipv4(packet) => {
+            line += &format!(
+                "IP {} > {}: ",
+                format_ipv4_address(&packet.source_add),
+                format_ipv4_address(&packet.destination_add)
+            );
+            if packet.protocol == IpProtocol::ICMP {
+                let icmp = &packet.datagram;
+                line += &match icmp.packet_type {
+                    ECHO_REQUEST | ECHO_REPLY => format!(
+                        "ICMP echo {}, id {}, seq {}",
+                        if icmp.packet_type == ECHO_REQUEST { "request" } else { "reply" },
+                        icmp.identifier,
+                        icmp.sequence
+                    ),
+                    DESTINATION_UNREACHABLE => format!("ICMP destination unreachable, code {}", icmp.code),
+                    TIME_EXCEEDED => "ICMP time exceeded in-transit".to_string(),
+                    packet_type => format!("ICMP type {}, code {}", packet_type, icmp.code),
+                };
+            } else {
+                line += packet.protocol.name().unwrap_or("unknown protocol");
+            }
+            line += &format!(", ttl {}, length {}", packet.ttl, packet.total_length);
+        }
+        EtherPayload::Arp(arp) => {
+            line += &match arp.operation {
+                ARP_REQUEST => format!(
+                    "ARP, Request who-has {} tell {}",
+                    format_ipv4_address(&arp.target_protocol_address),
+                    format_ipv4_address(&arp.sender_protocol_address)
+                ),
+                ARP_REPLY => format!(
+                    "ARP, Reply {} is-at {}",
+                    format_ipv4_address(&arp.sender_protocol_address),
+                    arp.sender_hardware_address
+                ),
+                operation => format!("ARP, operation {}", operation),
+            };
+        }
+        EtherPayload::Unknown(bytes) => line += &format!("{}, length {}", frame.ether_type, bytes.len()),
+    }
+    line
+}
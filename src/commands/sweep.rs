@@ -0,0 +1,143 @@
+//! Subnet ping sweep.
+//!
+//! Sends one ICMP echo request to every host address of a CIDR range and reports
+//! the hosts that answered. The pinger engine paces the probes and matches the
+//! replies, so the number of probes in flight is not tied to the number of threads.
+
+use crate::output::{event_record, Output, Record};
+use rust_network::dns::Resolver;
+use rust_network::interface::route::next_hop;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::ipv4::subnet::{parse_cidr, MIN_SCAN_PREFIX};
+use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+use rust_network::transport::{Layer, Transport};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Probes sent per second.
+const PROBES_PER_SECOND: u32 = 500;
+
+/// How long to wait for the reply of each probe.
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Hosts that replied, with the round trip time of their reply.
+type AliveHosts = Vec<([u8; 4], Duration)>;
+
+/// Sweeps the given CIDR range and prints the hosts that replied.
+///
+/// # Arguments
+/// * `transport` - Transport to send the echo requests on.
+/// * `cidr` - Range to sweep, e.g. `192.168.1.0/24`.
+/// * `source_ip` - Source address of the echo requests, by default that of the route to the range.
+/// * `resolver` - Resolver naming the hosts that replied in the summary.
+/// * `output` - Output of the probes and the summary.
+pub(crate) fn sweep(
+    transport: Arc<dyn Transport>,
+    cidr: &str,
+    source_ip: Option<[u8; 4]>,
+    resolver: &Resolver,
+    output: &mut Output,
+) {
+    let hosts = match parse_cidr(cidr) {
+        Some(hosts) => hosts,
+        None => {
+            eprintln!(
+                "Invalid CIDR range: {}, give an address with a prefix of /{} or longer",
+                cidr, MIN_SCAN_PREFIX
+            );
+            return;
+        }
+    };
+    let route = hosts
+        .first()
+        .and_then(|host| next_hop(*host))
+        .filter(|route| transport.layer() != Layer::Link || route.interface.name == transport.name());
+    let source_ip_add = match source_ip.or(route.map(|route| route.source)) {
+        Some(source_ip_add) => source_ip_add,
+        None => {
+            eprintln!("No route to {}, pick a source address with --source", cidr);
+            return;
+        }
+    };
+    if output.is_text() {
+        println!("Sweeping {} ({} hosts)", cidr, hosts.len());
+    }
+
+    let options = PingerOptions {
+        source: source_ip_add,
+        count: Some(1),
+        rate: PROBES_PER_SECOND,
+        timeout: REPLY_TIMEOUT,
+        ..PingerOptions::default()
+    };
+    let mut pinger = match Pinger::new(transport, options) {
+        Ok(pinger) => pinger,
+        Err(e) => {
+            eprintln!("Failed to create pinger: {}", e);
+            return;
+        }
+    };
+    for host in hosts.iter() {
+        pinger.add_target(*host);
+    }
+
+    let mut alive: AliveHosts = Vec::new();
+    let result = pinger.run(|event| {
+        let target = event.target();
+        if !output.is_text() {
+            output.event(event_record(&event));
+            if let PingEvent::Reply { rtt, .. } = event {
+                alive.push((target, rtt));
+            }
+            return;
+        }
+        match event {
+            PingEvent::Reply { rtt, .. } => {
+                println!(
+                    "{} is alive, time={:.3} ms",
+                    format_ipv4_address(&target),
+                    rtt.as_secs_f64() * 1000.0
+                );
+                alive.push((target, rtt));
+            }
+            PingEvent::Unreachable { from, .. } => println!(
+                "{} is unreachable, reported by {}",
+                format_ipv4_address(&target),
+                format_ipv4_address(&from)
+            ),
+            PingEvent::SendFailed { error, .. } => {
+                eprintln!("Failed to send to {}: {}", format_ipv4_address(&target), error)
+            }
+            _ => {}
+        }
+    });
+    if let Err(e) = result {
+        eprintln!("Receive failed: {}", e);
+    }
+
+    alive.sort_by_key(|(ip, _)| *ip);
+    if !output.is_text() {
+        output.summary(
+            Record::new("summary")
+                .field("range", cidr)
+                .field("hosts", hosts.len())
+                .field("alive", alive.len()),
+        );
+        output.finish();
+        return;
+    }
+    println!("\n--- {} sweep summary ---", cidr);
+    for (ip, rtt) in alive.iter() {
+        match resolver.reverse(*ip) {
+            Some(name) => println!(
+                "{:<15} {:.3} ms  {}",
+                format_ipv4_address(ip),
+                rtt.as_secs_f64() * 1000.0,
+                name
+            ),
+            None => println!("{:<15} {:.3} ms", format_ipv4_address(ip), rtt.as_secs_f64() * 1000.0),
+        }
+    }
+    println!("{} of {} hosts alive", alive.len(), hosts.len());
+}
@@ -0,0 +1,229 @@
+//! Traceroute over ICMP echo requests.
+//!
+//! Sends echo requests with increasing TTLs. The router where the TTL of a probe
+//! runs out drops it and answers with Time Exceeded, naming itself, so every hop
+//! of the path shows up in turn until the target answers the echo request.
+
+use crate::cli::{next_value, parse_count, parse_seconds};
+use crate::output::{event_record, Output, Record};
+use rust_network::dns::Resolver;
+use rust_network::ipv4::icmp::CODE_HOST_UNREACHABLE;
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+use rust_network::transport::Transport;
+
+use std::io;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Options of a traceroute.
+pub(crate) struct TracerouteOptions {
+    /// Host to trace the path to, a name or an address.
+    pub(crate) target: String,
+    /// Largest TTL to try.
+    pub(crate) max_hops: u8,
+    /// Probes sent per hop.
+    pub(crate) probes: u32,
+    /// How long to wait for the answer to a probe.
+    pub(crate) timeout: Duration,
+}
+
+impl TracerouteOptions {
+    /// Parses the traceroute options from the command line arguments following `traceroute`.
+    ///
+    /// Usage: `<host> [-m hops] [--probes count] [-W seconds]`
+    pub(crate) fn from_args(args: &[String]) -> Result<TracerouteOptions, String> {
+        let mut target = None;
+        let mut options = TracerouteOptions {
+            target: String::new(),
+            max_hops: 30,
+            probes: 3,
+            timeout: Duration::from_secs(2),
+        };
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "-m" => {
+                    let hops = next_value(&mut args, arg)?;
+                    options.max_hops = match hops.parse() {
+                        Ok(hops) if hops > 0 => hops,
+                        _ => return Err(format!("Invalid hop count: {}", hops)),
+                    };
+                }
+                "--probes" => options.probes = parse_count(next_value(&mut args, arg)?)?,
+                "-W" => options.timeout = parse_seconds(next_value(&mut args, arg)?)?,
+                _ if target.is_none() => target = Some(arg.clone()),
+                _ => return Err(format!("Unexpected argument: {}", arg)),
+            }
+        }
+
+        options.target = target.ok_or("Missing target host")?;
+        Ok(options)
+    }
+}
+
+/// Traces the path to a target, printing one line per hop.
+///
+/// # Arguments
+/// * `transport` - Transport to send the probes on.
+/// * `target` - Address to trace the path to.
+/// * `options` - Options of the traceroute.
+/// * `probe_options` - Source, next hop and VLAN stack of the probes; TTL and count are set per hop.
+/// * `resolver` - Resolver naming the routers.
+/// * `output` - Output of the hops, or of a record per probe and a summary.
+pub(crate) fn traceroute(
+    transport: Arc<dyn Transport>,
+    target: [u8; 4],
+    options: &TracerouteOptions,
+    probe_options: PingerOptions,
+    resolver: &Resolver,
+    output: &mut Output,
+) {
+    if output.is_text() {
+        println!(
+            "traceroute to {} ({}), {} hops max",
+            options.target,
+            format_ipv4_address(&target),
+            options.max_hops
+        );
+    }
+
+    let mut hops = 0;
+    let mut reached = false;
+    for ttl in 1..=options.max_hops {
+        hops = ttl;
+        let answers = match probe_hop(&transport, target, ttl, options, &probe_options) {
+            Ok(answers) => answers,
+            Err(e) => {
+                eprintln!("Receive failed: {}", e);
+                break;
+            }
+        };
+
+        let mut line = format!("{:>2} ", ttl);
+        let mut last_from = None;
+        for event in answers.iter() {
+            if !output.is_text() {
+                output.event(event_record(event).field("hop", ttl));
+                reached |= matches!(event, PingEvent::Reply { .. } | PingEvent::Unreachable { .. });
+                continue;
+            }
+            let (from, rtt, mark) = match *event {
+                PingEvent::Timeout { .. } => {
+                    line += " *";
+                    continue;
+                }
+                PingEvent::SendFailed { ref error, .. } => {
+                    eprintln!("traceroute: send failed at hop {}: {}", ttl, error);
+                    line += " *";
+                    continue;
+                }
+                PingEvent::Duplicate { .. } => continue,
+                PingEvent::Reply { rtt, .. } => {
+                    reached = true;
+                    (target, rtt, "")
+                }
+                PingEvent::TtlExceeded { from, rtt, .. } => (from, rtt, ""),
+                PingEvent::Unreachable { from, rtt, code, .. } => {
+                    reached = true;
+                    (from, rtt, if code == CODE_HOST_UNREACHABLE { " !H" } else { " !X" })
+                }
+            };
+            if last_from != Some(from) {
+                match resolver.reverse(from) {
+                    Some(name) => line += &format!(" {} ({})", name, format_ipv4_address(&from)),
+                    None => line += &format!(" {}", format_ipv4_address(&from)),
+                }
+                last_from = Some(from);
+            }
+            line += &format!("  {:.3} ms{}", rtt.as_secs_f64() * 1000.0, mark);
+        }
+        if output.is_text() {
+            println!("{}", line);
+        }
+
+        if reached {
+            break;
+        }
+    }
+
+    if !output.is_text() {
+        output.summary(
+            Record::new("summary")
+                .field("target", options.target.clone())
+                .field("hops", hops)
+                .field("reached", reached),
+        );
+        output.finish();
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Sends the probes of one hop, with the given TTL, and returns their events in the
+/// order the probes were sent.
+fn probe_hop(
+    transport: &Arc<dyn Transport>,
+    target: [u8; 4],
+    ttl: u8,
+    options: &TracerouteOptions,
+    probe_options: &PingerOptions,
+) -> io::Result<Vec<PingEvent>> {
+    let hop_options = PingerOptions {
+        ttl,
+        count: Some(options.probes),
+        interval: Duration::ZERO,
+        timeout: options.timeout,
+        ..probe_options.clone()
+    };
+    let mut pinger = Pinger::new(Arc::clone(transport), hop_options)?;
+    pinger.add_target(target);
+
+    let mut answers = Vec::new();
+    pinger.run(|event| answers.push(event))?;
+    answers.sort_by_key(PingEvent::sequence);
+    Ok(answers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rust_network::transport::simulated::SimulatedNetwork;
+
+    #[test]
+    fn every_router_answers_its_hop_until_the_target() {
+        let transport: Arc<dyn Transport> = Arc::new(SimulatedNetwork::lab(7));
+        let options = TracerouteOptions::from_args(&["203.0.113.10".to_string()]).unwrap();
+        let probe_options = PingerOptions {
+            source: [10, 0, 0, 2],
+            ..PingerOptions::default()
+        };
+        let target = [203, 0, 113, 10];
+
+        let routers = [[10, 0, 0, 1], [192, 0, 2, 1], [198, 51, 100, 1]];
+        for (ttl, router) in (1..).zip(routers) {
+            let answers = probe_hop(&transport, target, ttl, &options, &probe_options).unwrap();
+            let sequences: Vec<u64> = answers.iter().map(PingEvent::sequence).collect();
+            assert_eq!(sequences, [0, 1, 2]);
+            for answer in answers.iter() {
+                match *answer {
+                    PingEvent::TtlExceeded { from, rtt, .. } => {
+                        assert_eq!(from, router);
+                        // The gateway is 300 µs away, the core router 5 ms further.
+                        match ttl {
+                            1 => assert!(rtt < Duration::from_millis(1), "{:?}", rtt),
+                            _ => assert!(rtt > Duration::from_millis(10), "{:?}", rtt),
+                        }
+                    }
+                    // Probes past the core router cross the lossy WAN link.
+                    PingEvent::Timeout { .. } if ttl == 3 => {}
+                    _ => panic!("unexpected answer at hop {}: {:?}", ttl, answer),
+                }
+            }
+        }
+
+        let answers = probe_hop(&transport, target, 4, &options, &probe_options).unwrap();
+        assert!(answers.iter().any(|answer| matches!(answer, PingEvent::Reply { ttl: 61, .. })));
+    }
+}
@@ -0,0 +1,451 @@
+//! Module for resolving host names.
+//!
+//! Names go through the system resolver by default, or through a small DNS client
+//! speaking the wire protocol over UDP straight to a given server, which shows the
+//! answers of that server regardless of the local resolver configuration.
+
+extern crate libc;
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::Duration;
+
+/// Type of an A record, an IPv4 address.
+pub const TYPE_A: u16 = 1;
+/// Type of a PTR record, the name of an address.
+pub const TYPE_PTR: u16 = 12;
+/// Type of an AAAA record, an IPv6 address.
+pub const TYPE_AAAA: u16 = 28;
+
+/// Class of Internet records.
+const CLASS_IN: u16 = 1;
+
+/// Largest DNS message over UDP without EDNS.
+const MAX_MESSAGE_LEN: usize = 512;
+
+/// Record of a DNS answer.
+#[derive(Debug)]
+pub enum Record {
+    /// IPv4 address.
+    A([u8; 4]),
+    /// IPv6 address.
+    Aaaa([u8; 16]),
+    /// Name of an address.
+    Ptr(String),
+}
+
+/// Represents a DNS client sending queries over UDP.
+pub struct DnsClient {
+    /// Server to query, on port 53.
+    server: SocketAddr,
+    /// How long to wait for an answer.
+    timeout: Duration,
+}
+
+impl DnsClient {
+    /// Creates a client querying the given server.
+    pub fn new(server: [u8; 4]) -> DnsClient {
+        DnsClient {
+            server: SocketAddr::from((server, 53)),
+            timeout: Duration::from_secs(2),
+        }
+    }
+
+    /// Sends a query and returns the records of the answer with the queried type.
+    ///
+    /// # Arguments
+    /// * `name` - Name to query, e.g. `example.com`.
+    /// * `record_type` - `TYPE_A`, `TYPE_AAAA` or `TYPE_PTR`.
+    pub fn query(&self, name: &str, record_type: u16) -> io::Result<Vec<Record>> {
+        let socket = UdpSocket::bind(("0.0.0.0", 0))?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        socket.connect(self.server)?;
+
+        // A random id, with the random source port, keeps off-path spoofers from guessing the answer.
+        let id = random_id()?;
+        socket.send(&build_query(id, name, record_type)?)?;
+
+        let mut buffer = [0; MAX_MESSAGE_LEN];
+        loop {
+            let len = socket.recv(&mut buffer)?;
+            // Answers to other queries, e.g. late ones, are not ours.
+            if len >= 2 && u16::from_be_bytes([buffer[0], buffer[1]]) == id {
+                return parse_response(&buffer[..len], record_type);
+            }
+        }
+    }
+
+    /// Resolves a name into its IPv4 addresses.
+    pub fn lookup_ipv4(&self, name: &str) -> io::Result<Vec<[u8; 4]>> {
+        let records = self.query(name, TYPE_A)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::A(address) => Some(address),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Resolves a name into its IPv6 addresses.
+    pub fn lookup_ipv6(&self, name: &str) -> io::Result<Vec<[u8; 16]>> {
+        let records = self.query(name, TYPE_AAAA)?;
+        Ok(records
+            .into_iter()
+            .filter_map(|record| match record {
+                Record::Aaaa(address) => Some(address),
+                _ => None,
+            })
+            .collect())
+    }
+
+    /// Looks up the name of an IPv4 address in `in-addr.arpa`.
+    pub fn reverse(&self, address: [u8; 4]) -> io::Result<Option<String>> {
+        let name = format!(
+            "{}.{}.{}.{}.in-addr.arpa",
+            address[3], address[2], address[1], address[0]
+        );
+        let records = self.query(&name, TYPE_PTR)?;
+        Ok(records.into_iter().find_map(|record| match record {
+            Record::Ptr(name) => Some(name),
+            _ => None,
+        }))
+    }
+}
+
+/// How the commands turn names into addresses and back.
+pub enum Resolver {
+    /// The system resolver, following `/etc/hosts` and the configured servers.
+    System,
+    /// Our own DNS client, querying the given server.
+    Native(DnsClient),
+    /// No resolution, with `-n`: targets must be addresses and replies show addresses.
+    Disabled,
+}
+
+impl Resolver {
+    /// Resolves a name into all its IPv4 and IPv6 addresses.
+    pub fn lookup(&self, name: &str) -> Result<Vec<IpAddr>, String> {
+        let addresses = match self {
+            Resolver::System => (name, 0)
+                .to_socket_addrs()
+                .map(|addresses| addresses.map(|address| address.ip()).collect()),
+            Resolver::Native(client) => client.lookup_ipv4(name).and_then(|ipv4| {
+                let ipv6 = client.lookup_ipv6(name)?;
+                Ok(ipv4
+                    .into_iter()
+                    .map(IpAddr::from)
+                    .chain(ipv6.into_iter().map(IpAddr::from))
+                    .collect())
+            }),
+            Resolver::Disabled => return Err(format!("{} is not an address and -n turns name resolution off", name)),
+        };
+        addresses.map_err(|e| format!("Cannot resolve {}: {}", name, e))
+    }
+
+    /// Resolves a target into the IPv4 address to use.
+    ///
+    /// Dotted quads are taken as they are; a name resolving to several addresses
+    /// uses the first IPv4 one.
+    pub fn resolve_ipv4(&self, target: &str) -> Result<[u8; 4], String> {
+        if let Ok(address) = target.parse::<Ipv4Addr>() {
+            return Ok(address.octets());
+        }
+        let addresses = match self {
+            // Only the A records are needed, so a failing AAAA query cannot lose them.
+            Resolver::Native(client) => client
+                .lookup_ipv4(target)
+                .map_err(|e| format!("Cannot resolve {}: {}", target, e))?
+                .into_iter()
+                .map(IpAddr::from)
+                .collect(),
+            _ => self.lookup(target)?,
+        };
+        addresses
+            .into_iter()
+            .find_map(|address| match address {
+                IpAddr::V4(address) => Some(address.octets()),
+                IpAddr::V6(_) => None,
+            })
+            .ok_or_else(|| format!("No IPv4 address for {}", target))
+    }
+
+    /// Looks up the name of an address, for display.
+    ///
+    /// Returns `None` if it has none, or if resolution is disabled.
+    pub fn reverse(&self, address: [u8; 4]) -> Option<String> {
+        match self {
+            Resolver::System => system_reverse(address),
+            Resolver::Native(client) => client.reverse(address).ok().flatten(),
+            Resolver::Disabled => None,
+        }
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Builds a recursive query for one name.
+fn build_query(id: u16, name: &str, record_type: u16) -> io::Result<Vec<u8>> {
+    let mut query = Vec::with_capacity(MAX_MESSAGE_LEN);
+    query.extend_from_slice(&id.to_be_bytes());
+    // Standard query with recursion desired.
+    query.extend_from_slice(&0x0100u16.to_be_bytes());
+    // One question, no answer, authority or additional records.
+    query.extend_from_slice(&[0, 1, 0, 0, 0, 0, 0, 0]);
+    for label in name.trim_end_matches('.').split('.') {
+        if label.is_empty() || label.len() > 63 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid name: {}", name),
+            ));
+        }
+        query.push(label.len() as u8);
+        query.extend_from_slice(label.as_bytes());
+    }
+    query.push(0);
+    query.extend_from_slice(&record_type.to_be_bytes());
+    query.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Ok(query)
+}
+
+/// Draws the id of a query from the system's random source.
+fn random_id() -> io::Result<u16> {
+    let mut id = [0; 2];
+    File::open("/dev/urandom")?.read_exact(&mut id)?;
+    Ok(u16::from_ne_bytes(id))
+}
+
+/// Parses a response, returning its answers of the queried type.
+fn parse_response(message: &[u8], record_type: u16) -> io::Result<Vec<Record>> {
+    let malformed = || io::Error::new(io::ErrorKind::InvalidData, "malformed DNS response");
+    // A query echoed back is no answer.
+    if message.len() < 12 || message[2] & 0x80 == 0 {
+        return Err(malformed());
+    }
+    // Without TCP to fetch the rest, a truncated answer would pass for a complete one.
+    if message[2] & 0x02 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "DNS response truncated, the answers do not fit in UDP",
+        ));
+    }
+    match message[3] & 0x0f {
+        0 => {}
+        3 => return Err(io::Error::new(io::ErrorKind::NotFound, "no such name")),
+        rcode => {
+            return Err(io::Error::other(format!("DNS server failed with code {}", rcode)));
+        }
+    }
+    let questions = u16::from_be_bytes([message[4], message[5]]);
+    let answers = u16::from_be_bytes([message[6], message[7]]);
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        offset = read_name(message, offset).ok_or_else(malformed)?.1 + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        offset = read_name(message, offset).ok_or_else(malformed)?.1;
+        let header = message.get(offset..offset + 10).ok_or_else(malformed)?;
+        let answer_type = u16::from_be_bytes([header[0], header[1]]);
+        let data_len = u16::from_be_bytes([header[8], header[9]]) as usize;
+        let data_start = offset + 10;
+        let data = message.get(data_start..data_start + data_len).ok_or_else(malformed)?;
+        offset = data_start + data_len;
+
+        // CNAMEs leading to the answer, and other types, are skipped.
+        if answer_type != record_type {
+            continue;
+        }
+        match answer_type {
+            TYPE_A if data.len() == 4 => records.push(Record::A([data[0], data[1], data[2], data[3]])),
+            TYPE_AAAA if data.len() == 16 => {
+                let mut address = [0; 16];
+                address.copy_from_slice(data);
+                records.push(Record::Aaaa(address));
+            }
+            TYPE_PTR => {
+                let (name, _) = read_name(message, data_start).ok_or_else(malformed)?;
+                records.push(Record::Ptr(name));
+            }
+            _ => return Err(malformed()),
+        }
+    }
+    Ok(records)
+}
+
+/// Reads a possibly compressed name at `offset`.
+///
+/// Returns the name and the offset following it in the message.
+fn read_name(message: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end = None;
+    // Every pointer must go backwards, which rules out loops.
+    let mut limit = offset;
+    loop {
+        let len = *message.get(position)? as usize;
+        match len {
+            0 => {
+                return Some((labels.join("."), end.unwrap_or(position + 1)));
+            }
+            len if len & 0xc0 == 0xc0 => {
+                let pointer = (len & 0x3f) << 8 | *message.get(position + 1)? as usize;
+                if pointer >= limit {
+                    return None;
+                }
+                end.get_or_insert(position + 2);
+                limit = pointer;
+                position = pointer;
+            }
+            len => {
+                let label = message.get(position + 1..position + 1 + len)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                position += 1 + len;
+            }
+        }
+    }
+}
+
+/// Looks up the name of an address through the system resolver.
+fn system_reverse(address: [u8; 4]) -> Option<String> {
+    let socket_address = libc::sockaddr_in {
+        sin_family: libc::AF_INET as libc::sa_family_t,
+        sin_port: 0,
+        sin_addr: libc::in_addr {
+            s_addr: u32::from_ne_bytes(address),
+        },
+        ..unsafe { std::mem::zeroed() }
+    };
+    let mut host = [0 as libc::c_char; libc::NI_MAXHOST as usize];
+    let result = unsafe {
+        libc::getnameinfo(
+            &socket_address as *const libc::sockaddr_in as *const libc::sockaddr,
+            std::mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            host.as_mut_ptr(),
+            host.len() as libc::socklen_t,
+            std::ptr::null_mut(),
+            0,
+            libc::NI_NAMEREQD,
+        )
+    };
+    if result != 0 {
+        return None;
+    }
+    Some(unsafe { CStr::from_ptr(host.as_ptr()) }.to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Question of the test responses: `example.com`, type A, class IN, at offset 12.
+    const QUESTION: &[u8] = b"\x07example\x03com\x00\x00\x01\x00\x01";
+
+    /// Builds a response with the given flags, the test question and the given answers.
+    fn response(flags: u16, answers: &[&[u8]]) -> Vec<u8> {
+        let mut message = vec![0x12, 0x34];
+        message.extend_from_slice(&flags.to_be_bytes());
+        message.extend_from_slice(&[0, 1, 0, answers.len() as u8, 0, 0, 0, 0]);
+        message.extend_from_slice(QUESTION);
+        for answer in answers {
+            message.extend_from_slice(answer);
+        }
+        message
+    }
+
+    /// Builds an answer for the name at `name`, of the given type, with a TTL of 60.
+    fn answer(name: &[u8], record_type: u16, data: &[u8]) -> Vec<u8> {
+        let mut answer = name.to_vec();
+        answer.extend_from_slice(&record_type.to_be_bytes());
+        answer.extend_from_slice(&CLASS_IN.to_be_bytes());
+        answer.extend_from_slice(&60u32.to_be_bytes());
+        answer.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        answer.extend_from_slice(data);
+        answer
+    }
+
+    /// Pointer to the name of the question.
+    const TO_QUESTION: &[u8] = &[0xc0, 12];
+
+    #[test]
+    fn query_asks_for_one_name_recursively() {
+        let query = build_query(0x1234, "example.com.", TYPE_A).unwrap();
+        assert_eq!(query[..12], [0x12, 0x34, 0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(query[12..], *QUESTION);
+        assert!(build_query(1, "a..b", TYPE_A).is_err());
+        assert!(build_query(1, &"a".repeat(64), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn compressed_answers_are_read() {
+        // A CNAME to `www.example.com`, pointing into itself, then the A record of that name.
+        let cname = answer(TO_QUESTION, 5, b"\x03www\xc0\x0c");
+        let www = 12 + QUESTION.len() as u8 + 12;
+        let a = answer(&[0xc0, www], TYPE_A, &[93, 184, 216, 34]);
+        let message = response(0x8180, &[&cname, &a]);
+
+        assert_eq!(read_name(&message, www as usize), Some(("www.example.com".to_string(), www as usize + 6)));
+        let records = parse_response(&message, TYPE_A).unwrap();
+        assert!(matches!(records[..], [Record::A([93, 184, 216, 34])]));
+    }
+
+    #[test]
+    fn ptr_and_aaaa_answers_are_read() {
+        let ptr = answer(TO_QUESTION, TYPE_PTR, b"\x04host\xc0\x0c");
+        match &parse_response(&response(0x8180, &[&ptr]), TYPE_PTR).unwrap()[..] {
+            [Record::Ptr(name)] => assert_eq!(name, "host.example.com"),
+            records => panic!("expected a PTR record, got {:?}", records),
+        }
+
+        let aaaa = answer(TO_QUESTION, TYPE_AAAA, &[0x20, 0x01, 0x0d, 0xb8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+        let records = parse_response(&response(0x8180, &[&aaaa]), TYPE_AAAA).unwrap();
+        assert!(matches!(records[..], [Record::Aaaa([0x20, 0x01, 0x0d, 0xb8, ..])]));
+    }
+
+    #[test]
+    fn pointer_loops_are_rejected() {
+        let mut message = response(0x8180, &[]);
+        let end = message.len();
+        // A pointer to itself, and one to a later pointer back to it.
+        message.extend_from_slice(&[0xc0, end as u8, 0xc0, end as u8 + 4, 0xc0, end as u8 + 2]);
+        assert_eq!(read_name(&message, end), None);
+        assert_eq!(read_name(&message, end + 2), None);
+        assert_eq!(read_name(&message, end + 4), None);
+        // A pointer past the end of the message.
+        assert_eq!(read_name(&[0xc0, 0xff], 0), None);
+    }
+
+    #[test]
+    fn truncated_responses_are_malformed() {
+        let a = answer(TO_QUESTION, TYPE_A, &[192, 0, 2, 1]);
+        let message = response(0x8180, &[&a]);
+        assert_eq!(parse_response(&message, TYPE_A).unwrap().len(), 1);
+        for len in [0, 11, 20, message.len() - 12, message.len() - 1] {
+            let error = parse_response(&message[..len], TYPE_A).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData, "{} bytes", len);
+        }
+        // An A record of the wrong length.
+        let short = answer(TO_QUESTION, TYPE_A, &[192, 0, 2]);
+        assert!(parse_response(&response(0x8180, &[&short]), TYPE_A).is_err());
+    }
+
+    #[test]
+    fn flags_of_the_header_are_checked() {
+        let a = answer(TO_QUESTION, TYPE_A, &[192, 0, 2, 1]);
+        // A query rather than a response.
+        let error = parse_response(&response(0x0100, &[&a]), TYPE_A).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // Truncated, TC set.
+        let error = parse_response(&response(0x8380, &[&a]), TYPE_A).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        // No such name.
+        let error = parse_response(&response(0x8183, &[]), TYPE_A).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        // Server failure.
+        assert!(parse_response(&response(0x8182, &[]), TYPE_A).is_err());
+    }
+}
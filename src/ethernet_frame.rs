@@ -1,47 +1,226 @@
+use crate::arp::ARPPacket;
 use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
 
 use std::fmt;
 
+///EtherType of an Ethernet frame, identifying the protocol of its payload.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EtherType(pub u16);
+
+impl EtherType {
+    pub const IPV4: EtherType = EtherType(0x0800);
+    pub const ARP: EtherType = EtherType(0x0806);
+    pub const WAKE_ON_LAN: EtherType = EtherType(0x0842);
+    pub const RARP: EtherType = EtherType(0x8035);
+    pub const VLAN: EtherType = EtherType(0x8100);
+    pub const IPV6: EtherType = EtherType(0x86dd);
+    pub const MPLS: EtherType = EtherType(0x8847);
+    pub const PPPOE_DISCOVERY: EtherType = EtherType(0x8863);
+    pub const PPPOE_SESSION: EtherType = EtherType(0x8864);
+    pub const QINQ: EtherType = EtherType(0x88a8);
+    pub const LLDP: EtherType = EtherType(0x88cc);
+    pub const QINQ_LEGACY: EtherType = EtherType(0x9100);
+
+    ///Checks whether this EtherType announces a VLAN tag rather than a payload.
+    pub fn is_vlan_tag(&self) -> bool {
+        matches!(*self, EtherType::VLAN | EtherType::QINQ | EtherType::QINQ_LEGACY)
+    }
+
+    ///Name of the EtherType, if it is a known one.
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            EtherType::IPV4 => Some("IPv4"),
+            EtherType::ARP => Some("ARP"),
+            EtherType::WAKE_ON_LAN => Some("Wake-on-LAN"),
+            EtherType::RARP => Some("RARP"),
+            EtherType::VLAN => Some("802.1Q VLAN"),
+            EtherType::IPV6 => Some("IPv6"),
+            EtherType::MPLS => Some("MPLS"),
+            EtherType::PPPOE_DISCOVERY => Some("PPPoE Discovery"),
+            EtherType::PPPOE_SESSION => Some("PPPoE Session"),
+            EtherType::QINQ => Some("802.1ad QinQ"),
+            EtherType::LLDP => Some("LLDP"),
+            EtherType::QINQ_LEGACY => Some("QinQ (legacy)"),
+            _ => None,
+        }
+    }
+}
+
+///Implements the Display trait for EtherType, e.g. `IPv4 (0x0800)`.
+impl fmt::Display for EtherType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} (0x{:04x})", self.name().unwrap_or("Unknown"), self.0)
+    }
+}
+
+///Represents an 802.1Q or 802.1ad VLAN tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct VlanTag {
+    ///Tag protocol identifier, `EtherType::VLAN` for 802.1Q or `EtherType::QINQ` for an 802.1ad service tag.
+    pub tpid: EtherType,
+    ///Priority code point, 0 to 7.
+    pub pcp: u8,
+    ///Drop eligible indicator.
+    pub dei: bool,
+    ///VLAN identifier, 0 to 4095.
+    pub vid: u16,
+}
+
+impl VlanTag {
+    ///Creates a new 802.1Q customer tag with default priority.
+    pub fn new(vid: u16) -> VlanTag {
+        VlanTag {
+            tpid: EtherType::VLAN,
+            pcp: 0,
+            dei: false,
+            vid: vid & 0x0fff,
+        }
+    }
+
+    ///Creates a new 802.1ad service tag with default priority, the outer tag of a QinQ stack.
+    pub fn new_service(vid: u16) -> VlanTag {
+        VlanTag {
+            tpid: EtherType::QINQ,
+            ..VlanTag::new(vid)
+        }
+    }
+
+    ///Parses a VLAN stack such as `100` or `200.100`, outermost tag first.
+    ///
+    ///With more than one VLAN, all but the innermost tag are 802.1ad service tags.
+    pub fn parse_stack(s: &str) -> Option<Vec<VlanTag>> {
+        let vids: Vec<u16> = s
+            .split('.')
+            .map(|vid| vid.parse().ok().filter(|vid| *vid < 4096))
+            .collect::<Option<_>>()?;
+        let last = vids.len() - 1;
+        Some(
+            vids.iter()
+                .enumerate()
+                .map(|(i, vid)| if i < last { VlanTag::new_service(*vid) } else { VlanTag::new(*vid) })
+                .collect(),
+        )
+    }
+
+    ///Converts the tag into its 4 bytes on the wire: TPID, then the tag control information.
+    pub fn to_bytes(self) -> [u8; 4] {
+        let tci = (self.pcp as u16 & 0x7) << 13 | (self.dei as u16) << 12 | (self.vid & 0x0fff);
+        let tpid = self.tpid.0.to_be_bytes();
+        let tci = tci.to_be_bytes();
+        [tpid[0], tpid[1], tci[0], tci[1]]
+    }
+
+    ///Converts the tag control information that follows a TPID into a tag.
+    pub fn from_tci(tpid: EtherType, tci: u16) -> VlanTag {
+        VlanTag {
+            tpid,
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            vid: tci & 0x0fff,
+        }
+    }
+}
+
+///Payload carried by an Ethernet frame, selected by its EtherType.
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum EtherPayload {
+    ///IPv4 packet.
+    Ipv4(IPV4),
+    ///ARP packet.
+    Arp(ARPPacket),
+    ///Any other EtherType, kept as raw bytes.
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::hex"))]
+    Unknown(Vec<u8>),
+}
+
+impl EtherPayload {
+    ///Converts the payload into bytes for transmission.
+    pub fn to_bytes(&mut self) -> Vec<u8> {
+        match self {
+            EtherPayload::Ipv4(packet) => packet.to_bytes(),
+            EtherPayload::Arp(packet) => packet.to_bytes(),
+            EtherPayload::Unknown(bytes) => bytes.clone(),
+        }
+    }
+}
+
 ///Represents an Ethernet frame.
-pub(crate) struct EthernetFrame {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct EthernetFrame {
     ///Destination MAC address.
-    pub(crate) destination_address: [u8; 6],
+    pub destination_address: MacAddr,
     ///Source MAC address.
-    pub(crate) source_address: [u8; 6],
-    ///EtherType of the frame.
-    pub(crate) ether_type: [u8; 2],
-    ///Packet payload of the frame.(Support for IPv4 only)
-    pub(crate) packet: IPV4,
+    pub source_address: MacAddr,
+    ///VLAN tags of the frame, outermost first. Empty for untagged frames.
+    pub vlan_tags: Vec<VlanTag>,
+    ///EtherType of the payload, after any VLAN tags.
+    pub ether_type: EtherType,
+    ///Packet payload of the frame.
+    pub packet: EtherPayload,
 }
 
 impl EthernetFrame {
 
     ///Converts the Ethernet frame into bytes for transmission.
-    pub(crate) fn to_bytes(&mut self) -> Vec<u8> {
+    pub fn to_bytes(&mut self) -> Vec<u8> {
         let mut result = Vec::new();
-        result.append(&mut self.destination_address.to_vec());
-        result.append(&mut self.source_address.to_vec());
-        result.append(&mut self.ether_type.to_vec());
+        result.extend_from_slice(&self.destination_address.octets());
+        result.extend_from_slice(&self.source_address.octets());
+        for tag in self.vlan_tags.iter() {
+            result.extend_from_slice(&tag.to_bytes());
+        }
+        result.extend_from_slice(&self.ether_type.0.to_be_bytes());
         result.append(&mut self.packet.to_bytes());
-        return result;
+        result
     }
 
     ///Converts a byte array into an Ethernet frame.
-    pub(crate) fn from_bytes(bytes: &[u8]) -> EthernetFrame {
+    ///
+    ///Returns `None` if the bytes are too short to hold the Ethernet header. A payload that
+    ///cannot be decoded, or a VLAN tag cut short, is kept as `EtherPayload::Unknown`.
+    pub fn from_bytes(bytes: &[u8]) -> Option<EthernetFrame> {
+        if bytes.len() < 14 {
+            return None;
+        }
         let mut destination_address = [0; 6];
         let mut source_address = [0; 6];
-        let mut ether_type = [0; 2];
         // let mut packet = IPV4::new();
         destination_address.copy_from_slice(&bytes[0..6]);
         source_address.copy_from_slice(&bytes[6..12]);
-        ether_type.copy_from_slice(&bytes[12..14]);
-        let packet = IPV4::from_bytes(&bytes[14..]);
-        EthernetFrame {
-            destination_address,
-            source_address,
+
+        // Walk the stack of VLAN tags until the EtherType of the payload.
+        let mut offset = 12;
+        let mut vlan_tags = Vec::new();
+        let mut ether_type = EtherType(u16::from_be_bytes([bytes[offset], bytes[offset + 1]]));
+        while ether_type.is_vlan_tag() && bytes.len() >= offset + 6 {
+            let tci = u16::from_be_bytes([bytes[offset + 2], bytes[offset + 3]]);
+            vlan_tags.push(VlanTag::from_tci(ether_type, tci));
+            offset += 4;
+            ether_type = EtherType(u16::from_be_bytes([bytes[offset], bytes[offset + 1]]));
+        }
+        let payload = &bytes[offset + 2..];
+
+        let packet = match ether_type {
+            EtherType::IPV4 => match IPV4::from_bytes(payload) {
+                Some(packet) => EtherPayload::Ipv4(packet),
+                None => EtherPayload::Unknown(payload.to_vec()),
+            },
+            EtherType::ARP => match ARPPacket::from_bytes(payload) {
+                Some(arp) => EtherPayload::Arp(arp),
+                None => EtherPayload::Unknown(payload.to_vec()),
+            },
+            _ => EtherPayload::Unknown(payload.to_vec()),
+        };
+        Some(EthernetFrame {
+            destination_address: MacAddr(destination_address),
+            source_address: MacAddr(source_address),
+            vlan_tags,
             ether_type,
             packet,
-        }
+        })
     }
 
     ///Creates a new Ethernet frame.
@@ -51,12 +230,64 @@ impl EthernetFrame {
     ///     
     /// # Returns
     /// A new Ethernet frame.
-    pub(crate) fn new_ether(packet: IPV4) -> EthernetFrame {
+    pub fn new_ether(packet: IPV4) -> EthernetFrame {
         EthernetFrame {
-            destination_address: [0xff, 0xff, 0xff, 0xff, 0xff, 0xff],//[0x48, 0xa9, 0x8a, 0x3f, 0xb8, 0x5e],//[0xAC, 0x84, 0xC6, 0x67, 0x43, 0x8C],
-            source_address: [0x3c, 0x06, 0x30, 0x36, 0x61, 0x6c],
-            ether_type: [0x08, 0x00],
-            packet,
+            destination_address: MacAddr::BROADCAST,//[0x48, 0xa9, 0x8a, 0x3f, 0xb8, 0x5e],//[0xAC, 0x84, 0xC6, 0x67, 0x43, 0x8C],
+            source_address: MacAddr([0x3c, 0x06, 0x30, 0x36, 0x61, 0x6c]),
+            vlan_tags: Vec::new(),
+            ether_type: EtherType::IPV4,
+            packet: EtherPayload::Ipv4(packet),
+        }
+    }
+
+    ///Creates a new Ethernet frame carrying an ARP packet.
+    ///
+    /// # Arguments
+    /// * `packet` - ARP packet, its sender MAC is used as the source address.
+    /// * `destination_address` - Destination MAC address, usually broadcast for requests.
+    ///
+    /// # Returns
+    /// A new Ethernet frame.
+    pub fn new_arp(packet: ARPPacket, destination_address: MacAddr) -> EthernetFrame {
+        EthernetFrame {
+            destination_address,
+            source_address: packet.sender_hardware_address,
+            vlan_tags: Vec::new(),
+            ether_type: EtherType::ARP,
+            packet: EtherPayload::Arp(packet),
+        }
+    }
+
+    ///Tags the frame with the given VLAN stack, outermost first.
+    pub fn with_vlan_tags(mut self, vlan_tags: Vec<VlanTag>) -> EthernetFrame {
+        self.vlan_tags = vlan_tags;
+        self
+    }
+
+    ///Returns the IPv4 packet of the frame, if it carries one.
+    pub fn ipv4(&self) -> Option<&IPV4> {
+        match &self.packet {
+            EtherPayload::Ipv4(packet) => Some(packet),
+            _ => None,
+        }
+    }
+
+    ///Returns the ARP packet of the frame, if it carries one.
+    pub fn arp(&self) -> Option<&ARPPacket> {
+        match &self.packet {
+            EtherPayload::Arp(packet) => Some(packet),
+            _ => None,
+        }
+    }
+}
+
+///Implements the Display trait for EtherPayload.
+impl fmt::Display for EtherPayload {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EtherPayload::Ipv4(packet) => write!(f, "{}", packet),
+            EtherPayload::Arp(packet) => write!(f, "{}", packet),
+            EtherPayload::Unknown(bytes) => writeln!(f, "DATA: {} bytes", bytes.len()),
         }
     }
 }
@@ -64,34 +295,137 @@ impl EthernetFrame {
 ///Implements the Display trait for EthernetFrame.
 impl fmt::Display for EthernetFrame {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ETHER: -----Ether Header-----\n")?;
-        write!(f, "ETHER:\n")?;
-        write!(
-            f,
-            "ETHER: Destination= {:x}:{:x}:{:x}:{:x}:{:x}:{:x}\n",
-            self.destination_address[0],
-            self.destination_address[1],
-            self.destination_address[2],
-            self.destination_address[3],
-            self.destination_address[4],
-            self.destination_address[5]
-        )?;
-        write!(
+        writeln!(f, "ETHER: -----Ether Header-----")?;
+        writeln!(f, "ETHER:")?;
+        writeln!(
             f,
-            "ETHER: Source     = {:x}:{:x}:{:x}:{:x}:{:x}:{:x}\n",
-            self.source_address[0],
-            self.source_address[1],
-            self.source_address[2],
-            self.source_address[3],
-            self.source_address[4],
-            self.source_address[5]
+            "ETHER: Destination= {} ({})",
+            self.destination_address,
+            self.destination_address.describe()
         )?;
-        write!(
+        writeln!(
             f,
-            "ETHER: Ethertype  = 0x{:x}{:x}\n",
-            self.ether_type[0], self.ether_type[1]
+            "ETHER: Source     = {} ({})",
+            self.source_address,
+            self.source_address.describe()
         )?;
-        write!(f, "ETHER: -----Ether Header-----\n")?;
+        for tag in self.vlan_tags.iter() {
+            writeln!(
+                f,
+                "ETHER: VLAN tag   = {}, VID= {}, PCP= {}, DEI= {}",
+                tag.tpid,
+                tag.vid,
+                tag.pcp,
+                tag.dei as u8
+            )?;
+        }
+        writeln!(f, "ETHER: Ethertype  = {}", self.ether_type)?;
+        writeln!(f, "ETHER: -----Ether Header-----")?;
         write!(f, "\nPacket: \n{}", self.packet)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipv4::icmp::ICMPPacket;
+
+    /// Destination and source MAC addresses of the test frames.
+    const ADDRESSES: [u8; 12] = [0x02, 0, 0, 0, 0, 0x01, 0x02, 0, 0, 0, 0, 0x02];
+
+    /// Builds a frame of the test addresses followed by the given bytes.
+    fn frame_bytes(rest: &[u8]) -> Vec<u8> {
+        [&ADDRESSES[..], rest].concat()
+    }
+
+    #[test]
+    fn ether_types_are_named_or_kept_as_numbers() {
+        assert_eq!(EtherType(0x0800), EtherType::IPV4);
+        assert_eq!(EtherType::IPV4.to_string(), "IPv4 (0x0800)");
+        assert_eq!(EtherType::LLDP.name(), Some("LLDP"));
+        assert_eq!(EtherType(0x88b5).name(), None);
+        assert_eq!(EtherType(0x88b5).to_string(), "Unknown (0x88b5)");
+        assert!(EtherType::VLAN.is_vlan_tag() && EtherType::QINQ.is_vlan_tag() && EtherType::QINQ_LEGACY.is_vlan_tag());
+        assert!(!EtherType::IPV4.is_vlan_tag());
+    }
+
+    #[test]
+    fn tag_control_information_round_trips() {
+        let tag = VlanTag {
+            tpid: EtherType::VLAN,
+            pcp: 5,
+            dei: true,
+            vid: 0xabc,
+        };
+        assert_eq!(tag.to_bytes(), [0x81, 0x00, 0xba, 0xbc]);
+        assert_eq!(VlanTag::from_tci(EtherType::VLAN, 0xbabc), tag);
+        assert_eq!(VlanTag::from_tci(EtherType::QINQ, 0x0064), VlanTag::new_service(100));
+    }
+
+    #[test]
+    fn vlan_stacks_parse_outermost_first() {
+        assert_eq!(VlanTag::parse_stack("100"), Some(vec![VlanTag::new(100)]));
+        assert_eq!(
+            VlanTag::parse_stack("200.100"),
+            Some(vec![VlanTag::new_service(200), VlanTag::new(100)])
+        );
+        for bad in ["", "4096", "1..2", "100.", "vlan"] {
+            assert_eq!(VlanTag::parse_stack(bad), None, "{}", bad);
+        }
+    }
+
+    #[test]
+    fn single_tagged_frame_decodes_and_encodes_back() {
+        let bytes = frame_bytes(&[0x81, 0x00, 0x60, 0x64, 0x88, 0xb5, 0xde, 0xad]);
+        let mut frame = EthernetFrame::from_bytes(&bytes).unwrap();
+
+        assert_eq!(frame.destination_address, MacAddr([0x02, 0, 0, 0, 0, 0x01]));
+        assert_eq!(frame.source_address, MacAddr([0x02, 0, 0, 0, 0, 0x02]));
+        assert_eq!(
+            frame.vlan_tags,
+            [VlanTag {
+                tpid: EtherType::VLAN,
+                pcp: 3,
+                dei: false,
+                vid: 100,
+            }]
+        );
+        assert_eq!(frame.ether_type, EtherType(0x88b5));
+        assert!(matches!(&frame.packet, EtherPayload::Unknown(data) if data == &[0xde, 0xad]));
+        assert_eq!(frame.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn qinq_frame_carries_its_ipv4_packet() {
+        let packet = IPV4::new_icmp_from_ip(ICMPPacket::new_echo_request(1, 2), 64, [10, 0, 0, 2], [10, 0, 0, 1]);
+        let tags = vec![VlanTag::new_service(200), VlanTag::new(100)];
+        let mut sent = EthernetFrame::new_ether(packet).with_vlan_tags(tags.clone());
+        let bytes = sent.to_bytes();
+        assert_eq!(bytes[12..22], [0x88, 0xa8, 0x00, 0xc8, 0x81, 0x00, 0x00, 0x64, 0x08, 0x00]);
+
+        let mut frame = EthernetFrame::from_bytes(&bytes).unwrap();
+        assert_eq!(frame.vlan_tags, tags);
+        assert_eq!(frame.ether_type, EtherType::IPV4);
+        let packet = frame.ipv4().expect("an IPv4 packet");
+        assert_eq!(packet.destination_add, [10, 0, 0, 1]);
+        assert_eq!(packet.datagram.sequence, 2);
+        assert_eq!(frame.to_bytes(), bytes);
+    }
+
+    #[test]
+    fn truncated_frames_do_not_panic() {
+        for len in 0..14 {
+            assert!(EthernetFrame::from_bytes(&frame_bytes(&[0x08, 0x00])[..len]).is_none());
+        }
+
+        // A tag without the EtherType after it stays in the payload.
+        let frame = EthernetFrame::from_bytes(&frame_bytes(&[0x81, 0x00, 0x00, 0x64])).unwrap();
+        assert!(frame.vlan_tags.is_empty());
+        assert_eq!(frame.ether_type, EtherType::VLAN);
+        assert!(matches!(&frame.packet, EtherPayload::Unknown(data) if data == &[0x00, 0x64]));
+
+        // An IPv4 EtherType with a cut packet keeps the bytes.
+        let frame = EthernetFrame::from_bytes(&frame_bytes(&[0x08, 0x00, 0x45, 0x00])).unwrap();
+        assert!(frame.ipv4().is_none());
+    }
+}
@@ -0,0 +1,177 @@
+//! Module for discovering the local network interfaces and routes.
+
+extern crate libc;
+
+// Module declarations for each file in the interface directory
+pub mod route;
+
+use crate::mac_address::MacAddr;
+
+use std::ffi::CStr;
+
+/// Represents a local network interface.
+pub struct Interface {
+    /// Name of the interface, e.g. `en0`.
+    pub name: String,
+    /// Index of the interface, as used by the kernel.
+    pub index: u32,
+    /// MAC address of the interface.
+    pub mac: Option<MacAddr>,
+    /// IPv4 address of the interface.
+    pub ipv4: Option<[u8; 4]>,
+    /// IPv4 netmask of the interface.
+    pub netmask: Option<[u8; 4]>,
+    /// IPv6 addresses of the interface.
+    pub ipv6: Vec<[u8; 16]>,
+    /// MTU of the interface.
+    pub mtu: Option<u32>,
+    /// Whether the interface is administratively up.
+    pub up: bool,
+    /// Whether the interface is a loopback interface.
+    pub loopback: bool,
+}
+
+/// Lists the local network interfaces, in the order the kernel reports them.
+pub fn list_interfaces() -> Vec<Interface> {
+    let mut interfaces: Vec<Interface> = Vec::new();
+
+    unsafe {
+        let mut addrs: *mut libc::ifaddrs = std::ptr::null_mut();
+        if libc::getifaddrs(&mut addrs) != 0 {
+            return interfaces;
+        }
+
+        // getifaddrs reports one entry per address, so entries are merged by name.
+        let mut cursor = addrs;
+        while !cursor.is_null() {
+            let ifa = &*cursor;
+            cursor = ifa.ifa_next;
+
+            let name = CStr::from_ptr(ifa.ifa_name).to_string_lossy();
+            let interface = match interfaces.iter().position(|interface| interface.name == name) {
+                Some(i) => &mut interfaces[i],
+                None => {
+                    interfaces.push(Interface {
+                        name: name.to_string(),
+                        index: libc::if_nametoindex(ifa.ifa_name),
+                        mac: None,
+                        ipv4: None,
+                        netmask: None,
+                        ipv6: Vec::new(),
+                        mtu: interface_mtu(ifa),
+                        up: ifa.ifa_flags & libc::IFF_UP as libc::c_uint != 0,
+                        loopback: ifa.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0,
+                    });
+                    interfaces.last_mut().unwrap()
+                }
+            };
+            if ifa.ifa_addr.is_null() {
+                continue;
+            }
+
+            match (*ifa.ifa_addr).sa_family as i32 {
+                libc::AF_INET => {
+                    if interface.ipv4.is_none() {
+                        interface.ipv4 = Some(sockaddr_in_octets(ifa.ifa_addr));
+                        if !ifa.ifa_netmask.is_null() {
+                            interface.netmask = Some(sockaddr_in_octets(ifa.ifa_netmask));
+                        }
+                    }
+                }
+                libc::AF_INET6 => {
+                    let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in6);
+                    interface.ipv6.push(addr.sin6_addr.s6_addr);
+                }
+                family => {
+                    if let Some(mac) = link_layer_address(family, ifa.ifa_addr) {
+                        interface.mac = Some(MacAddr(mac));
+                    }
+                    if interface.mtu.is_none() {
+                        interface.mtu = interface_mtu(ifa);
+                    }
+                }
+            }
+        }
+
+        libc::freeifaddrs(addrs);
+    }
+
+    interfaces
+}
+
+/// Looks up the addresses of the interface with the given name.
+///
+/// Returns `None` if no interface has that name.
+pub fn lookup_interface(name: &str) -> Option<Interface> {
+    list_interfaces().into_iter().find(|interface| interface.name == name)
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Reads the IPv4 address out of a `sockaddr_in`.
+unsafe fn sockaddr_in_octets(addr: *const libc::sockaddr) -> [u8; 4] {
+    let addr = &*(addr as *const libc::sockaddr_in);
+    // `s_addr` is stored in network byte order.
+    addr.sin_addr.s_addr.to_ne_bytes()
+}
+
+/// Reads the MAC address out of a link layer `sockaddr_dl`.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn link_layer_address(family: i32, addr: *const libc::sockaddr) -> Option<[u8; 6]> {
+    if family != libc::AF_LINK {
+        return None;
+    }
+    let addr = &*(addr as *const libc::sockaddr_dl);
+    if addr.sdl_alen != 6 {
+        return None;
+    }
+    // The link layer address follows the interface name in `sdl_data`.
+    let data = addr.sdl_data.as_ptr() as *const u8;
+    let mut mac = [0; 6];
+    std::ptr::copy_nonoverlapping(data.add(addr.sdl_nlen as usize), mac.as_mut_ptr(), 6);
+    Some(mac)
+}
+
+/// Reads the MAC address out of a link layer `sockaddr_ll`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn link_layer_address(family: i32, addr: *const libc::sockaddr) -> Option<[u8; 6]> {
+    if family != libc::AF_PACKET {
+        return None;
+    }
+    let addr = &*(addr as *const libc::sockaddr_ll);
+    if addr.sll_halen != 6 {
+        return None;
+    }
+    let mut mac = [0; 6];
+    mac.copy_from_slice(&addr.sll_addr[..6]);
+    Some(mac)
+}
+
+/// Reads the MTU out of the `if_data` of a link layer entry.
+#[cfg(any(target_os = "macos", target_os = "ios", target_os = "freebsd"))]
+unsafe fn interface_mtu(ifa: &libc::ifaddrs) -> Option<u32> {
+    if ifa.ifa_addr.is_null() || (*ifa.ifa_addr).sa_family as i32 != libc::AF_LINK || ifa.ifa_data.is_null() {
+        return None;
+    }
+    Some((*(ifa.ifa_data as *const libc::if_data)).ifi_mtu)
+}
+
+/// Asks the kernel for the MTU of the interface.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+unsafe fn interface_mtu(ifa: &libc::ifaddrs) -> Option<u32> {
+    let fd = libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0);
+    if fd == -1 {
+        return None;
+    }
+    let mut ifr: libc::ifreq = std::mem::zeroed();
+    let name = CStr::from_ptr(ifa.ifa_name).to_bytes();
+    for (i, &byte) in name.iter().take(libc::IFNAMSIZ - 1).enumerate() {
+        ifr.ifr_name[i] = byte as libc::c_char;
+    }
+    let result = libc::ioctl(fd, libc::SIOCGIFMTU, &mut ifr);
+    libc::close(fd);
+    if result == -1 {
+        return None;
+    }
+    Some(ifr.ifr_ifru.ifru_mtu as u32)
+}
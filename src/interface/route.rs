@@ -0,0 +1,352 @@
+//! Module for reading the kernel's IPv4 routing table and neighbor cache.
+//!
+//! The routing table picks the interface, source address and gateway a packet to
+//! a destination leaves through, and the neighbor cache the MAC address of that
+//! next hop, so frames can be addressed without hard-coding either.
+
+use crate::interface::{lookup_interface, Interface};
+use crate::ipv4::subnet::{netmask_to_prefix, prefix_to_mask};
+use crate::mac_address::MacAddr;
+
+/// Represents a route of the kernel's IPv4 routing table.
+pub struct Route {
+    /// Network the route leads to.
+    pub destination: [u8; 4],
+    /// Prefix length of the network, 0 for the default route.
+    pub prefix: u32,
+    /// Router to send through, `None` for directly connected networks.
+    pub gateway: Option<[u8; 4]>,
+    /// Name of the interface the route leaves through.
+    pub interface: String,
+    /// Metric of the route, lower is preferred.
+    pub metric: u32,
+}
+
+impl Route {
+    /// Checks whether the route covers the given address.
+    pub fn contains(&self, address: [u8; 4]) -> bool {
+        let mask = prefix_to_mask(self.prefix);
+        u32::from_be_bytes(address) & mask == u32::from_be_bytes(self.destination) & mask
+    }
+}
+
+/// Where a packet to a destination leaves the host.
+pub struct NextHop {
+    /// Interface the packet leaves through.
+    pub interface: Interface,
+    /// Address the packet is sent from.
+    pub source: [u8; 4],
+    /// Router the packet is sent through, `None` if the destination is on-link.
+    pub gateway: Option<[u8; 4]>,
+}
+
+impl NextHop {
+    /// Address whose MAC address frames to the destination are sent to.
+    pub fn address(&self, destination: [u8; 4]) -> [u8; 4] {
+        self.gateway.unwrap_or(destination)
+    }
+}
+
+/// Picks the interface, source address and gateway for a destination.
+///
+/// The most specific route wins, then the one with the lowest metric. Returns
+/// `None` if no route covers the destination or its interface has no IPv4 address.
+pub fn next_hop(destination: [u8; 4]) -> Option<NextHop> {
+    let route = best_route(routing_table(), destination)?;
+    let interface = lookup_interface(&route.interface)?;
+    let source = interface.ipv4?;
+    Some(NextHop {
+        interface,
+        source,
+        gateway: route.gateway,
+    })
+}
+
+/// Returns the name of the interface of the default route.
+pub fn default_interface() -> Option<String> {
+    routing_table()
+        .into_iter()
+        .filter(|route| route.prefix == 0)
+        .min_by_key(|route| route.metric)
+        .map(|route| route.interface)
+}
+
+/// Reads the IPv4 routing table from `/proc/net/route`.
+///
+/// Addresses are printed as hexadecimal integers in host byte order:
+///
+/// ```text
+/// Iface  Destination  Gateway   Flags  RefCnt  Use  Metric  Mask      MTU  Window  IRTT
+/// eth0   00000000     0101A8C0  0003   0       0    100     00000000  0    0       0
+/// ```
+#[cfg(target_os = "linux")]
+pub fn routing_table() -> Vec<Route> {
+    parse_proc_route(&std::fs::read_to_string("/proc/net/route").unwrap_or_default())
+}
+
+/// Reads the IPv4 routing table from the output of `netstat -rn -f inet`.
+///
+/// Networks are printed with their trailing zero octets left out:
+///
+/// ```text
+/// Destination        Gateway            Flags           Netif Expire
+/// default            192.168.1.1        UGScg             en0
+/// 192.168.1          link#4             UCS               en0      !
+/// 192.168.1.1/32     link#4             UCS               en0      !
+/// ```
+#[cfg(not(target_os = "linux"))]
+pub fn routing_table() -> Vec<Route> {
+    match std::process::Command::new("netstat").args(["-rn", "-f", "inet"]).output() {
+        Ok(output) => parse_netstat(&String::from_utf8_lossy(&output.stdout)),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// Looks up the MAC address of a neighbor in the kernel's ARP cache.
+///
+/// Returns `None` if the address has not been resolved yet.
+#[cfg(target_os = "linux")]
+pub fn lookup_neighbor(address: [u8; 4]) -> Option<MacAddr> {
+    /// Entry is resolved.
+    const ATF_COM: u32 = 0x2;
+
+    let cache = std::fs::read_to_string("/proc/net/arp").ok()?;
+    let address = std::net::Ipv4Addr::from(address).to_string();
+    cache.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let flags = u32::from_str_radix(fields.get(2)?.trim_start_matches("0x"), 16).ok()?;
+        if fields[0] != address || flags & ATF_COM == 0 {
+            return None;
+        }
+        fields.get(3)?.parse().ok()
+    })
+}
+
+/// Looks up the MAC address of a neighbor in the output of `arp -n`.
+///
+/// Returns `None` if the address has not been resolved yet.
+#[cfg(not(target_os = "linux"))]
+pub fn lookup_neighbor(address: [u8; 4]) -> Option<MacAddr> {
+    let address = std::net::Ipv4Addr::from(address).to_string();
+    let output = std::process::Command::new("arp").args(["-n", &address]).output().ok()?;
+    // `? (192.168.1.1) at 3c:22:fb:1:2:3 on en0 ifscope [ethernet]`
+    let output = String::from_utf8_lossy(&output.stdout);
+    let mut words = output.split_whitespace().skip_while(|word| *word != "at").skip(1);
+    words.next()?.parse().ok()
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Picks the route to a destination: the most specific one, then the one with the lowest metric.
+fn best_route(routes: Vec<Route>, destination: [u8; 4]) -> Option<Route> {
+    routes
+        .into_iter()
+        .filter(|route| route.contains(destination))
+        .min_by_key(|route| (std::cmp::Reverse(route.prefix), route.metric))
+}
+
+/// Parses the routing table in the format of `/proc/net/route`, skipping malformed lines
+/// and routes that are down.
+#[cfg(any(target_os = "linux", test))]
+fn parse_proc_route(table: &str) -> Vec<Route> {
+    /// Route is usable.
+    const RTF_UP: u32 = 0x1;
+    /// Destination is reached through a gateway.
+    const RTF_GATEWAY: u32 = 0x2;
+
+    let address = |hex: &str| u32::from_str_radix(hex, 16).ok().map(u32::to_ne_bytes);
+
+    let mut routes = Vec::new();
+    for line in table.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 8 {
+            continue;
+        }
+        let (Some(destination), Some(gateway), Ok(flags), Ok(metric), Some(mask)) = (
+            address(fields[1]),
+            address(fields[2]),
+            u32::from_str_radix(fields[3], 16),
+            fields[6].parse(),
+            address(fields[7]),
+        ) else {
+            continue;
+        };
+        if flags & RTF_UP == 0 {
+            continue;
+        }
+        routes.push(Route {
+            destination,
+            prefix: netmask_to_prefix(mask),
+            gateway: if flags & RTF_GATEWAY != 0 { Some(gateway) } else { None },
+            interface: fields[0].to_string(),
+            metric,
+        });
+    }
+    routes
+}
+
+/// Parses the routing table in the format of `netstat -rn -f inet`, skipping malformed lines.
+#[cfg(any(not(target_os = "linux"), test))]
+fn parse_netstat(output: &str) -> Vec<Route> {
+    let mut routes = Vec::new();
+    for line in output.lines().skip_while(|line| !line.starts_with("Destination")).skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 4 {
+            continue;
+        }
+        let flags = fields[2];
+        let (destination, prefix) = match fields[0] {
+            "default" => ([0; 4], 0),
+            network => match parse_network(network, flags.contains('H')) {
+                Some(network) => network,
+                None => continue,
+            },
+        };
+        let gateway = if flags.contains('G') {
+            fields[1].parse::<std::net::Ipv4Addr>().ok().map(|ip| ip.octets())
+        } else {
+            None
+        };
+        routes.push(Route {
+            destination,
+            prefix,
+            gateway,
+            interface: fields[3].to_string(),
+            // netstat does not print metrics; the first route listed wins a tie.
+            metric: routes.len() as u32,
+        });
+    }
+    routes
+}
+
+/// Parses a network as printed by netstat, e.g. `192.168.1` or `10/8`.
+///
+/// Without an explicit prefix length, host routes are `/32` and networks are as long
+/// as their printed octets.
+#[cfg(any(not(target_os = "linux"), test))]
+fn parse_network(network: &str, host: bool) -> Option<([u8; 4], u32)> {
+    let (address, prefix) = match network.split_once('/') {
+        Some((address, prefix)) => (address, Some(prefix.parse().ok()?)),
+        None => (network, None),
+    };
+    let mut octets = [0; 4];
+    let mut count = 0;
+    for (i, octet) in address.split('.').enumerate() {
+        *octets.get_mut(i)? = octet.parse().ok()?;
+        count = i + 1;
+    }
+    let prefix = prefix.unwrap_or(if host { 32 } else { count as u32 * 8 });
+    Some((octets, prefix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Prints an address as `/proc/net/route` does, in host byte order.
+    fn hex(address: [u8; 4]) -> String {
+        format!("{:08X}", u32::from_ne_bytes(address))
+    }
+
+    /// Header line of `/proc/net/route`.
+    const PROC_HEADER: &str =
+        "Iface\tDestination\tGateway\tFlags\tRefCnt\tUse\tMetric\tMask\tMTU\tWindow\tIRTT\n";
+
+    /// Builds a line of `/proc/net/route`.
+    fn proc_line(
+        interface: &str,
+        destination: [u8; 4],
+        gateway: [u8; 4],
+        flags: u32,
+        metric: u32,
+        mask: [u8; 4],
+    ) -> String {
+        format!(
+            "{}\t{}\t{}\t{:04X}\t0\t0\t{}\t{}\t0\t0\t0\n",
+            interface,
+            hex(destination),
+            hex(gateway),
+            flags,
+            metric,
+            hex(mask)
+        )
+    }
+
+    #[test]
+    fn proc_route_reads_gateways_and_prefixes() {
+        let mut table = [
+            PROC_HEADER.to_string(),
+            proc_line("eth0", [0; 4], [192, 168, 1, 1], 0x3, 100, [0; 4]),
+            proc_line("eth0", [192, 168, 1, 0], [0; 4], 0x1, 100, [255, 255, 255, 0]),
+            proc_line("wg0", [10, 8, 0, 0], [10, 8, 0, 1], 0x3, 50, [255, 255, 0, 0]),
+            // A route that is down.
+            proc_line("eth1", [172, 16, 0, 0], [0; 4], 0x0, 0, [255, 240, 0, 0]),
+        ]
+        .concat();
+        table += "eth0\tnot-hex\t00000000\t0001\t0\t0\t0\t00000000\t0\t0\t0\n";
+        table += "eth0\t00000000\n";
+
+        let routes = parse_proc_route(&table);
+        assert_eq!(routes.len(), 3);
+        assert_eq!((routes[0].destination, routes[0].prefix), ([0; 4], 0));
+        assert_eq!(routes[0].gateway, Some([192, 168, 1, 1]));
+        assert_eq!((routes[1].destination, routes[1].prefix), ([192, 168, 1, 0], 24));
+        assert_eq!(routes[1].gateway, None);
+        assert_eq!((routes[2].interface.as_str(), routes[2].prefix, routes[2].metric), ("wg0", 16, 50));
+    }
+
+    #[test]
+    fn netstat_reads_short_networks_and_host_routes() {
+        let output = "Routing tables\n\nInternet:\n\
+            Destination        Gateway            Flags           Netif Expire\n\
+            default            192.168.1.1        UGScg             en0\n\
+            10/8               10.0.0.1           UGSc              utun3\n\
+            192.168.1          link#4             UCS               en0      !\n\
+            192.168.1.1/32     link#4             UCS               en0      !\n\
+            192.168.1.7        3c:22:fb:1:2:3     UHLWIi            en0   1180\n\
+            300.1              link#4             UCS               en0\n\
+            truncated          link#4\n";
+
+        let routes = parse_netstat(output);
+        let summary: Vec<_> = routes
+            .iter()
+            .map(|route| (route.destination, route.prefix, route.gateway, route.interface.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ([0; 4], 0, Some([192, 168, 1, 1]), "en0"),
+                ([10, 0, 0, 0], 8, Some([10, 0, 0, 1]), "utun3"),
+                ([192, 168, 1, 0], 24, None, "en0"),
+                ([192, 168, 1, 1], 32, None, "en0"),
+                ([192, 168, 1, 7], 32, None, "en0"),
+            ]
+        );
+        assert_eq!(routes[4].metric, 4);
+    }
+
+    #[test]
+    fn most_specific_route_wins_then_lowest_metric() {
+        let table = [
+            PROC_HEADER.to_string(),
+            proc_line("eth0", [0; 4], [192, 168, 1, 1], 0x3, 100, [0; 4]),
+            proc_line("wlan0", [0; 4], [192, 168, 2, 1], 0x3, 600, [0; 4]),
+            proc_line("eth0", [192, 168, 1, 0], [0; 4], 0x1, 100, [255, 255, 255, 0]),
+            proc_line("wg0", [10, 8, 0, 0], [10, 8, 0, 1], 0x3, 50, [255, 255, 0, 0]),
+            proc_line("wg1", [10, 8, 0, 0], [10, 9, 0, 1], 0x3, 20, [255, 255, 0, 0]),
+        ]
+        .concat();
+        let route = |destination| best_route(parse_proc_route(&table), destination);
+
+        let default = route([8, 8, 8, 8]).unwrap();
+        assert_eq!((default.interface.as_str(), default.gateway), ("eth0", Some([192, 168, 1, 1])));
+        let on_link = route([192, 168, 1, 20]).unwrap();
+        assert_eq!((on_link.interface.as_str(), on_link.gateway), ("eth0", None));
+        let tunnel = route([10, 8, 3, 4]).unwrap();
+        assert_eq!((tunnel.interface.as_str(), tunnel.gateway), ("wg1", Some([10, 9, 0, 1])));
+
+        let lan = proc_line("eth0", [192, 168, 1, 0], [0; 4], 0x1, 0, [255, 255, 255, 0]);
+        let on_link_only = PROC_HEADER.to_string() + &lan;
+        assert!(best_route(parse_proc_route(&on_link_only), [8, 8, 8, 8]).is_none());
+    }
+}
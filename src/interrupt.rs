@@ -0,0 +1,36 @@
+//! Ctrl-C handling of the commands that run until interrupted.
+//!
+//! The first Ctrl-C sets a stop flag instead of killing the process, so the command
+//! winds down and still prints its summary. The handler is then reset, and a second
+//! Ctrl-C kills the process as usual, should the command be stuck.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// Flag set by the first Ctrl-C.
+static STOP: OnceLock<Arc<AtomicBool>> = OnceLock::new();
+
+/// Makes the first Ctrl-C set the given flag, e.g. the stop flag of a pinger.
+///
+/// Only the first flag of the process is set; commands install one at most.
+pub(crate) fn stop_on_interrupt(flag: Arc<AtomicBool>) {
+    if STOP.set(flag).is_err() {
+        return;
+    }
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = on_interrupt as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        action.sa_flags = libc::SA_RESETHAND;
+        libc::sigemptyset(&mut action.sa_mask);
+        libc::sigaction(libc::SIGINT, &action, std::ptr::null_mut());
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Handler of SIGINT; only touches atomics, as befits a signal handler.
+extern "C" fn on_interrupt(_signal: libc::c_int) {
+    if let Some(flag) = STOP.get() {
+        flag.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,352 @@
+//! The Internet checksum of IPv4 headers, ICMP messages and UDP and TCP segments.
+//!
+//! Checksums are the one's complement of the one's complement sum of the 16-bit words
+//! they cover, a trailing odd byte padded with zero. Data may be summed in several
+//! slices, e.g. a pseudo-header and a segment, without joining them; a checksum can be
+//! updated when a field changes without summing the data again (RFC 1624); and bulk
+//! data is summed eight bytes at a time.
+//!
+//! Packets captured on their way out of this host may carry a zero or partial
+//! checksum, left for the network card to fill in with checksum offload. Verification
+//! tells those apart, so they are only reported as bad on packets received from elsewhere.
+
+use crate::ipv4::fields::IpProtocol;
+
+use std::fmt;
+
+/// Length of the pseudo-header of UDP and TCP checksums.
+pub const PSEUDO_HEADER_LEN: usize = 12;
+
+/// Running one's complement sum of data given in slices of any length.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Checksum {
+    /// Sum of the words so far, folded only when finished.
+    sum: u64,
+    /// Odd byte ending the last slice, the high half of a word the next slice completes.
+    pending: Option<u8>,
+}
+
+impl Checksum {
+    /// Creates an empty sum.
+    pub fn new() -> Checksum {
+        Checksum::default()
+    }
+
+    /// Adds bytes following those already added.
+    pub fn add(&mut self, mut data: &[u8]) -> &mut Checksum {
+        if let Some(high) = self.pending.take() {
+            match data.split_first() {
+                Some((low, rest)) => {
+                    self.sum += u16::from_be_bytes([high, *low]) as u64;
+                    data = rest;
+                }
+                None => {
+                    self.pending = Some(high);
+                    return self;
+                }
+            }
+        }
+        if let Some((last, words)) = data.split_last().filter(|_| data.len() % 2 == 1) {
+            self.pending = Some(*last);
+            data = words;
+        }
+        self.sum += sum_words(data);
+        self
+    }
+
+    /// Returns the checksum of the bytes added, a trailing odd byte padded with zero.
+    pub fn finish(&self) -> u16 {
+        let sum = self.sum + self.pending.map_or(0, |high| (high as u64) << 8);
+        !fold(sum)
+    }
+}
+
+/// Outcome of the verification of a checksum.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumStatus {
+    /// The checksum matches the bytes it covers.
+    Correct,
+    /// The checksum does not match the bytes it covers.
+    Incorrect {
+        /// Value the checksum should hold.
+        expected: u16,
+    },
+    /// The checksum is zero or covers only the pseudo-header, as left for the network
+    /// card to fill in. Expected on packets sent by this host, a corruption otherwise.
+    Offloaded {
+        /// Value the checksum should hold once filled in.
+        expected: u16,
+    },
+    /// The checksum cannot be checked: the packet is truncated or a fragment, the
+    /// sender left it out, or the protocol is not one whose checksum is known.
+    Unverified,
+}
+
+impl ChecksumStatus {
+    /// Checks whether the checksum is wrong.
+    ///
+    /// # Arguments
+    /// * `sent_locally` - Whether the packet was sent by this host, whose offloaded checksums are not wrong.
+    pub fn is_bad(&self, sent_locally: bool) -> bool {
+        match self {
+            ChecksumStatus::Incorrect { .. } => true,
+            ChecksumStatus::Offloaded { .. } => !sent_locally,
+            ChecksumStatus::Correct | ChecksumStatus::Unverified => false,
+        }
+    }
+}
+
+/// Implements the Display trait for ChecksumStatus, e.g. `INCORRECT, should be 0x7ad9`.
+impl fmt::Display for ChecksumStatus {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChecksumStatus::Correct => write!(f, "correct"),
+            ChecksumStatus::Incorrect { expected } => write!(f, "INCORRECT, should be 0x{:04x}", expected),
+            ChecksumStatus::Offloaded { expected } => {
+                write!(f, "unfilled, should be 0x{:04x} unless offloaded", expected)
+            }
+            ChecksumStatus::Unverified => write!(f, "not verified"),
+        }
+    }
+}
+
+/// Calculates the Internet checksum of data, e.g. of an ICMP message or an IPv4 header
+/// with its checksum field zeroed.
+///
+/// Data whose checksum field holds the right value sums to a checksum of zero.
+pub fn calculate_checksum(data: &[u8]) -> u16 {
+    Checksum::new().add(data).finish()
+}
+
+/// Calculates the Internet checksum of data given in slices, as if they were joined.
+pub fn calculate_checksum_of(slices: &[&[u8]]) -> u16 {
+    let mut checksum = Checksum::new();
+    for slice in slices {
+        checksum.add(slice);
+    }
+    checksum.finish()
+}
+
+/// Updates a checksum after a 16-bit word it covers changed from `old` to `new` (RFC 1624).
+///
+/// A byte field is updated with the word it shares, e.g. the TTL with the protocol.
+pub fn incremental_update(checksum: u16, old: u16, new: u16) -> u16 {
+    !fold(!checksum as u64 + !old as u64 + new as u64)
+}
+
+/// Builds the pseudo-header covered by the checksum of a UDP or TCP segment.
+///
+/// # Arguments
+/// * `source` - Source address of the IPv4 packet.
+/// * `destination` - Destination address of the IPv4 packet.
+/// * `protocol` - Protocol of the segment.
+/// * `length` - Length of the segment, header and data.
+pub fn pseudo_header(source: [u8; 4], destination: [u8; 4], protocol: IpProtocol, length: u16) -> [u8; PSEUDO_HEADER_LEN] {
+    let length = length.to_be_bytes();
+    [
+        source[0],
+        source[1],
+        source[2],
+        source[3],
+        destination[0],
+        destination[1],
+        destination[2],
+        destination[3],
+        0,
+        protocol.0,
+        length[0],
+        length[1],
+    ]
+}
+
+/// Verifies the checksum at `checksum_at` in `data`, the bytes it covers.
+pub fn verify(data: &[u8], checksum_at: usize) -> ChecksumStatus {
+    if calculate_checksum(data) == 0 {
+        return ChecksumStatus::Correct;
+    }
+    ChecksumStatus::Incorrect {
+        expected: calculate_checksum_of(&[&data[..checksum_at], &[0, 0], &data[checksum_at + 2..]]),
+    }
+}
+
+/// Verifies the checksum of a UDP or TCP segment, at `checksum_at` in the segment.
+///
+/// A zero checksum, or one holding the sum of the pseudo-header alone as Linux leaves
+/// it for the network card, is reported as offloaded rather than incorrect.
+pub fn verify_segment(pseudo_header: &[u8; PSEUDO_HEADER_LEN], segment: &[u8], checksum_at: usize) -> ChecksumStatus {
+    if calculate_checksum_of(&[pseudo_header, segment]) == 0 {
+        return ChecksumStatus::Correct;
+    }
+    let expected = calculate_checksum_of(&[pseudo_header, &segment[..checksum_at], &[0, 0], &segment[checksum_at + 2..]]);
+    let checksum = u16::from_be_bytes([segment[checksum_at], segment[checksum_at + 1]]);
+    if checksum == 0 || checksum == !calculate_checksum(pseudo_header) {
+        ChecksumStatus::Offloaded { expected }
+    } else {
+        ChecksumStatus::Incorrect { expected }
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Sums the big endian 16-bit words of data of even length, without folding.
+///
+/// Words are summed in pairs as 32-bit values, eight bytes at a time: the carries out of
+/// the low word land in the high one, which folding gives back as 2^16 is 1 modulo 0xffff.
+fn sum_words(data: &[u8]) -> u64 {
+    let mut chunks = data.chunks_exact(8);
+    let mut sum = 0u64;
+    for chunk in chunks.by_ref() {
+        let value = u64::from_be_bytes(chunk.try_into().unwrap());
+        sum += (value >> 32) + (value & 0xffff_ffff);
+    }
+    for word in chunks.remainder().chunks_exact(2) {
+        sum += u16::from_be_bytes([word[0], word[1]]) as u64;
+    }
+    sum
+}
+
+/// Folds a sum to 16 bits, adding the carries back in.
+fn fold(mut sum: u64) -> u16 {
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// IPv4 header of a UDP packet from 192.168.0.1 to 192.168.0.199, checksum zeroed.
+    const HEADER: [u8; 20] = [
+        0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8, 0x00, 0x01, 0xc0, 0xa8,
+        0x00, 0xc7,
+    ];
+
+    /// Sums the words of data one at a time, as a reference.
+    fn naive_checksum(data: &[u8]) -> u16 {
+        let mut sum = 0u64;
+        for word in data.chunks(2) {
+            sum += u16::from_be_bytes([word[0], *word.get(1).unwrap_or(&0)]) as u64;
+        }
+        !fold(sum)
+    }
+
+    /// Draws bytes from a xorshift generator with a fixed seed.
+    fn test_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                (state >> 24) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn known_vectors() {
+        // RFC 1071, section 3: the words sum to 0xddf2.
+        assert_eq!(calculate_checksum(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]), !0xddf2);
+        assert_eq!(calculate_checksum(&HEADER), 0xb861);
+        assert_eq!(calculate_checksum(&[]), 0xffff);
+        // A trailing odd byte is the high half of a word.
+        assert_eq!(calculate_checksum(&[0x01]), !0x0100);
+    }
+
+    #[test]
+    fn odd_bytes_carry_over_into_the_next_slice() {
+        let data = test_bytes(37, 1);
+        for first in 0..data.len() {
+            for second in first..data.len() {
+                let mut checksum = Checksum::new();
+                checksum.add(&data[..first]).add(&[]).add(&data[first..second]).add(&data[second..]);
+                assert_eq!(checksum.finish(), naive_checksum(&data), "split at {} and {}", first, second);
+            }
+        }
+        // Odd bytes only, each completing the word of the one before.
+        let mut checksum = Checksum::new();
+        for byte in [0xff, 0xff, 0xff] {
+            checksum.add(&[byte]);
+        }
+        assert_eq!(checksum.finish(), naive_checksum(&[0xff, 0xff, 0xff]));
+    }
+
+    #[test]
+    fn eight_byte_sums_match_the_word_by_word_sum() {
+        // All ones makes every 32-bit addition carry into the high word.
+        for len in (0..64).step_by(2) {
+            let ones = vec![0xff; len];
+            assert_eq!(!fold(sum_words(&ones)), naive_checksum(&ones), "{} bytes of ones", len);
+            let data = test_bytes(len, len as u64);
+            assert_eq!(!fold(sum_words(&data)), naive_checksum(&data), "{} bytes", len);
+        }
+        let large = test_bytes(65536, 7);
+        assert_eq!(calculate_checksum(&large), naive_checksum(&large));
+    }
+
+    #[test]
+    fn incremental_update_matches_a_full_recompute() {
+        // RFC 1624, section 4: the update must give 0x0000, where RFC 1141 gave 0xffff.
+        assert_eq!(incremental_update(0xdd2f, 0x5555, 0x3285), 0x0000);
+        let mut data = [0x55, 0x55, 0xcd, 0x7a];
+        assert_eq!(calculate_checksum(&data), 0xdd2f);
+        data[..2].copy_from_slice(&[0x32, 0x85]);
+        assert_eq!(calculate_checksum(&data), 0x0000);
+
+        // Decrementing the TTL of the header, as a router does.
+        let mut header = HEADER;
+        header[8] = 0x3f;
+        assert_eq!(incremental_update(0xb861, 0x4011, 0x3f11), calculate_checksum(&header));
+
+        let mut data = test_bytes(64, 3);
+        let mut checksum = calculate_checksum(&data);
+        for (i, new) in test_bytes(32, 5).chunks(2).enumerate() {
+            let old = u16::from_be_bytes([data[2 * i], data[2 * i + 1]]);
+            let new = u16::from_be_bytes([new[0], new[1]]);
+            data[2 * i..2 * i + 2].copy_from_slice(&new.to_be_bytes());
+            checksum = incremental_update(checksum, old, new);
+            assert_eq!(checksum, calculate_checksum(&data), "word {}", i);
+        }
+        for (old, new) in [(0x0000, 0xffff), (0xffff, 0x0000), (0x1234, 0x1234)] {
+            let mut data = [0x12, 0x34, 0x00, 0x00];
+            data[2..].copy_from_slice(&u16::to_be_bytes(old));
+            let checksum = calculate_checksum(&data);
+            data[2..].copy_from_slice(&u16::to_be_bytes(new));
+            assert_eq!(incremental_update(checksum, old, new), calculate_checksum(&data));
+        }
+    }
+
+    #[test]
+    fn verify_reports_the_expected_checksum() {
+        let mut header = HEADER;
+        header[10..12].copy_from_slice(&0xb861u16.to_be_bytes());
+        assert_eq!(verify(&header, 10), ChecksumStatus::Correct);
+        header[10..12].copy_from_slice(&0xb862u16.to_be_bytes());
+        assert_eq!(verify(&header, 10), ChecksumStatus::Incorrect { expected: 0xb861 });
+    }
+
+    #[test]
+    fn verify_segment_detects_offloaded_checksums() {
+        let pseudo_header = pseudo_header([192, 168, 0, 1], [192, 168, 0, 199], IpProtocol::UDP, 13);
+        // UDP from port 53 to 1234, length 13, checksum zeroed, and 5 bytes of data.
+        let mut segment = [0x00, 0x35, 0x04, 0xd2, 0x00, 0x0d, 0x00, 0x00, b'h', b'e', b'l', b'l', b'o'];
+        let expected = calculate_checksum_of(&[&pseudo_header, &segment]);
+        let mut with_checksum = |checksum: u16| {
+            segment[6..8].copy_from_slice(&checksum.to_be_bytes());
+            verify_segment(&pseudo_header, &segment, 6)
+        };
+
+        assert_eq!(with_checksum(expected), ChecksumStatus::Correct);
+        assert_eq!(with_checksum(0), ChecksumStatus::Offloaded { expected });
+        // Linux leaves the sum of the pseudo-header alone for the card to complete.
+        let partial = !calculate_checksum(&pseudo_header);
+        assert_eq!(with_checksum(partial), ChecksumStatus::Offloaded { expected });
+        assert_eq!(with_checksum(expected ^ 0x0100), ChecksumStatus::Incorrect { expected });
+
+        assert!(!ChecksumStatus::Offloaded { expected }.is_bad(true));
+        assert!(ChecksumStatus::Offloaded { expected }.is_bad(false));
+        assert!(ChecksumStatus::Incorrect { expected }.is_bad(true));
+    }
+}
@@ -0,0 +1,288 @@
+//! Typed IPv4 header fields.
+//!
+//! Wrappers around the raw protocol, DSCP, ECN and flags values of an IPv4 header
+//! with named constants, so decodes can show `ICMP (1)` or `DF` instead of bare numbers.
+//! Values without a name are kept as they are.
+
+use std::fmt;
+
+/// Protocol carried by an IPv4 packet.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IpProtocol(pub u8);
+
+impl IpProtocol {
+    pub const ICMP: IpProtocol = IpProtocol(1);
+    pub const IGMP: IpProtocol = IpProtocol(2);
+    pub const TCP: IpProtocol = IpProtocol(6);
+    pub const UDP: IpProtocol = IpProtocol(17);
+    pub const IPV6: IpProtocol = IpProtocol(41);
+    pub const GRE: IpProtocol = IpProtocol(47);
+    pub const ESP: IpProtocol = IpProtocol(50);
+    pub const AH: IpProtocol = IpProtocol(51);
+    pub const ICMPV6: IpProtocol = IpProtocol(58);
+    pub const OSPF: IpProtocol = IpProtocol(89);
+    pub const SCTP: IpProtocol = IpProtocol(132);
+
+    /// Name of the protocol, if it is a known one.
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            IpProtocol::ICMP => Some("ICMP"),
+            IpProtocol::IGMP => Some("IGMP"),
+            IpProtocol::TCP => Some("TCP"),
+            IpProtocol::UDP => Some("UDP"),
+            IpProtocol::IPV6 => Some("IPv6"),
+            IpProtocol::GRE => Some("GRE"),
+            IpProtocol::ESP => Some("ESP"),
+            IpProtocol::AH => Some("AH"),
+            IpProtocol::ICMPV6 => Some("ICMPv6"),
+            IpProtocol::OSPF => Some("OSPF"),
+            IpProtocol::SCTP => Some("SCTP"),
+            _ => None,
+        }
+    }
+}
+
+/// Implements the Display trait for IpProtocol, e.g. `ICMP (1)`.
+impl fmt::Display for IpProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.name().unwrap_or("Unknown"), self.0)
+    }
+}
+
+/// Differentiated Services Code Point, the upper six bits of the TOS byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Dscp(pub u8);
+
+impl Dscp {
+    pub const CS0: Dscp = Dscp(0);
+    pub const CS1: Dscp = Dscp(8);
+    pub const AF11: Dscp = Dscp(10);
+    pub const AF12: Dscp = Dscp(12);
+    pub const AF13: Dscp = Dscp(14);
+    pub const CS2: Dscp = Dscp(16);
+    pub const AF21: Dscp = Dscp(18);
+    pub const AF22: Dscp = Dscp(20);
+    pub const AF23: Dscp = Dscp(22);
+    pub const CS3: Dscp = Dscp(24);
+    pub const AF31: Dscp = Dscp(26);
+    pub const AF32: Dscp = Dscp(28);
+    pub const AF33: Dscp = Dscp(30);
+    pub const CS4: Dscp = Dscp(32);
+    pub const AF41: Dscp = Dscp(34);
+    pub const AF42: Dscp = Dscp(36);
+    pub const AF43: Dscp = Dscp(38);
+    pub const CS5: Dscp = Dscp(40);
+    pub const VOICE_ADMIT: Dscp = Dscp(44);
+    pub const EF: Dscp = Dscp(46);
+    pub const CS6: Dscp = Dscp(48);
+    pub const CS7: Dscp = Dscp(56);
+
+    /// Name of the code point, if it is a standard one.
+    pub fn name(&self) -> Option<&'static str> {
+        match *self {
+            Dscp::CS0 => Some("CS0"),
+            Dscp::CS1 => Some("CS1"),
+            Dscp::AF11 => Some("AF11"),
+            Dscp::AF12 => Some("AF12"),
+            Dscp::AF13 => Some("AF13"),
+            Dscp::CS2 => Some("CS2"),
+            Dscp::AF21 => Some("AF21"),
+            Dscp::AF22 => Some("AF22"),
+            Dscp::AF23 => Some("AF23"),
+            Dscp::CS3 => Some("CS3"),
+            Dscp::AF31 => Some("AF31"),
+            Dscp::AF32 => Some("AF32"),
+            Dscp::AF33 => Some("AF33"),
+            Dscp::CS4 => Some("CS4"),
+            Dscp::AF41 => Some("AF41"),
+            Dscp::AF42 => Some("AF42"),
+            Dscp::AF43 => Some("AF43"),
+            Dscp::CS5 => Some("CS5"),
+            Dscp::VOICE_ADMIT => Some("VOICE-ADMIT"),
+            Dscp::EF => Some("EF"),
+            Dscp::CS6 => Some("CS6"),
+            Dscp::CS7 => Some("CS7"),
+            _ => None,
+        }
+    }
+}
+
+/// Implements the Display trait for Dscp, e.g. `EF`, or the value if it has no name.
+impl fmt::Display for Dscp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "{}", self.0),
+        }
+    }
+}
+
+/// Explicit Congestion Notification, the lower two bits of the TOS byte.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum Ecn {
+    /// Not ECN-capable transport.
+    NotEct,
+    /// ECN-capable transport, codepoint 1.
+    Ect1,
+    /// ECN-capable transport, codepoint 0.
+    Ect0,
+    /// Congestion experienced.
+    Ce,
+}
+
+impl Ecn {
+    /// Converts the two ECN bits into an Ecn value.
+    pub fn from_bits(bits: u8) -> Ecn {
+        match bits & 0x03 {
+            0 => Ecn::NotEct,
+            1 => Ecn::Ect1,
+            2 => Ecn::Ect0,
+            _ => Ecn::Ce,
+        }
+    }
+
+    /// Converts the Ecn value into its two bits.
+    pub fn bits(&self) -> u8 {
+        match self {
+            Ecn::NotEct => 0,
+            Ecn::Ect1 => 1,
+            Ecn::Ect0 => 2,
+            Ecn::Ce => 3,
+        }
+    }
+}
+
+/// Implements the Display trait for Ecn, e.g. `ECT(0)`.
+impl fmt::Display for Ecn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ecn::NotEct => write!(f, "Not-ECT"),
+            Ecn::Ect1 => write!(f, "ECT(1)"),
+            Ecn::Ect0 => write!(f, "ECT(0)"),
+            Ecn::Ce => write!(f, "CE"),
+        }
+    }
+}
+
+/// Fragmentation flags, the upper three bits of the flags/fragment offset field.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct FragmentFlags(pub u8);
+
+impl FragmentFlags {
+    /// No flag set.
+    pub const NONE: FragmentFlags = FragmentFlags(0);
+    /// Reserved bit, must be zero.
+    pub const RESERVED: FragmentFlags = FragmentFlags(0b100);
+    /// Don't Fragment.
+    pub const DF: FragmentFlags = FragmentFlags(0b010);
+    /// More Fragments.
+    pub const MF: FragmentFlags = FragmentFlags(0b001);
+
+    /// Checks whether all flags of `other` are set.
+    pub fn contains(&self, other: FragmentFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for FragmentFlags {
+    type Output = FragmentFlags;
+
+    fn bitor(self, other: FragmentFlags) -> FragmentFlags {
+        FragmentFlags(self.0 | other.0)
+    }
+}
+
+/// Implements the Display trait for FragmentFlags, e.g. `DF` or `DF, MF`.
+impl fmt::Display for FragmentFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names = Vec::new();
+        if self.contains(FragmentFlags::RESERVED) {
+            names.push("Reserved");
+        }
+        if self.contains(FragmentFlags::DF) {
+            names.push("DF");
+        }
+        if self.contains(FragmentFlags::MF) {
+            names.push("MF");
+        }
+        if names.is_empty() {
+            write!(f, "None")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipv4::icmp::ICMPPacket;
+    use crate::ipv4::internet_packet::IPV4;
+
+    #[test]
+    fn protocols_are_named_or_kept_as_numbers() {
+        assert_eq!(IpProtocol::ICMP.to_string(), "ICMP (1)");
+        assert_eq!(IpProtocol(17), IpProtocol::UDP);
+        assert_eq!(IpProtocol::SCTP.name(), Some("SCTP"));
+        assert_eq!(IpProtocol(253).name(), None);
+        assert_eq!(IpProtocol(253).to_string(), "Unknown (253)");
+    }
+
+    #[test]
+    fn code_points_are_named_or_kept_as_numbers() {
+        assert_eq!(Dscp(46), Dscp::EF);
+        assert_eq!(Dscp::EF.to_string(), "EF");
+        assert_eq!(Dscp::AF41.to_string(), "AF41");
+        assert_eq!(Dscp::VOICE_ADMIT.to_string(), "VOICE-ADMIT");
+        assert_eq!(Dscp(45).name(), None);
+        assert_eq!(Dscp(45).to_string(), "45");
+    }
+
+    #[test]
+    fn ecn_bits_round_trip() {
+        for bits in 0..4 {
+            assert_eq!(Ecn::from_bits(bits).bits(), bits);
+        }
+        // Only the two lowest bits of the TOS byte count.
+        assert_eq!(Ecn::from_bits(0xb9), Ecn::Ect1);
+        assert_eq!(Ecn::from_bits(0xfe), Ecn::Ect0);
+        let names: Vec<String> = (0..4).map(|bits| Ecn::from_bits(bits).to_string()).collect();
+        assert_eq!(names, ["Not-ECT", "ECT(1)", "ECT(0)", "CE"]);
+    }
+
+    #[test]
+    fn fragment_flags_combine_and_display() {
+        let flags = FragmentFlags::DF | FragmentFlags::MF;
+        assert!(flags.contains(FragmentFlags::DF) && flags.contains(FragmentFlags::MF));
+        assert!(!flags.contains(FragmentFlags::RESERVED));
+        assert!(FragmentFlags::NONE.contains(FragmentFlags::NONE));
+        assert_eq!(flags.to_string(), "DF, MF");
+        assert_eq!(FragmentFlags(0b111).to_string(), "Reserved, DF, MF");
+        assert_eq!(FragmentFlags::NONE.to_string(), "None");
+    }
+
+    #[test]
+    fn fields_pack_back_into_the_header() {
+        let mut packet = IPV4::new_icmp_from_ip(ICMPPacket::new_echo_request(1, 1), 64, [10, 0, 0, 2], [10, 0, 0, 1]);
+        packet.dscp = Dscp::EF;
+        packet.ecn = Ecn::Ce;
+        packet.flags = FragmentFlags::DF | FragmentFlags::MF;
+        packet.fragment_offset = 0x0123;
+        packet.update_checksum();
+
+        let bytes = packet.to_bytes();
+        assert_eq!(bytes[1], 46 << 2 | 3);
+        assert_eq!(bytes[6..8], [0x61, 0x23]);
+
+        let decoded = IPV4::from_bytes(&bytes).unwrap();
+        assert_eq!(decoded.dscp, Dscp::EF);
+        assert_eq!(decoded.ecn, Ecn::Ce);
+        assert_eq!(decoded.flags, FragmentFlags::DF | FragmentFlags::MF);
+        assert_eq!(decoded.fragment_offset, 0x0123);
+        assert_eq!(decoded.to_bytes(), bytes);
+    }
+}
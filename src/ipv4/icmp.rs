@@ -3,34 +3,58 @@
 //! This module defines the structure and functionalities for creating and parsing ICMP packets,
 //! primarily used for network diagnostics such as ping.
 
+use crate::ipv4::checksum::{calculate_checksum, incremental_update, verify, ChecksumStatus};
+
 use std::fmt;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// ICMP type of an echo reply.
+pub const ECHO_REPLY: u8 = 0;
+/// ICMP type of a destination unreachable error.
+pub const DESTINATION_UNREACHABLE: u8 = 3;
+/// ICMP type of an echo request.
+pub const ECHO_REQUEST: u8 = 8;
+/// ICMP type of a time exceeded error.
+pub const TIME_EXCEEDED: u8 = 11;
+
+/// Destination unreachable code: host unreachable.
+pub const CODE_HOST_UNREACHABLE: u8 = 1;
+/// Destination unreachable code: fragmentation needed and DF set.
+pub const CODE_FRAGMENTATION_NEEDED: u8 = 4;
+
+/// Length of the ICMP header: type, code, checksum, identifier and sequence number.
+pub const ICMP_HEADER_LEN: usize = 8;
+
 /// Represents an ICMP packet.
-pub(crate) struct ICMPPacket {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct ICMPPacket {
     /// Type of the ICMP packet.
-    pub(crate) packet_type: u8,
+    pub packet_type: u8,
     /// Code for the ICMP packet.
-    pub(crate) code: u8,
+    pub code: u8,
     /// Checksum for error-checking.
-    pub(crate) checksum: u16,
+    pub checksum: u16,
     /// Identifier, often used for matching requests with replies.
-    pub(crate) identifier: u16,
+    pub identifier: u16,
     /// Sequence number, used to differentiate each packet uniquely.
-    pub(crate) sequence: u16,
+    pub sequence: u16,
     /// Data payload of the ICMP packet.
-    pub(crate) data: Vec<u8>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::hex"))]
+    pub data: Vec<u8>,
 }
 
 
 impl ICMPPacket {
     /// Calculates the total length of the ICMP packet.
-    pub(crate) fn len(&self) -> u16 {
-        28 as u16 + self.data.len() as u16
+    ///
+    /// A packet always has its header, so it is never empty.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> u16 {
+        28 + self.data.len() as u16
     }
 
     /// Converts the ICMP packet into bytes for transmission.
-    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+    pub fn to_bytes(&self) -> Vec<u8> {
         let mut bytes = Vec::new();
         bytes.push(self.packet_type);
         bytes.push(self.code);
@@ -41,26 +65,48 @@ impl ICMPPacket {
         bytes
     }
 
+    /// Recomputes the checksum, after fields or data were changed.
+    pub fn update_checksum(&mut self) {
+        self.checksum = 0;
+        self.checksum = calculate_checksum(&self.to_bytes());
+    }
+
+    /// Changes the sequence number, updating the checksum without summing the data again.
+    pub fn set_sequence(&mut self, sequence: u16) {
+        self.checksum = incremental_update(self.checksum, self.sequence, sequence);
+        self.sequence = sequence;
+    }
+
+    /// Verifies the checksum, of an ICMP message received whole.
+    pub fn checksum_status(&self) -> ChecksumStatus {
+        verify(&self.to_bytes(), 2)
+    }
+
     /// Converts a byte array into an ICMP packet.
-    pub(crate) fn from_bytes(bytes: &[u8]) -> ICMPPacket {
+    ///
+    /// Returns `None` if the bytes are too short to hold the ICMP header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<ICMPPacket> {
+        if bytes.len() < ICMP_HEADER_LEN {
+            return None;
+        }
         let packet_type = bytes[0];
         let code = bytes[1];
         let checksum = u16::from_be_bytes([bytes[2], bytes[3]]);
         let identifier = u16::from_be_bytes([bytes[4], bytes[5]]);
         let sequence = u16::from_be_bytes([bytes[6], bytes[7]]);
-        let data = bytes[8..].to_vec();
+        let data = bytes[ICMP_HEADER_LEN..].to_vec();
 
-        ICMPPacket {
+        Some(ICMPPacket {
             packet_type,
             code,
             checksum,
             identifier,
             sequence,
             data,
-        }
+        })
     }
 
-    // pub(crate) fn new() -> ICMPPacket {
+    // pub fn new() -> ICMPPacket {
     //     ICMPPacket {
     //         packet_type: 0,
     //         code: 0,
@@ -75,16 +121,23 @@ impl ICMPPacket {
     /// Creates a new ICMP Echo Request packet.
     /// 
     /// # Arguments
+    ///* `identifier` - Identifier of the packet, telling the pinging sessions apart.
     ///* `sequence` - Sequence number of the packet.
     /// 
-    pub(crate) fn new_echo_request(sequence: u16) -> ICMPPacket {
+    pub fn new_echo_request(identifier: u16, sequence: u16) -> ICMPPacket {
         let now = SystemTime::now();
-        let data = time_to_bytes(now);
-
-        let identifier: u16 = std::process::id() as u16;
+        ICMPPacket::new_echo_request_with_data(identifier, sequence, time_to_bytes(now))
+    }
 
+    /// Creates a new ICMP Echo Request packet carrying the given data.
+    ///
+    /// # Arguments
+    /// * `identifier` - Identifier of the packet, telling the pinging sessions apart.
+    /// * `sequence` - Sequence number of the packet.
+    /// * `data` - Data the target echoes back, e.g. a timestamp and a fill pattern.
+    pub fn new_echo_request_with_data(identifier: u16, sequence: u16, data: Vec<u8>) -> ICMPPacket {
         let mut packet = ICMPPacket {
-            packet_type: 0x08,
+            packet_type: ECHO_REQUEST,
             code: 0x00,
             checksum: 0,
             identifier,
@@ -94,21 +147,58 @@ impl ICMPPacket {
         let bytee = packet.to_bytes();
 
         packet.checksum = calculate_checksum(&bytee);
-        return packet;
+        packet
+    }
+
+    /// Creates the ICMP Echo Reply answering an Echo Request.
+    ///
+    /// The identifier, sequence number and data are echoed back unchanged.
+    pub fn new_echo_reply(request: &ICMPPacket) -> ICMPPacket {
+        let mut packet = ICMPPacket {
+            packet_type: ECHO_REPLY,
+            code: 0x00,
+            checksum: 0,
+            identifier: request.identifier,
+            sequence: request.sequence,
+            data: request.data.clone(),
+        };
+        packet.checksum = calculate_checksum(&packet.to_bytes());
+        packet
+    }
+
+    /// Creates an ICMP error message about a packet that could not be delivered.
+    ///
+    /// # Arguments
+    /// * `packet_type` - Type of the error, e.g. `TIME_EXCEEDED`.
+    /// * `code` - Code of the error.
+    /// * `next_hop_mtu` - MTU of the next hop for fragmentation needed errors, 0 otherwise.
+    /// * `original` - Bytes of the offending IPv4 packet; its header and first 8 data bytes are quoted.
+    pub fn new_error(packet_type: u8, code: u8, next_hop_mtu: u16, original: &[u8]) -> ICMPPacket {
+        let mut packet = ICMPPacket {
+            packet_type,
+            code,
+            checksum: 0,
+            // The identifier field is unused in error messages.
+            identifier: 0,
+            sequence: next_hop_mtu,
+            data: original[..original.len().min(28)].to_vec(),
+        };
+        packet.checksum = calculate_checksum(&packet.to_bytes());
+        packet
     }
 }
 
 /// Implements the Display trait for ICMPPacket.
 impl fmt::Display for ICMPPacket {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "ICMP: -----ICMP Header-----\n")?;
-        write!(f, "ICMP:\n")?;
-        write!(f, "ICMP: type= {}\n", self.packet_type)?;
-        write!(f, "ICMP: Code= {}\n", self.code)?;
-        write!(f, "ICMP: checksum= 0x{:x}\n", self.checksum)?;
-        write!(f, "ICMP: identifier= 0x{:x}\n", self.identifier)?;
-        write!(f, "ICMP: sequence= 0x{}\n", self.sequence)?;
-        write!(f, "ICMP: -----ICMP Header-----\n")
+        writeln!(f, "ICMP: -----ICMP Header-----")?;
+        writeln!(f, "ICMP:")?;
+        writeln!(f, "ICMP: type= {}", self.packet_type)?;
+        writeln!(f, "ICMP: Code= {}", self.code)?;
+        writeln!(f, "ICMP: checksum= 0x{:x} ({})", self.checksum, self.checksum_status())?;
+        writeln!(f, "ICMP: identifier= 0x{:x}", self.identifier)?;
+        writeln!(f, "ICMP: sequence= 0x{}", self.sequence)?;
+        writeln!(f, "ICMP: -----ICMP Header-----")
     }
 }
 
@@ -133,27 +223,3 @@ fn time_to_bytes(time: SystemTime) -> Vec<u8> {
         }
     }
 }
-
-/// Calculates the checksum for the ICMP packet.
-fn calculate_checksum(data: &Vec<u8>) -> u16 {
-    let mut sum = 0u32; // Using u32 to avoid overflow during addition
-
-    // Processing each 16-bit block
-    let mut iter: std::slice::Chunks<'_, u8> = data.chunks(2);
-    while let Some(chunk) = iter.next() {
-        let word = if chunk.len() == 2 {
-            ((chunk[0] as u16) << 8) + chunk[1] as u16 // Combinining two bytes into one word
-        } else {
-            (chunk[0] as u16) << 8 // If odd number of bytes, padding the last byte with zero
-        };
-        sum += word as u32;
-    }
-
-    // Add carry if any
-    while (sum >> 16) > 0 {
-        sum = (sum & 0xFFFF) + (sum >> 16);
-    }
-
-    // One's complement of the sum
-    !(sum as u16)
-}
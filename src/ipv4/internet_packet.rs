@@ -1,55 +1,82 @@
+use crate::ipv4::checksum::{
+    calculate_checksum, incremental_update, pseudo_header, verify, verify_segment, ChecksumStatus,
+};
+use crate::ipv4::fields::{Dscp, Ecn, FragmentFlags, IpProtocol};
 use crate::ipv4::icmp::ICMPPacket;
 
 /// Represents an IPv4 packet.
-pub(crate) struct IPV4 {
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct IPV4 {
     /// Version and header length of the packet.
-    pub(crate) version_header_len: u8,
-    /// Differentiated Services Code Point (DSCP) and Explicit Congestion Notification (ECN).
-    pub(crate) dscp_ecn: u8,
+    pub version_header_len: u8,
+    /// Differentiated Services Code Point (DSCP).
+    pub dscp: Dscp,
+    /// Explicit Congestion Notification (ECN).
+    pub ecn: Ecn,
     /// Total length of the packet.
-    pub(crate) total_length: u16,
+    pub total_length: u16,
     /// Identification of the packet.
-    pub(crate) identification: u16,
-    /// Flags and fragment offset of the packet.
-    pub(crate) flags_fragment_offset: u16,
+    pub identification: u16,
+    /// Fragmentation flags of the packet.
+    pub flags: FragmentFlags,
+    /// Fragment offset of the packet, in units of 8 bytes.
+    pub fragment_offset: u16,
     /// Time to live of the packet.
-    pub(crate) ttl: u8,
+    pub ttl: u8,
     /// Protocol of the packet.
-    pub(crate) protocol: u8,
+    pub protocol: IpProtocol,
     /// Header checksum of the packet.
-    pub(crate) header_checksum: u16,
+    pub header_checksum: u16,
     /// Source address of the packet.
-    pub(crate) source_add: [u8; 4],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::ipv4_address"))]
+    pub source_add: [u8; 4],
     /// Destination address of the packet.
-    pub(crate) destination_add: [u8; 4],
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::ipv4_address"))]
+    pub destination_add: [u8; 4],
     /// Options of the packet.
-    pub(crate) options: Option<Vec<u8>>,
+    #[cfg_attr(feature = "serde", serde(serialize_with = "crate::serialize::optional_hex"))]
+    pub options: Option<Vec<u8>>,
     /// Data payload of the packet.
-    pub(crate) datagram: ICMPPacket,
+    pub datagram: ICMPPacket,
 }
 
 impl IPV4 {
 
     /// Creates a new IPv4 packet from a byte array.
-    pub(crate) fn from_bytes(bytes: &[u8]) -> IPV4 {
+    ///
+    /// Returns `None` if the bytes are too short to hold the fixed header, or the payload
+    /// within the total length too short to hold an ICMP header.
+    pub fn from_bytes(bytes: &[u8]) -> Option<IPV4> {
+        if bytes.len() < 20 {
+            return None;
+        }
         let version_header_len = bytes[0];
-        let dscp_ecn = bytes[1];
+        let dscp = Dscp(bytes[1] >> 2);
+        let ecn = Ecn::from_bits(bytes[1]);
         let total_length = u16::from_be_bytes([bytes[2], bytes[3]]);
         let identification = u16::from_be_bytes([bytes[4], bytes[5]]);
         let flags_fragment_offset = u16::from_be_bytes([bytes[6], bytes[7]]);
+        let flags = FragmentFlags((flags_fragment_offset >> 13) as u8);
+        let fragment_offset = flags_fragment_offset & 0x1fff;
         let ttl = bytes[8];
-        let protocol = bytes[9];
+        let protocol = IpProtocol(bytes[9]);
         let header_checksum = u16::from_be_bytes([bytes[10], bytes[11]]);
         let source_add = [bytes[12], bytes[13], bytes[14], bytes[15]];
         let destination_add = [bytes[16], bytes[17], bytes[18], bytes[19]];
-        let options = None;
-        let datagram = ICMPPacket::from_bytes(&bytes[20..]);
-        IPV4 {
+        // Options sit between the fixed header and the payload, and anything past the
+        // total length is link layer padding, e.g. of minimum size Ethernet frames.
+        let header_len = ((version_header_len & 0x0f) as usize * 4).clamp(20, bytes.len());
+        let end = (total_length as usize).clamp(header_len, bytes.len());
+        let options = if header_len > 20 { Some(bytes[20..header_len].to_vec()) } else { None };
+        let datagram = ICMPPacket::from_bytes(&bytes[header_len..end])?;
+        Some(IPV4 {
             version_header_len,
-            dscp_ecn,
+            dscp,
+            ecn,
             total_length,
             identification,
-            flags_fragment_offset,
+            flags,
+            fragment_offset,
             ttl,
             protocol,
             header_checksum,
@@ -57,7 +84,7 @@ impl IPV4 {
             destination_add,
             options,
             datagram,
-        }
+        })
     }
 
     /// Creates a new IPv4 packet.
@@ -66,84 +93,159 @@ impl IPV4 {
     /// * `datagram` - Data payload of the packet.
     /// * `protocol` - Protocol of the packet.
     /// * `ttl` - Time to live of the packet.
+    /// * `source_add` - Source address of the packet.
+    /// * `destination_add` - Destination address of the packet.
     /// * `options` - Options of the packet.
     /// 
     /// # Returns
     /// A new IPv4 packet.
-    pub(crate) fn new(
+    pub fn new(
         datagram: ICMPPacket,
-        protocol: u8,
+        protocol: IpProtocol,
         ttl: u8,
         source_add: [u8; 4],
+        destination_add: [u8; 4],
         options: Option<Vec<u8>>,
     ) -> IPV4 {
         let mut version_header_len = 0x45; // First 4 bits for version, next 4 bits for header length
-        if options.is_some() {
-            let len: usize = options.as_ref().unwrap().len();
+        let mut options = options;
+        if let Some(bytes) = options.as_mut() {
+            let len: usize = bytes.len();
 
+            // The header holds at most 40 bytes of options, padded to whole words
+            // with End of Option List bytes.
             let k = ceiling_division(len, 4);
-            if k <= 4 {
-                version_header_len = version_header_len + k as u8;
+            if k <= 10 {
+                version_header_len += k;
+                bytes.resize(k as usize * 4, 0);
+            } else {
+                options = None;
             }
         }
-        let total_length = datagram.len();
+        let total_length = datagram.len() + options.as_ref().map_or(0, |bytes| bytes.len() as u16);
 
         let mut ipv4 = IPV4 {
             version_header_len,
-            dscp_ecn: 0,
+            dscp: Dscp::CS0,
+            ecn: Ecn::NotEct,
             total_length,
             identification: 0,
-            flags_fragment_offset: 0,
+            flags: FragmentFlags::NONE,
+            fragment_offset: 0,
             ttl,
             protocol,
             header_checksum: 0,
             source_add, //[172, 16, 67, 126],     //[192, 168, 0, 101], // Source IP Address of my computer
-            destination_add: [142, 251, 35, 174], //Destination IP Address of Google.com
+            destination_add,
             options,
             datagram,
         };
-        let bytee = ipv4.to_bytes();
-        let checksum = calculate_ipv4_checksum(bytee);
-        ipv4.header_checksum = checksum;
-        return ipv4;
+        ipv4.header_checksum = calculate_checksum(&ipv4.header_to_bytes());
+        ipv4
+    }
+
+    /// Recomputes the header checksum, after header fields were changed.
+    pub fn update_checksum(&mut self) {
+        self.header_checksum = 0;
+        self.header_checksum = calculate_checksum(&self.header_to_bytes());
+    }
+
+    /// Changes the TTL, updating the header checksum without summing the header again.
+    pub fn set_ttl(&mut self, ttl: u8) {
+        let protocol = self.protocol.0;
+        self.header_checksum = incremental_update(
+            self.header_checksum,
+            u16::from_be_bytes([self.ttl, protocol]),
+            u16::from_be_bytes([ttl, protocol]),
+        );
+        self.ttl = ttl;
+    }
+
+    /// Length of the header, the fixed 20 bytes and the options.
+    pub fn header_len(&self) -> usize {
+        20 + self.options.as_ref().map_or(0, Vec::len)
+    }
+
+    /// Verifies the header checksum.
+    ///
+    /// A zero checksum is reported as offloaded, as left for the network card to fill in.
+    pub fn header_checksum_status(&self) -> ChecksumStatus {
+        match verify(&self.header_to_bytes(), 10) {
+            ChecksumStatus::Incorrect { expected } if self.header_checksum == 0 => ChecksumStatus::Offloaded { expected },
+            status => status,
+        }
+    }
+
+    /// Verifies the checksum of the ICMP message, UDP datagram or TCP segment carried.
+    ///
+    /// The checksum of other protocols, of fragments and of truncated packets is not verified,
+    /// nor that of UDP datagrams whose sender left it out.
+    pub fn payload_checksum_status(&self) -> ChecksumStatus {
+        let payload = self.datagram.to_bytes();
+        if self.flags.contains(FragmentFlags::MF)
+            || self.fragment_offset != 0
+            || self.header_len() + payload.len() < self.total_length as usize
+        {
+            return ChecksumStatus::Unverified;
+        }
+        let pseudo_header = pseudo_header(self.source_add, self.destination_add, self.protocol, payload.len() as u16);
+        match self.protocol {
+            IpProtocol::ICMP => self.datagram.checksum_status(),
+            IpProtocol::UDP if payload.len() >= 8 && (payload[6], payload[7]) != (0, 0) => {
+                verify_segment(&pseudo_header, &payload, 6)
+            }
+            IpProtocol::TCP if payload.len() >= 20 => verify_segment(&pseudo_header, &payload, 16),
+            _ => ChecksumStatus::Unverified,
+        }
     }
 
     /// C0nverts the IPv4 packet into bytes for transmission.
-    pub(crate) fn to_bytes(&mut self) -> Vec<u8> {
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = self.header_to_bytes();
+        result.append(&mut self.datagram.to_bytes());
+        result
+    }
+
+    /// Converts the header of the IPv4 packet, options included, into bytes.
+    fn header_to_bytes(&self) -> Vec<u8> {
         let mut result = Vec::new();
         result.push(self.version_header_len);
-        result.push(self.dscp_ecn);
+        result.push(self.dscp.0 << 2 | self.ecn.bits());
         let total_length = u16_to_bytes_big_endian(self.total_length);
         result.push(total_length[0]);
         result.push(total_length[1]);
         let identification = u16_to_bytes_big_endian(self.identification);
         result.push(identification[0]);
         result.push(identification[1]);
-        let flags_fragment_offset = u16_to_bytes_big_endian(self.flags_fragment_offset);
+        let flags_fragment_offset = u16_to_bytes_big_endian(
+            (self.flags.0 as u16) << 13 | (self.fragment_offset & 0x1fff),
+        );
         result.push(flags_fragment_offset[0]);
         result.push(flags_fragment_offset[1]);
         result.push(self.ttl);
-        result.push(self.protocol);
+        result.push(self.protocol.0);
         let header_checksum = u16_to_bytes_big_endian(self.header_checksum);
         result.push(header_checksum[0]);
         result.push(header_checksum[1]);
         result.append(&mut self.source_add.to_vec());
         result.append(&mut self.destination_add.to_vec());
-        let options = self.options.clone();
-        if options.is_some() {
-            let mut p = options.as_ref().unwrap().clone();
-            result.append(&mut p);
+        if let Some(options) = self.options.as_ref() {
+            result.extend_from_slice(options);
         }
-        result.append(&mut self.datagram.to_bytes());
-        return result;
+        result
     }
 
     ///Creates a new ICMP packet from an IPv4 packet.
-    pub(crate) fn new_icmp_from_ip(datagram: ICMPPacket, ttl: u8, source_add: [u8; 4]) -> IPV4 {
-        IPV4::new(datagram, 1, ttl,source_add,None)
+    pub fn new_icmp_from_ip(
+        datagram: ICMPPacket,
+        ttl: u8,
+        source_add: [u8; 4],
+        destination_add: [u8; 4],
+    ) -> IPV4 {
+        IPV4::new(datagram, IpProtocol::ICMP, ttl, source_add, destination_add, None)
     }
 
-    /*    pub(crate) fn new_with_ttl(datagram: ICMPPacket, ttl: u8,
+    /*    pub fn new_with_ttl(datagram: ICMPPacket, ttl: u8,
                                total_length: [u8; 2], identification: [u8; 2],
                                source_add: [u8; 4], destination_add: [u8; 4]) -> IPacket {
         IPacket {
@@ -175,29 +277,39 @@ impl IPV4 {
 impl std::fmt::Display for IPV4 {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "\nIPV4 Packet: -----Packet Header-----")?;
-        write!(f, "IPV4: Version/Header Length: {:x}\n", self.version_header_len)?;
-        write!(f, "IPV4: DSCP/ECN: {}\n", self.dscp_ecn)?;
-        write!(f, "IPV4: Total Length: {:x}\n", self.total_length)?;
-        write!(f, "IPV4: Identification: {}\n", self.identification)?;
-        write!(f, "IPV4: Flags/Fragment Offset: {}\n", self.flags_fragment_offset)?;
-        write!(f, "IPV4: TTL: {}\n", self.ttl)?;
-        write!(f, "IPV4: Protocol: {}\n", self.protocol)?;
-        write!(f, "IPV4: Header Checksum: {}\n", self.header_checksum)?;
-        write!(
+        writeln!(f, "IPV4: Version/Header Length: {:x}", self.version_header_len)?;
+        writeln!(f, "IPV4: DSCP: {}", self.dscp)?;
+        writeln!(f, "IPV4: ECN: {}", self.ecn)?;
+        writeln!(f, "IPV4: Total Length: {:x}", self.total_length)?;
+        writeln!(f, "IPV4: Identification: {}", self.identification)?;
+        writeln!(f, "IPV4: Flags: {}", self.flags)?;
+        writeln!(f, "IPV4: Fragment Offset: {}", self.fragment_offset)?;
+        writeln!(f, "IPV4: TTL: {}", self.ttl)?;
+        writeln!(f, "IPV4: Protocol: {}", self.protocol)?;
+        writeln!(
+            f,
+            "IPV4: Header Checksum: 0x{:04x} ({})",
+            self.header_checksum,
+            self.header_checksum_status()
+        )?;
+        writeln!(
             f,
-            "IPV4: Source Address: {}\n",
+            "IPV4: Source Address: {}",
             format_ipv4_address(&self.source_add)
         )?;
-        write!(
+        writeln!(
             f,
-            "IPV4: Destination Address: {}\n",
+            "IPV4: Destination Address: {}",
             format_ipv4_address(&self.destination_add)
         )?;
 
         if let Some(ref options) = self.options {
-            write!(f, "IPV4: Options: {:?}\n", options)?;
+            writeln!(f, "IPV4: Options: {:?}", options)?;
         } else {
-            write!(f, "IPV4: Options: None\n")?;
+            writeln!(f, "IPV4: Options: None")?;
+        }
+        if matches!(self.protocol, IpProtocol::UDP | IpProtocol::TCP) {
+            writeln!(f, "IPV4: {} Checksum: {}", self.protocol, self.payload_checksum_status())?;
         }
         write!(f, "IPV4 Packet: -----Packet Header-----\n\n")?;
         write!(f, "Datagram: \n{}", self.datagram)
@@ -206,13 +318,13 @@ impl std::fmt::Display for IPV4 {
 
 // --------------HELPER FUNCTIONS----------------
 
-/// Calculates the checksum of an IPv4 packet.
+/// Divides, rounding up.
 fn ceiling_division(dividend: usize, divisor: usize) -> u8 {
     if divisor == 0 {
         panic!("Attempted to divide by zero");
     }
 
-    ((dividend + divisor - 1) / divisor) as u8
+    dividend.div_ceil(divisor) as u8
 }
 
 /// Converts a u16 value into a [u8; 2] array in big-endian order.
@@ -223,28 +335,7 @@ fn u16_to_bytes_big_endian(value: u16) -> [u8; 2] {
     ]
 }
 
-/// Calculates the checksum of an IPv4 packet.
-fn calculate_ipv4_checksum(header: Vec<u8>) -> u16 {
-    assert!(header.len() % 2 == 0, "Header length must be even");
-
-    let mut sum = 0u32;
-
-    // Iterate over each 16-bit word
-    for word in header.chunks(2) {
-        let part = u16::from_be_bytes([word[0], word[1]]);
-        sum += part as u32;
-    }
-
-    // Add carry if present
-    while (sum >> 16) != 0 {
-        sum = (sum & 0xFFFF) + (sum >> 16);
-    }
-
-    // Return the one's complement of the sum as a [u8; 2] array
-    !(sum as u16)
-}
-
 /// Formats an IPv4 address into a human-readable string.
-fn format_ipv4_address(addr: &[u8; 4]) -> String {
+pub fn format_ipv4_address(addr: &[u8; 4]) -> String {
     format!("{}.{}.{}.{}", addr[0], addr[1], addr[2], addr[3])
 }
@@ -1,4 +1,7 @@
 // Module declarations for each file in the network directory
+pub mod checksum;
+pub mod fields;
 pub mod icmp;
 pub mod internet_packet;
+pub mod subnet;
 
@@ -0,0 +1,59 @@
+//! Helpers for IPv4 subnets and CIDR ranges.
+
+use std::net::Ipv4Addr;
+
+/// Shortest prefix length whose hosts are listed, a `/16` of 65534 hosts.
+///
+/// Larger ranges would take hours to probe and gigabytes to list, up to 16 GiB for `/0`.
+pub const MIN_SCAN_PREFIX: u32 = 16;
+
+/// Parses a CIDR range into the list of its host addresses.
+///
+/// An address without a prefix length is treated as a `/32`. Returns `None` if the
+/// range is invalid or shorter than `MIN_SCAN_PREFIX`.
+pub fn parse_cidr(cidr: &str) -> Option<Vec<[u8; 4]>> {
+    let (address, prefix) = match cidr.split_once('/') {
+        Some((address, prefix)) => (address, prefix.parse::<u32>().ok()?),
+        None => (cidr, 32),
+    };
+    if prefix > 32 {
+        return None;
+    }
+    let address: Ipv4Addr = address.parse().ok()?;
+
+    hosts_in_subnet(address.octets(), prefix)
+}
+
+/// Lists the host addresses of the subnet containing `address`.
+///
+/// The network and broadcast addresses are left out, except for `/31` and `/32`
+/// where every address is a host. Returns `None` for prefixes shorter than `MIN_SCAN_PREFIX`.
+pub fn hosts_in_subnet(address: [u8; 4], prefix: u32) -> Option<Vec<[u8; 4]>> {
+    if prefix < MIN_SCAN_PREFIX {
+        return None;
+    }
+    let mask = prefix_to_mask(prefix);
+    let network = u32::from_be_bytes(address) & mask;
+    let broadcast = network | !mask;
+    let (first, last) = if prefix >= 31 {
+        (network, broadcast)
+    } else {
+        (network + 1, broadcast - 1)
+    };
+
+    Some((first..=last).map(|ip| ip.to_be_bytes()).collect())
+}
+
+/// Converts a netmask such as `255.255.255.0` into its prefix length.
+pub fn netmask_to_prefix(netmask: [u8; 4]) -> u32 {
+    u32::from_be_bytes(netmask).leading_ones()
+}
+
+/// Converts a prefix length into the matching netmask as an integer.
+pub fn prefix_to_mask(prefix: u32) -> u32 {
+    if prefix == 0 {
+        0
+    } else {
+        u32::MAX << (32 - prefix.min(32))
+    }
+}
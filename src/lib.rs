@@ -0,0 +1,52 @@
+//! Low-level networking in Rust: Ethernet, ARP, IPv4 and ICMP codecs, transports
+//! exchanging raw packets with the network, and a pinger engine on top of them.
+//!
+//! Packets are plain structs with public fields, built with constructors such as
+//! `IPV4::new_icmp_from_ip` or `ARPPacket::new_request`, encoded with `to_bytes` and
+//! decoded with `from_bytes`. A `Transport` carries them on a real interface, a TUN
+//! or TAP device, a raw or ICMP socket, or a simulated network, and a `Pinger` sends
+//! echo requests to many targets over any of them. Every pinger of the process
+//! has an ICMP identifier of its own, and pingers running at once can share one
+//! transport through a `pinger::demux::Demultiplexer`.
+//!
+//! ```no_run
+//! use rust_network::pinger::{PingEvent, Pinger, PingerOptions};
+//! use rust_network::transport::simulated::SimulatedNetwork;
+//! use std::sync::Arc;
+//!
+//! let options = PingerOptions {
+//!     source: [10, 0, 0, 2],
+//!     count: Some(3),
+//!     ..PingerOptions::default()
+//! };
+//! let mut pinger = Pinger::new(Arc::new(SimulatedNetwork::lab(1)), options)?;
+//! pinger.add_target([10, 0, 0, 10]);
+//! pinger.run(|event| {
+//!     if let PingEvent::Reply { sequence, rtt, .. } = event {
+//!         println!("icmp_seq={} time={:?}", sequence, rtt);
+//!     }
+//! })?;
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+// Module declarations for each layer and facility of the library
+pub mod arp;
+pub mod dns;
+pub mod ethernet_frame;
+pub mod interface;
+pub mod ipv4;
+pub mod mac_address;
+pub mod oui;
+pub mod pcap_file;
+pub mod pinger;
+#[cfg(feature = "serde")]
+mod serialize;
+pub mod transport;
+
+pub use arp::ARPPacket;
+pub use ethernet_frame::{EthernetFrame, VlanTag};
+pub use ipv4::icmp::ICMPPacket;
+pub use ipv4::internet_packet::IPV4;
+pub use mac_address::MacAddr;
+pub use pinger::{PingEvent, Pinger, PingerOptions};
+pub use transport::Transport;
@@ -0,0 +1,204 @@
+//! Module for MAC (Media Access Control) addresses.
+//!
+//! This module defines the MAC address type used by the Ethernet and ARP layers, with
+//! parsing of the common notations and vendor lookup by OUI.
+
+use crate::oui;
+
+use std::fmt;
+use std::str::FromStr;
+
+/// Represents a 48-bit MAC address.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// Broadcast address, `ff:ff:ff:ff:ff:ff`.
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+    /// All-zero address, used for unknown targets in ARP requests.
+    pub const ZERO: MacAddr = MacAddr([0; 6]);
+
+    /// Returns the bytes of the address.
+    pub fn octets(&self) -> [u8; 6] {
+        self.0
+    }
+
+    /// Checks whether this is the broadcast address.
+    pub fn is_broadcast(&self) -> bool {
+        *self == MacAddr::BROADCAST
+    }
+
+    /// Checks whether the address is a group address (I/G bit set), broadcast included.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+
+    /// Checks whether the address is an individual address (I/G bit clear).
+    pub fn is_unicast(&self) -> bool {
+        !self.is_multicast()
+    }
+
+    /// Checks whether the address was assigned locally rather than by the vendor (U/L bit set).
+    pub fn is_locally_administered(&self) -> bool {
+        self.0[0] & 0x02 != 0
+    }
+
+    /// Looks up the vendor of the address by its OUI.
+    ///
+    /// Locally administered and group addresses have no vendor.
+    pub fn vendor(&self) -> Option<&'static str> {
+        if !self.is_unicast() || self.is_locally_administered() {
+            return None;
+        }
+        oui::vendor(&self.0)
+    }
+
+    /// Describes the address for decodes: its vendor, or what kind of address it is.
+    pub fn describe(&self) -> &'static str {
+        if self.is_broadcast() {
+            "Broadcast"
+        } else if self.is_multicast() {
+            "Multicast"
+        } else if self.is_locally_administered() {
+            "Locally administered"
+        } else {
+            self.vendor().unwrap_or("Unknown vendor")
+        }
+    }
+}
+
+impl From<[u8; 6]> for MacAddr {
+    fn from(octets: [u8; 6]) -> MacAddr {
+        MacAddr(octets)
+    }
+}
+
+/// Implements the Display trait for MacAddr, e.g. `0a:00:27:00:00:01`.
+impl fmt::Display for MacAddr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let a = self.0;
+        write!(
+            f,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a[0], a[1], a[2], a[3], a[4], a[5]
+        )
+    }
+}
+
+/// Error returned when a string is not a valid MAC address.
+#[derive(Debug, PartialEq)]
+pub struct ParseMacAddrError(String);
+
+impl fmt::Display for ParseMacAddrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid MAC address: {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseMacAddrError {}
+
+/// Parses a MAC address in colon (`00:1a:2b:3c:4d:5e`), dash (`00-1A-2B-3C-4D-5E`)
+/// or Cisco dotted (`001a.2b3c.4d5e`) notation.
+impl FromStr for MacAddr {
+    type Err = ParseMacAddrError;
+
+    fn from_str(s: &str) -> Result<MacAddr, ParseMacAddrError> {
+        let error = || ParseMacAddrError(s.to_string());
+
+        // Colon and dash notations split into six one-byte groups, the dotted
+        // notation into three two-byte groups.
+        let (groups, group_len): (Vec<&str>, usize) = if s.contains(':') {
+            (s.split(':').collect(), 2)
+        } else if s.contains('-') {
+            (s.split('-').collect(), 2)
+        } else if s.contains('.') {
+            (s.split('.').collect(), 4)
+        } else {
+            return Err(error());
+        };
+        if groups.len() * group_len != 12 {
+            return Err(error());
+        }
+
+        let mut octets = [0; 6];
+        let mut i = 0;
+        for group in groups {
+            // Single digit groups such as `0:1a:...` are accepted in the colon and dash notations.
+            let group = if group_len == 2 && group.len() == 1 {
+                format!("0{}", group)
+            } else {
+                group.to_string()
+            };
+            if group.len() != group_len || !group.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(error());
+            }
+            for pair in group.as_bytes().chunks(2) {
+                let pair = std::str::from_utf8(pair).map_err(|_| error())?;
+                octets[i] = u8::from_str_radix(pair, 16).map_err(|_| error())?;
+                i += 1;
+            }
+        }
+
+        Ok(MacAddr(octets))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Address every notation of the parsing tests spells.
+    const ADDRESS: MacAddr = MacAddr([0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+
+    #[test]
+    fn every_notation_parses() {
+        for s in ["00:1a:2b:3c:4d:5e", "00-1A-2B-3C-4D-5E", "001a.2b3c.4d5e", "0:1a:2b:3c:4d:5e", "0-1A-2b-3C-4d-5E"] {
+            assert_eq!(s.parse::<MacAddr>(), Ok(ADDRESS), "{}", s);
+        }
+    }
+
+    #[test]
+    fn malformed_addresses_are_rejected() {
+        for s in [
+            "",
+            "001a2b3c4d5e",
+            "00:1a:2b:3c:4d",
+            "00:1a:2b:3c:4d:5e:6f",
+            "00:1a:2b:3c:4d-5e",
+            "00:1a:2b:3c:4d:5g",
+            "000:1a:2b:3c:4d:5",
+            "001a.2b3c",
+            "01a.2b3c.4d5e5",
+            "+1:1a:2b:3c:4d:5e",
+        ] {
+            assert_eq!(s.parse::<MacAddr>(), Err(ParseMacAddrError(s.to_string())), "{}", s);
+        }
+    }
+
+    #[test]
+    fn display_pads_every_byte() {
+        assert_eq!(MacAddr([0x0a, 0, 0x27, 0, 0, 0x01]).to_string(), "0a:00:27:00:00:01");
+        assert_eq!(ADDRESS.to_string().parse::<MacAddr>(), Ok(ADDRESS));
+    }
+
+    #[test]
+    fn address_kinds_follow_the_first_byte() {
+        assert!(ADDRESS.is_unicast() && !ADDRESS.is_multicast() && !ADDRESS.is_locally_administered());
+        assert!(MacAddr::BROADCAST.is_broadcast() && MacAddr::BROADCAST.is_multicast());
+        let multicast = MacAddr([0x01, 0x00, 0x5e, 0x00, 0x00, 0x01]);
+        assert!(multicast.is_multicast() && !multicast.is_broadcast());
+        let local = MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]);
+        assert!(local.is_unicast() && local.is_locally_administered());
+    }
+
+    #[test]
+    fn only_vendor_assigned_addresses_have_a_vendor() {
+        assert_eq!(MacAddr([0x00, 0x0c, 0x29, 0x01, 0x02, 0x03]).describe(), "VMware");
+        assert_eq!(ADDRESS.vendor(), None);
+        assert_eq!(ADDRESS.describe(), "Unknown vendor");
+        assert_eq!(MacAddr([0x52, 0x54, 0x00, 0x12, 0x34, 0x56]).describe(), "Locally administered");
+        assert_eq!(MacAddr([0x01, 0x00, 0x0c, 0xcc, 0xcc, 0xcc]).vendor(), None);
+        assert_eq!(MacAddr([0x01, 0x00, 0x0c, 0xcc, 0xcc, 0xcc]).describe(), "Multicast");
+        assert_eq!(MacAddr::BROADCAST.describe(), "Broadcast");
+    }
+}
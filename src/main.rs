@@ -1,27 +1,262 @@
+mod cli;
 mod commands;
-mod ethernet_frame;
-mod ipv4;
+mod interrupt;
+mod output;
 
-use commands::ping::{recv_icmp_response, send_icmp_echo_request};
+use cli::{completions, print_command_help, print_help, GlobalOptions, OutputFormat};
+use commands::arping::{arping, ArpingOptions};
+use commands::arpscan::arpscan;
+use commands::craft::{craft, CraftOptions};
+use commands::decode::{decode, DecodeOptions};
+use commands::interfaces::interfaces;
+use commands::lookup::lookup;
+use commands::monitor::{monitor, MonitorOptions};
+use commands::ping::{ping, PingOptions};
+use commands::pmtu::{pmtu, PmtuOptions};
+use commands::responder::responder;
+use commands::sniff::{count_from_args, sniff};
+use commands::sweep::sweep;
+use commands::traceroute::{traceroute, TracerouteOptions};
+use output::Output;
+use rust_network::interface::lookup_interface;
+use rust_network::interface::route::{lookup_neighbor, next_hop};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::mac_address::MacAddr;
+use rust_network::pinger::PingerOptions;
+use rust_network::transport::{Layer, Transport};
 
-fn main() {
-    let mut sequence = 0;
+use std::process::ExitCode;
 
-    for _ in 0..100000 {
-        sequence += 1;
-        send_and_recv(sequence);
+/// Name of the tool in the help and the completion scripts.
+const PROGRAM: &str = "rust_network";
+
+/// Address pinged by default.
+const PING_TARGET: [u8; 4] = [142, 251, 35, 174];
+
+fn main() -> ExitCode {
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+
+    // Global options are valid anywhere on the command line, before or after the command.
+    let help = cli::take_flag(&mut args, "-h") | cli::take_flag(&mut args, "--help");
+    let globals = match GlobalOptions::from_args(&mut args) {
+        Ok(globals) => globals,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::from(2);
+        }
+    };
+    let (command, args) = match args.split_first() {
+        Some((command, args)) => (command.as_str(), args),
+        None => {
+            print_help(PROGRAM);
+            return if help { ExitCode::SUCCESS } else { ExitCode::from(2) };
+        }
+    };
+    if help {
+        return report(command, print_command_help(PROGRAM, command));
+    }
+    if globals.format != OutputFormat::Text && cli::find_command(command).is_some_and(|spec| !spec.records) {
+        eprintln!("{}: only text output is supported", command);
+        return ExitCode::from(2);
     }
-    fn send_and_recv(sequence: u16) {
-        let sequence = sequence;
-        let handle1 = std::thread::spawn(move || {
-            send_icmp_echo_request(sequence, [172, 16, 67, 153]);
-        });
-
-        // let handle2 = std::thread::spawn(|| {
-        //     recv_icmp_response();
-        // });
-
-        handle1.join().unwrap();
-        // handle2.join().unwrap();
+    let mut output = Output::new(globals.format, globals.verbosity);
+
+    // Commands that do not touch the network.
+    match command {
+        "help" => {
+            return match args.first() {
+                Some(name) => report(command, print_command_help(PROGRAM, name)),
+                None => {
+                    print_help(PROGRAM);
+                    ExitCode::SUCCESS
+                }
+            }
+        }
+        "completions" => {
+            let shell = args.first().map_or("", String::as_str);
+            return report(command, completions(shell, PROGRAM).map(|script| print!("{}", script)));
+        }
+        "interfaces" => {
+            interfaces();
+            return ExitCode::SUCCESS;
+        }
+        "lookup" => {
+            return match args {
+                [name] => {
+                    lookup(&globals.resolver, name);
+                    ExitCode::SUCCESS
+                }
+                _ => usage_error(command),
+            }
+        }
+        "decode" => {
+            let result = DecodeOptions::from_args(args).and_then(|options| decode(&options, &mut output));
+            return report(command, result);
+        }
+        "craft" => {
+            let result = CraftOptions::from_args(args).and_then(|options| {
+                let target = globals.resolver.resolve_ipv4(&options.target)?;
+                if options.write.is_some() {
+                    // Writing to a file needs no transport, only the defaults of the route.
+                    let route = next_hop(target);
+                    let probe_options = PingerOptions {
+                        source: globals.source.or(route.as_ref().map(|route| route.source)).unwrap_or([0; 4]),
+                        destination_mac: route
+                            .and_then(|route| lookup_neighbor(route.address(target)))
+                            .unwrap_or(MacAddr::BROADCAST),
+                        vlan_tags: globals.vlan_tags.clone(),
+                        ..PingerOptions::default()
+                    };
+                    return craft(None, target, &options, probe_options);
+                }
+                let transport = globals
+                    .open_transport()
+                    .map_err(|e| format!("Failed to open transport: {}", e))?;
+                let probe_options = probe_options(&globals, transport.as_ref(), target)?;
+                craft(Some(transport.as_ref()), target, &options, probe_options)
+            });
+            return report(command, result);
+        }
+        _ if cli::find_command(command).is_none() => {
+            eprintln!("Unknown command: {}\n", command);
+            print_help(PROGRAM);
+            return ExitCode::from(2);
+        }
+        _ => {}
+    }
+
+    let transport = match globals.open_transport() {
+        Ok(transport) => transport,
+        Err(e) => {
+            eprintln!("Failed to open transport: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = match command {
+        "ping" => PingOptions::from_args(args).and_then(|options| {
+            let (target, target_name) = match &options.target {
+                Some(host) => (globals.resolver.resolve_ipv4(host)?, host.clone()),
+                None => (PING_TARGET, format_ipv4_address(&PING_TARGET)),
+            };
+            let probe_options = PingerOptions {
+                count: options.count,
+                interval: options.interval,
+                ttl: options.ttl,
+                timeout: options.timeout,
+                payload_size: options.payload_size,
+                pattern: options.pattern,
+                timestamp: options.timestamp,
+                ..probe_options(&globals, transport.as_ref(), target)?
+            };
+            ping(transport, target, target_name, probe_options, &mut output);
+            Ok(())
+        }),
+        "traceroute" => TracerouteOptions::from_args(args).and_then(|options| {
+            let target = globals.resolver.resolve_ipv4(&options.target)?;
+            let probe_options = probe_options(&globals, transport.as_ref(), target)?;
+            traceroute(transport, target, &options, probe_options, &globals.resolver, &mut output);
+            Ok(())
+        }),
+        "pmtu" => PmtuOptions::from_args(args).and_then(|options| {
+            let target = globals.resolver.resolve_ipv4(&options.target)?;
+            let probe_options = probe_options(&globals, transport.as_ref(), target)?;
+            pmtu(transport, target, &options, probe_options, &mut output);
+            Ok(())
+        }),
+        "monitor" => MonitorOptions::from_args(args).and_then(|options| {
+            let mut targets = Vec::new();
+            for host in options.targets.iter() {
+                targets.push((globals.resolver.resolve_ipv4(host)?, host.clone()));
+            }
+            let probe_options = probe_options(&globals, transport.as_ref(), targets[0].0)?;
+            monitor(transport, &targets, &options, probe_options, &mut output);
+            Ok(())
+        }),
+        "arping" => ArpingOptions::from_args(args)
+            .map(|options| arping(transport.as_ref(), &options, globals.source, &mut output)),
+        "sniff" => count_from_args(args).map(|count| sniff(transport.as_ref(), count, &mut output)),
+        "sweep" => match args {
+            [cidr] => {
+                let source_ip = globals.source.or_else(|| transport.source_address());
+                sweep(transport, cidr, source_ip, &globals.resolver, &mut output);
+                Ok(())
+            }
+            _ => return usage_error(command),
+        },
+        "arpscan" => match args {
+            [] => {
+                let source_ip = globals.source.or_else(|| transport.source_address());
+                arpscan(transport, source_ip, &mut output);
+                Ok(())
+            }
+            _ => return usage_error(command),
+        },
+        // `responder 10.0.0.2 10.0.0.3` answers for the listed addresses, by default our own.
+        "responder" => args
+            .iter()
+            .map(|arg| cli::parse_ipv4(arg))
+            .collect::<Result<Vec<[u8; 4]>, String>>()
+            .and_then(|mut addresses| {
+                if addresses.is_empty() {
+                    let own = globals
+                        .source
+                        .or_else(|| transport.source_address())
+                        .or_else(|| lookup_interface(transport.name()).and_then(|i| i.ipv4))
+                        .ok_or("No address to answer for, pass one or use --source")?;
+                    addresses.push(own);
+                }
+                responder(transport.as_ref(), &addresses);
+                Ok(())
+            }),
+        _ => unreachable!("every command is handled"),
+    };
+    report(command, result)
+}
+
+/// Builds the probe options shared by the commands pinging a target.
+///
+/// Probes are sent from the address of the route to the target, to the MAC address of
+/// its next hop, unless a link layer transport is on another interface than the route's.
+/// Network layer transports such as raw and ICMP sockets leave the routing to the kernel.
+fn probe_options(globals: &GlobalOptions, transport: &dyn Transport, target: [u8; 4]) -> Result<PingerOptions, String> {
+    let route = next_hop(target)
+        .filter(|route| transport.layer() != Layer::Link || route.interface.name == transport.name());
+    let source = globals
+        .source
+        .or_else(|| transport.source_address())
+        .or(route.as_ref().map(|route| route.source))
+        .ok_or_else(|| {
+            format!(
+                "No route to {}, pick a source address with --source",
+                format_ipv4_address(&target)
+            )
+        })?;
+    let destination_mac = route
+        .as_ref()
+        .and_then(|route| lookup_neighbor(route.address(target)))
+        .unwrap_or(MacAddr::BROADCAST);
+
+    Ok(PingerOptions {
+        source,
+        destination_mac,
+        vlan_tags: globals.vlan_tags.clone(),
+        ..PingerOptions::default()
+    })
+}
+
+/// Prints the error of a command, if any, and returns the exit code of the tool.
+fn report(command: &str, result: Result<(), String>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}: {}", command, e);
+            ExitCode::FAILURE
+        }
     }
 }
+
+/// Prints the usage of a command called with the wrong arguments.
+fn usage_error(command: &str) -> ExitCode {
+    let _ = print_command_help(PROGRAM, command);
+    ExitCode::from(2)
+}
@@ -0,0 +1,80 @@
+//! Vendor lookup by Organizationally Unique Identifier (OUI).
+//!
+//! The table is a small embedded excerpt of the IEEE MA-L registry covering
+//! vendors commonly found on lab and office networks.
+
+/// OUI prefixes and their vendors, sorted by prefix.
+const OUI_TABLE: &[([u8; 3], &str)] = &[
+    ([0x00, 0x00, 0x0c], "Cisco Systems"),
+    ([0x00, 0x00, 0x5e], "IANA"),
+    ([0x00, 0x03, 0x93], "Apple"),
+    ([0x00, 0x03, 0xff], "Microsoft"),
+    ([0x00, 0x04, 0x96], "Extreme Networks"),
+    ([0x00, 0x05, 0x02], "Apple"),
+    ([0x00, 0x05, 0x69], "VMware"),
+    ([0x00, 0x05, 0x85], "Juniper Networks"),
+    ([0x00, 0x09, 0x0f], "Fortinet"),
+    ([0x00, 0x0a, 0x95], "Apple"),
+    ([0x00, 0x0c, 0x29], "VMware"),
+    ([0x00, 0x0c, 0x42], "MikroTik"),
+    ([0x00, 0x0d, 0x93], "Apple"),
+    ([0x00, 0x0d, 0xb9], "PC Engines"),
+    ([0x00, 0x10, 0xdb], "Juniper Networks"),
+    ([0x00, 0x11, 0x24], "Apple"),
+    ([0x00, 0x14, 0x22], "Dell"),
+    ([0x00, 0x15, 0x5d], "Microsoft"),
+    ([0x00, 0x16, 0x3e], "Xensource"),
+    ([0x00, 0x17, 0x88], "Philips Lighting"),
+    ([0x00, 0x18, 0x0a], "Cisco Meraki"),
+    ([0x00, 0x18, 0x82], "Huawei"),
+    ([0x00, 0x1a, 0x11], "Google"),
+    ([0x00, 0x1b, 0x17], "Palo Alto Networks"),
+    ([0x00, 0x1b, 0x21], "Intel"),
+    ([0x00, 0x1b, 0x63], "Apple"),
+    ([0x00, 0x1c, 0x14], "VMware"),
+    ([0x00, 0x1c, 0x42], "Parallels"),
+    ([0x00, 0x1c, 0x73], "Arista Networks"),
+    ([0x00, 0x1e, 0x67], "Intel"),
+    ([0x00, 0x1f, 0x12], "Juniper Networks"),
+    ([0x00, 0x25, 0x90], "Super Micro Computer"),
+    ([0x00, 0x27, 0x22], "Ubiquiti"),
+    ([0x00, 0x50, 0x56], "VMware"),
+    ([0x00, 0xe0, 0x4c], "Realtek"),
+    ([0x00, 0xe0, 0xfc], "Huawei"),
+    ([0x08, 0x00, 0x27], "Oracle VirtualBox"),
+    ([0x3c, 0x06, 0x30], "Apple"),
+    ([0x3c, 0x5a, 0xb4], "Google"),
+    ([0x3c, 0xfd, 0xfe], "Intel"),
+    ([0xac, 0x1f, 0x6b], "Super Micro Computer"),
+    ([0xb8, 0x27, 0xeb], "Raspberry Pi Foundation"),
+    ([0xdc, 0xa6, 0x32], "Raspberry Pi Trading"),
+    ([0xe4, 0x5f, 0x01], "Raspberry Pi Trading"),
+    ([0xf0, 0x18, 0x98], "Apple"),
+];
+
+/// Looks up the vendor of a MAC address by its OUI.
+pub fn vendor(mac: &[u8; 6]) -> Option<&'static str> {
+    let oui = [mac[0], mac[1], mac[2]];
+    OUI_TABLE
+        .binary_search_by(|(prefix, _)| prefix.cmp(&oui))
+        .ok()
+        .map(|i| OUI_TABLE[i].1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn table_is_sorted_for_the_binary_search() {
+        assert!(OUI_TABLE.windows(2).all(|pair| pair[0].0 < pair[1].0));
+    }
+
+    #[test]
+    fn vendors_are_found_by_their_prefix_only() {
+        assert_eq!(vendor(&[0x00, 0x00, 0x0c, 0x12, 0x34, 0x56]), Some("Cisco Systems"));
+        assert_eq!(vendor(&[0x00, 0x0c, 0x29, 0xff, 0xff, 0xff]), Some("VMware"));
+        assert_eq!(vendor(&[0xf0, 0x18, 0x98, 0x00, 0x00, 0x00]), Some("Apple"));
+        assert_eq!(vendor(&[0x00, 0x0c, 0x2a, 0x00, 0x00, 0x00]), None);
+    }
+}
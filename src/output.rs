@@ -0,0 +1,282 @@
+//! Machine-readable output of the commands.
+//!
+//! With `--format json`, `ndjson` or `csv`, the commands write records instead of
+//! lines of text: one per probe or packet, and summaries at the end. JSON output is
+//! a single array written as the records come, NDJSON one object per line, and CSV
+//! a header row followed by the records, with a new header whenever the columns change.
+
+use crate::cli::{OutputFormat, Verbosity};
+use rust_network::ipv4::internet_packet::format_ipv4_address;
+use rust_network::pinger::PingEvent;
+
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Value of a record field.
+pub(crate) enum Value {
+    /// A string.
+    Text(String),
+    /// An integer.
+    Integer(i64),
+    /// A decimal number, written with three decimals.
+    Number(f64),
+    /// Raw JSON, e.g. a serialized packet. Written as a string in CSV.
+    Json(String),
+    /// No value.
+    Null,
+}
+
+impl From<&str> for Value {
+    fn from(value: &str) -> Value {
+        Value::Text(value.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(value: String) -> Value {
+        Value::Text(value)
+    }
+}
+
+impl From<u64> for Value {
+    fn from(value: u64) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(value: u32) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<u16> for Value {
+    fn from(value: u16) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<u8> for Value {
+    fn from(value: u8) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<usize> for Value {
+    fn from(value: usize) -> Value {
+        Value::Integer(value as i64)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(value: f64) -> Value {
+        Value::Number(value)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(value: bool) -> Value {
+        Value::Json(value.to_string())
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(value: Option<T>) -> Value {
+        value.map_or(Value::Null, Into::into)
+    }
+}
+
+/// Represents a record, an ordered list of named fields.
+pub(crate) struct Record {
+    /// Names and values of the fields.
+    fields: Vec<(&'static str, Value)>,
+}
+
+impl Record {
+    /// Creates a record of the given type, e.g. `event` or `summary`, stamped with the current time.
+    pub(crate) fn new(record_type: &'static str) -> Record {
+        Record {
+            fields: vec![("type", Value::from(record_type)), ("timestamp", Value::Number(unix_time()))],
+        }
+    }
+
+    /// Adds a field to the record.
+    pub(crate) fn field(mut self, name: &'static str, value: impl Into<Value>) -> Record {
+        self.fields.push((name, value.into()));
+        self
+    }
+}
+
+/// Represents the output of a command, text or records.
+pub(crate) struct Output {
+    /// Format of the output.
+    pub(crate) format: OutputFormat,
+    /// How much is printed.
+    pub(crate) verbosity: Verbosity,
+    /// Columns of the last CSV header written.
+    columns: Vec<&'static str>,
+    /// Number of records written.
+    written: usize,
+}
+
+impl Output {
+    /// Creates the output of a command.
+    pub(crate) fn new(format: OutputFormat, verbosity: Verbosity) -> Output {
+        Output {
+            format,
+            verbosity,
+            columns: Vec::new(),
+            written: 0,
+        }
+    }
+
+    /// Checks whether the command prints text, rather than records.
+    pub(crate) fn is_text(&self) -> bool {
+        self.format == OutputFormat::Text
+    }
+
+    /// Writes the record of a probe or packet, unless text is printed or with `-q`.
+    pub(crate) fn event(&mut self, record: Record) {
+        if self.verbosity != Verbosity::Quiet {
+            self.write(record);
+        }
+    }
+
+    /// Writes a summary record, unless text is printed.
+    pub(crate) fn summary(&mut self, record: Record) {
+        self.write(record);
+    }
+
+    /// Ends the output, closing the JSON array.
+    pub(crate) fn finish(&mut self) {
+        if self.format == OutputFormat::Json {
+            if self.written == 0 {
+                println!("[");
+            }
+            println!("]");
+        }
+    }
+
+    /// Writes a record in the output format.
+    fn write(&mut self, record: Record) {
+        match self.format {
+            OutputFormat::Text => return,
+            OutputFormat::Json => {
+                let separator = if self.written == 0 { "[" } else { "," };
+                println!("{}\n  {}", separator, to_json(&record));
+            }
+            OutputFormat::Ndjson => println!("{}", to_json(&record)),
+            OutputFormat::Csv => {
+                let columns: Vec<&'static str> = record.fields.iter().map(|(name, _)| *name).collect();
+                if columns != self.columns {
+                    if !self.columns.is_empty() {
+                        println!();
+                    }
+                    println!("{}", columns.join(","));
+                    self.columns = columns;
+                }
+                let values: Vec<String> = record.fields.iter().map(|(_, value)| csv_value(value)).collect();
+                println!("{}", values.join(","));
+            }
+        }
+        self.written += 1;
+    }
+}
+
+/// Builds the record of a probe outcome.
+///
+/// Every record has the target, sequence number and status (`reply`, `corrupted`, `duplicate`,
+/// `timeout`, `unreachable`, `ttl_exceeded` or `send_failed`), and the fields the outcome
+/// knows, null otherwise.
+pub(crate) fn event_record(event: &PingEvent) -> Record {
+    let (status, ttl, rtt, size, from) = match *event {
+        PingEvent::Reply { ttl, rtt, bytes, .. } => {
+            let status = if event.is_corrupted() { "corrupted" } else { "reply" };
+            (status, Some(ttl), Some(rtt), Some(bytes), None)
+        }
+        PingEvent::Duplicate { ttl, rtt, bytes, .. } => ("duplicate", Some(ttl), Some(rtt), Some(bytes), None),
+        PingEvent::Timeout { .. } => ("timeout", None, None, None, None),
+        PingEvent::Unreachable { rtt, from, .. } => ("unreachable", None, Some(rtt), None, Some(from)),
+        PingEvent::TtlExceeded { rtt, from, .. } => ("ttl_exceeded", None, Some(rtt), None, Some(from)),
+        PingEvent::SendFailed { .. } => ("send_failed", None, None, None, None),
+    };
+    let error = match event {
+        PingEvent::SendFailed { error, .. } => Some(error.to_string()),
+        _ => None,
+    };
+    Record::new("event")
+        .field("target", format_ipv4_address(&event.target()))
+        .field("seq", event.sequence())
+        .field("ttl", ttl)
+        .field("rtt_ms", rtt.map(milliseconds))
+        .field("size", size)
+        .field("status", status)
+        .field("from", from.map(|from| format_ipv4_address(&from)))
+        .field("error", error)
+}
+
+/// Converts a duration to milliseconds.
+pub(crate) fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Returns the seconds since the Unix epoch.
+fn unix_time() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |time| time.as_secs_f64())
+}
+
+/// Formats a record as a JSON object.
+fn to_json(record: &Record) -> String {
+    let fields: Vec<String> = record
+        .fields
+        .iter()
+        .map(|(name, value)| {
+            let value = match value {
+                Value::Text(text) => json_string(text),
+                Value::Integer(integer) => integer.to_string(),
+                Value::Number(number) => format!("{:.3}", number),
+                Value::Json(json) => json.clone(),
+                Value::Null => "null".to_string(),
+            };
+            format!("{}:{}", json_string(name), value)
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+/// Quotes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\r' => quoted += "\\r",
+            '\t' => quoted += "\\t",
+            c if (c as u32) < 0x20 => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Formats a value for CSV, quoting text holding separators or quotes.
+fn csv_value(value: &Value) -> String {
+    let text = match value {
+        Value::Text(text) | Value::Json(text) => text.clone(),
+        Value::Integer(integer) => integer.to_string(),
+        Value::Number(number) => format!("{:.3}", number),
+        Value::Null => String::new(),
+    };
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text
+    }
+}
@@ -0,0 +1,61 @@
+//! Writing of pcap capture files.
+//!
+//! Frames are saved in the classic libpcap format that tcpdump and Wireshark read,
+//! with microsecond timestamps, without going through libpcap itself.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Magic number of a pcap file with microsecond timestamps.
+const MAGIC: u32 = 0xa1b2_c3d4;
+
+/// Largest frame recorded whole.
+const SNAPLEN: u32 = 65535;
+
+/// Link type of files holding Ethernet frames.
+pub const LINKTYPE_ETHERNET: u32 = 1;
+/// Link type of files holding bare IPv4 packets.
+pub const LINKTYPE_IPV4: u32 = 228;
+
+/// Writes frames to a pcap file.
+pub struct PcapWriter {
+    /// File written to.
+    file: BufWriter<File>,
+}
+
+impl PcapWriter {
+    /// Creates a pcap file, replacing any existing one, and writes its header.
+    ///
+    /// # Arguments
+    /// * `path` - Path of the file.
+    /// * `link_type` - Link type of the frames, e.g. `LINKTYPE_ETHERNET`.
+    pub fn create(path: impl AsRef<Path>, link_type: u32) -> io::Result<PcapWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        file.write_all(&MAGIC.to_le_bytes())?;
+        file.write_all(&2u16.to_le_bytes())?; // Major version
+        file.write_all(&4u16.to_le_bytes())?; // Minor version
+        file.write_all(&0i32.to_le_bytes())?; // Time zone offset, always UTC
+        file.write_all(&0u32.to_le_bytes())?; // Timestamp accuracy, unused
+        file.write_all(&SNAPLEN.to_le_bytes())?;
+        file.write_all(&link_type.to_le_bytes())?;
+        Ok(PcapWriter { file })
+    }
+
+    /// Writes a frame captured at the given time.
+    pub fn write(&mut self, frame: &[u8], time: SystemTime) -> io::Result<()> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let captured = frame.len().min(SNAPLEN as usize);
+        self.file.write_all(&(since_epoch.as_secs() as u32).to_le_bytes())?;
+        self.file.write_all(&since_epoch.subsec_micros().to_le_bytes())?;
+        self.file.write_all(&(captured as u32).to_le_bytes())?;
+        self.file.write_all(&(frame.len() as u32).to_le_bytes())?;
+        self.file.write_all(&frame[..captured])
+    }
+
+    /// Writes the buffered frames to the file.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
@@ -0,0 +1,222 @@
+//! Async pinging on a Tokio runtime, behind the `tokio` feature.
+//!
+//! A pinger session runs as a task that waits on the transport's socket through an
+//! `AsyncFd` instead of blocking in `recv`, so async services can ping many hosts
+//! without a thread each. The probes are the same `ICMPPacket` and `IPV4` encodings
+//! the blocking engine sends, and every probe still ends in exactly one event.
+
+use crate::ipv4::internet_packet::format_ipv4_address;
+use crate::pinger::{PingEvent, Pinger, PingerOptions, Session};
+use crate::transport::raw_socket::RawSocketTransport;
+use crate::transport::socket::poll_readable;
+use crate::transport::Transport;
+
+use futures_core::Stream;
+use std::io;
+use std::os::fd::{AsRawFd, RawFd};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::unix::AsyncFd;
+use tokio::io::Interest;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tokio::task::JoinHandle;
+
+/// Answer of a target to a ping.
+#[derive(Clone, Debug)]
+pub struct PingReply {
+    /// Address that answered.
+    pub target: [u8; 4],
+    /// Number of the answered probe, which its echo request carried modulo 65536.
+    pub sequence: u64,
+    /// Round trip time.
+    pub rtt: Duration,
+    /// TTL of the reply.
+    pub ttl: u8,
+    /// Length of the ICMP message of the reply.
+    pub bytes: usize,
+    /// The reply failed its ICMP checksum.
+    pub corrupted: bool,
+    /// Offset of the first byte of data not echoed back as sent, `None` if it came back intact.
+    pub wrong_byte: Option<usize>,
+}
+
+/// Stream of the events of a pinger running as a task.
+///
+/// Every probe yields one event, its reply or why it got none. Dropping the stream
+/// stops the pinger.
+pub struct PingStream {
+    /// Events sent by the task, then its error if it failed.
+    receiver: UnboundedReceiver<io::Result<PingEvent>>,
+    /// Task running the pinger.
+    task: JoinHandle<()>,
+}
+
+impl PingStream {
+    /// Waits for the next event, `None` once every probe has ended.
+    pub async fn next(&mut self) -> Option<io::Result<PingEvent>> {
+        self.receiver.recv().await
+    }
+}
+
+impl Stream for PingStream {
+    type Item = io::Result<PingEvent>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.receiver.poll_recv(cx)
+    }
+}
+
+impl Drop for PingStream {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl Pinger {
+    /// Runs the pinger as a task of the current Tokio runtime, returning the stream of its events.
+    ///
+    /// Fails with `Unsupported` if the transport has no file descriptor to wait on,
+    /// like the simulated network. Must be called from within a Tokio runtime.
+    pub fn stream(self) -> io::Result<PingStream> {
+        let fd = self.transport.readable_fd().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("{} cannot be polled by an async runtime", self.transport.name()),
+            )
+        })?;
+        let fd = AsyncFd::with_interest(fd, Interest::READABLE)?;
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = tokio::spawn(async move {
+            if let Err(e) = drive(&self, &fd, &sender).await {
+                let _ = sender.send(Err(e));
+            }
+        });
+        Ok(PingStream { receiver, task })
+    }
+}
+
+/// Pings a target once.
+///
+/// The echo request goes out on a raw socket or, without the privileges for one, on
+/// an unprivileged ICMP socket. A zero `options.source` lets the kernel pick the
+/// source address. Fails with `TimedOut` if no answer comes within `options.timeout`,
+/// with `HostUnreachable` if a router reports the target unreachable or the TTL
+/// exceeded on the way, and with the error of the socket if the request cannot be sent.
+pub async fn ping(target: [u8; 4], options: &PingerOptions) -> io::Result<PingReply> {
+    let options = PingerOptions {
+        count: Some(1),
+        ..options.clone()
+    };
+    let mut pinger = Pinger::new(open_socket_transport()?, options)?;
+    pinger.add_target(target);
+    let mut events = pinger.stream()?;
+
+    match events.next().await {
+        Some(Ok(PingEvent::Reply {
+            target,
+            sequence,
+            rtt,
+            ttl,
+            bytes,
+            corrupted,
+            wrong_byte,
+        })) => Ok(PingReply {
+            target,
+            sequence,
+            rtt,
+            ttl,
+            bytes,
+            corrupted,
+            wrong_byte,
+        }),
+        Some(Ok(PingEvent::Duplicate { .. })) => unreachable!("a duplicate follows the reply it repeats"),
+        Some(Ok(PingEvent::Timeout { .. })) => Err(io::Error::new(
+            io::ErrorKind::TimedOut,
+            format!("no reply from {}", format_ipv4_address(&target)),
+        )),
+        Some(Ok(PingEvent::Unreachable { from, code, .. })) => Err(io::Error::new(
+            io::ErrorKind::HostUnreachable,
+            format!(
+                "{} reported {} unreachable (code {})",
+                format_ipv4_address(&from),
+                format_ipv4_address(&target),
+                code
+            ),
+        )),
+        Some(Ok(PingEvent::TtlExceeded { from, .. })) => Err(io::Error::new(
+            io::ErrorKind::HostUnreachable,
+            format!("time to live exceeded at {}", format_ipv4_address(&from)),
+        )),
+        Some(Ok(PingEvent::SendFailed { error, .. })) => {
+            Err(Arc::try_unwrap(error).unwrap_or_else(|error| io::Error::new(error.kind(), error.to_string())))
+        }
+        Some(Err(e)) => Err(e),
+        None => Err(io::Error::other(format!(
+            "the ping of {} ended without an outcome",
+            format_ipv4_address(&target)
+        ))),
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Runs a pinger session until every probe has ended or the stream is dropped.
+async fn drive(
+    pinger: &Pinger,
+    fd: &AsyncFd<RawFd>,
+    sender: &UnboundedSender<io::Result<PingEvent>>,
+) -> io::Result<()> {
+    let transport = pinger.transport.as_ref();
+    let mut session = Session::new(pinger, Instant::now());
+    loop {
+        let now = Instant::now();
+        for event in session.send_due(now).into_iter().chain(session.expire(now)) {
+            if sender.send(Ok(event)).is_err() {
+                return Ok(());
+            }
+        }
+        if session.is_done() {
+            return Ok(());
+        }
+
+        let deadline = session.next_deadline(now);
+        tokio::select! {
+            _ = tokio::time::sleep_until(deadline.into()) => {}
+            guard = fd.readable() => {
+                let mut guard = guard?;
+                // Drain the socket, as readiness is only reported again for new packets.
+                loop {
+                    match transport.recv_frame(Duration::ZERO)? {
+                        Some(frame) => {
+                            if let Some(event) = session.receive(&frame, Instant::now()) {
+                                if sender.send(Ok(event)).is_err() {
+                                    return Ok(());
+                                }
+                            }
+                        }
+                        // Packets the transport drops, e.g. runts, leave more behind.
+                        None if poll_readable(fd.as_raw_fd(), Duration::ZERO)? => {}
+                        None => {
+                            guard.clear_ready();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Opens a raw socket transport, or an ICMP datagram socket one without the privileges.
+fn open_socket_transport() -> io::Result<Arc<dyn Transport>> {
+    match RawSocketTransport::open() {
+        Ok(transport) => Ok(Arc::new(transport)),
+        #[cfg(target_os = "linux")]
+        Err(e) if e.kind() == io::ErrorKind::PermissionDenied => Ok(Arc::new(
+            crate::transport::icmp_socket::IcmpSocketTransport::open()?,
+        )),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,235 @@
+//! Sharing one transport between pingers running at once.
+//!
+//! Every frame a transport receives goes to whichever reader takes it first, so two
+//! pingers reading the same transport would steal each other's replies. A
+//! `Demultiplexer` reads the transport for all of them and hands each echo reply, and
+//! each ICMP error quoting an echo request, to the pinger whose identifier it carries.
+//! Every pinger gets a handle of its own, a `Transport` learning the pinger's
+//! identifier from the first echo request sent through it.
+//!
+//! ```no_run
+//! use rust_network::pinger::demux::Demultiplexer;
+//! use rust_network::pinger::{Pinger, PingerOptions};
+//! use rust_network::transport::simulated::SimulatedNetwork;
+//! use std::sync::Arc;
+//!
+//! let demux = Demultiplexer::new(Arc::new(SimulatedNetwork::lab(1)));
+//! let options = PingerOptions {
+//!     source: [10, 0, 0, 2],
+//!     count: Some(3),
+//!     ..PingerOptions::default()
+//! };
+//! let mut handles = Vec::new();
+//! for target in [[10, 0, 0, 10], [10, 0, 0, 11]] {
+//!     let mut pinger = Pinger::new(demux.handle(), options.clone())?;
+//!     pinger.add_target(target);
+//!     handles.push(pinger.spawn());
+//! }
+//! # Ok::<(), std::io::Error>(())
+//! ```
+
+use crate::ethernet_frame::EthernetFrame;
+use crate::ipv4::fields::IpProtocol;
+use crate::ipv4::icmp::{DESTINATION_UNREACHABLE, ECHO_REPLY, ECHO_REQUEST, TIME_EXCEEDED};
+use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
+use crate::pinger::quoted_echo_request;
+use crate::transport::{Layer, Transport};
+
+use std::collections::{HashMap, VecDeque};
+use std::io;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// Most packets queued for one handle, beyond which the oldest are dropped.
+const QUEUE_LIMIT: usize = 1024;
+
+/// Reads a transport for several pingers, handing each its own replies.
+pub struct Demultiplexer {
+    /// Transport shared by the pingers.
+    transport: Arc<dyn Transport>,
+    /// Packets waiting for their handle, and whether a handle is reading the transport.
+    state: Mutex<DemuxState>,
+    /// Signalled when a reader has dispatched a packet or stopped reading.
+    dispatched: Condvar,
+}
+
+/// Shared state of a demultiplexer.
+struct DemuxState {
+    /// Packets received for each identifier a handle sends echo requests with.
+    queues: HashMap<u16, VecDeque<Vec<u8>>>,
+    /// Whether a handle is waiting on the transport, the others waiting for it to dispatch.
+    reading: bool,
+}
+
+/// Transport of one pinger sharing a demultiplexer's transport.
+///
+/// It receives only the echo replies and ICMP errors answering its own echo requests.
+/// As it waits on the demultiplexer rather than on a file descriptor, it cannot be
+/// polled by an async runtime.
+pub struct DemuxHandle {
+    /// Demultiplexer reading the transport.
+    demux: Arc<Demultiplexer>,
+    /// Identifier of the echo requests sent through the handle, `None` before the first.
+    identifier: Mutex<Option<u16>>,
+}
+
+impl Demultiplexer {
+    /// Creates a demultiplexer reading the given transport.
+    ///
+    /// An ICMP socket forces the identifier of its first echo request on all others,
+    /// leaving nothing to tell the pingers apart; open a socket per pinger instead.
+    pub fn new(transport: Arc<dyn Transport>) -> Arc<Demultiplexer> {
+        Arc::new(Demultiplexer {
+            transport,
+            state: Mutex::new(DemuxState {
+                queues: HashMap::new(),
+                reading: false,
+            }),
+            dispatched: Condvar::new(),
+        })
+    }
+
+    /// Creates the transport of one more pinger.
+    pub fn handle(self: &Arc<Demultiplexer>) -> Arc<DemuxHandle> {
+        Arc::new(DemuxHandle {
+            demux: Arc::clone(self),
+            identifier: Mutex::new(None),
+        })
+    }
+}
+
+impl Drop for DemuxHandle {
+    fn drop(&mut self) {
+        if let Some(identifier) = *self.identifier.lock().unwrap() {
+            self.demux.state.lock().unwrap().queues.remove(&identifier);
+        }
+    }
+}
+
+impl Transport for DemuxHandle {
+    fn layer(&self) -> Layer {
+        self.demux.transport.layer()
+    }
+
+    fn name(&self) -> &str {
+        self.demux.transport.name()
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        self.demux.transport.mac_address()
+    }
+
+    fn source_address(&self) -> Option<[u8; 4]> {
+        self.demux.transport.source_address()
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        let mut identifier = self.identifier.lock().unwrap();
+        if identifier.is_none() {
+            let request = decode(self.layer(), packet).and_then(|frame| {
+                let packet = frame.ipv4()?;
+                let is_request = packet.protocol == IpProtocol::ICMP && packet.datagram.packet_type == ECHO_REQUEST;
+                is_request.then_some(packet.datagram.identifier)
+            });
+            if let Some(request) = request {
+                self.demux.state.lock().unwrap().queues.entry(request).or_default();
+                *identifier = Some(request);
+            }
+        }
+        self.demux.transport.send(packet)
+    }
+
+    /// Receives the next packet for this handle, reading the transport in turn with the
+    /// other handles and queueing what it reads for them.
+    ///
+    /// The timeout runs on the transport's clock, so a simulated network's virtual time
+    /// ends it as it does the reads of the transport.
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let identifier = *self.identifier.lock().unwrap();
+        let deadline = self.now() + timeout;
+        let mut state = self.demux.state.lock().unwrap();
+        loop {
+            if let Some(packet) = identifier.and_then(|identifier| state.queues.get_mut(&identifier)?.pop_front()) {
+                return Ok(Some(packet));
+            }
+            let now = self.now();
+            if now >= deadline {
+                return Ok(None);
+            }
+            if state.reading {
+                state = self.demux.dispatched.wait_timeout(state, deadline - now).unwrap().0;
+                continue;
+            }
+
+            state.reading = true;
+            drop(state);
+            let received = self.demux.transport.recv(deadline - now);
+            state = self.demux.state.lock().unwrap();
+            state.reading = false;
+            self.demux.dispatched.notify_all();
+
+            let Some(packet) = received? else { continue };
+            let owner = decode(self.layer(), &packet).and_then(|frame| reply_identifier(&frame));
+            if let Some(queue) = owner.and_then(|owner| state.queues.get_mut(&owner)) {
+                if queue.len() == QUEUE_LIMIT {
+                    queue.pop_front();
+                }
+                queue.push_back(packet);
+            }
+        }
+    }
+
+    fn now(&self) -> Instant {
+        self.demux.transport.now()
+    }
+
+    fn echo_identifier(&self) -> Option<u16> {
+        self.demux.transport.echo_identifier()
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Decodes a packet of the given layer into a frame, as `Transport::recv_frame` does.
+fn decode(layer: Layer, bytes: &[u8]) -> Option<EthernetFrame> {
+    match layer {
+        Layer::Link => EthernetFrame::from_bytes(bytes),
+        Layer::Network if bytes.len() >= 20 && bytes[0] >> 4 == 4 => {
+            IPV4::from_bytes(bytes).map(EthernetFrame::new_ether)
+        }
+        _ => None,
+    }
+}
+
+/// Reads the identifier of the echo request a frame answers: that of an echo reply, or
+/// the one quoted in an ICMP error.
+fn reply_identifier(frame: &EthernetFrame) -> Option<u16> {
+    let packet = frame.ipv4()?;
+    if packet.protocol != IpProtocol::ICMP {
+        return None;
+    }
+    match packet.datagram.packet_type {
+        ECHO_REPLY => Some(packet.datagram.identifier),
+        DESTINATION_UNREACHABLE | TIME_EXCEEDED => {
+            quoted_echo_request(&packet.datagram.data).map(|(identifier, _, _)| identifier)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::simulated::SimulatedNetwork;
+
+    #[test]
+    fn recv_times_out_on_the_transport_clock() {
+        let network = Arc::new(SimulatedNetwork::lab(1));
+        let handle = Demultiplexer::new(network.clone()).handle();
+        let start = network.now();
+
+        assert!(handle.recv(Duration::from_secs(5)).unwrap().is_none());
+        assert_eq!(network.now() - start, Duration::from_secs(5));
+    }
+}
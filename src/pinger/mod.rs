@@ -0,0 +1,982 @@
+//! Event-driven pinger engine.
+//!
+//! A single loop on one thread sends the echo requests of every target as they fall
+//! due, receives the replies and ICMP errors, and times out the unanswered probes,
+//! so thousands of probes to many targets can be in flight at once. Every probe ends
+//! in exactly one event, handed to a callback or sent on a channel, and any further
+//! copies of its reply are reported as duplicates.
+//!
+//! Probes are numbered by a 64-bit counter that runs on for as long as the pinger
+//! does; the echo requests carry it modulo 65536, the sequence numbers wrapping
+//! around, and replies are matched back to the full number.
+//!
+//! A pinger without a probe count runs until its stop flag is set, e.g. on Ctrl-C.
+
+// Module declarations for each file in the pinger directory
+#[cfg(feature = "tokio")]
+pub mod async_ping;
+pub mod demux;
+pub mod payload;
+pub(crate) mod timer_wheel;
+
+use crate::ethernet_frame::{EthernetFrame, VlanTag};
+use crate::ipv4::fields::{FragmentFlags, IpProtocol};
+use crate::ipv4::icmp::{
+    ICMPPacket, CODE_FRAGMENTATION_NEEDED, DESTINATION_UNREACHABLE, ECHO_REPLY, ECHO_REQUEST, TIME_EXCEEDED,
+};
+use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
+use crate::transport::Transport;
+use payload::{EchoPayload, PayloadPattern, TimestampFormat};
+use timer_wheel::TimerWheel;
+
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, BinaryHeap, HashMap};
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Precision of the probe timeouts.
+const TIMER_TICK: Duration = Duration::from_millis(10);
+
+/// Longest wait for a packet, so the loop never sleeps past a due probe.
+const RECV_POLL: Duration = Duration::from_millis(100);
+
+/// ICMP identifiers held by the pingers of the process, and the next one to try handing out.
+static IDENTIFIERS: Mutex<(BTreeSet<u16>, Option<u16>)> = Mutex::new((BTreeSet::new(), None));
+
+/// Outcome of a probe.
+///
+/// The `sequence` of every event numbers the probe within the run, counting on past
+/// 65535; its echo request carried it modulo 65536.
+#[derive(Clone, Debug)]
+pub enum PingEvent {
+    /// The target answered.
+    Reply {
+        target: [u8; 4],
+        sequence: u64,
+        rtt: Duration,
+        ttl: u8,
+        /// Length of the ICMP message of the reply.
+        bytes: usize,
+        /// The reply failed its ICMP checksum. It still shows the target is up, but is
+        /// counted apart from the good replies.
+        corrupted: bool,
+        /// Offset of the first byte of data the target did not echo back as sent, the
+        /// end of the shorter data if the lengths differ; `None` if it came back intact.
+        wrong_byte: Option<usize>,
+    },
+    /// Another reply to a probe that was already answered, e.g. from a looping or
+    /// duplicating network. It comes on top of the probe's own event.
+    Duplicate {
+        target: [u8; 4],
+        sequence: u64,
+        /// Time from the probe to this copy of its reply.
+        rtt: Duration,
+        ttl: u8,
+        /// Length of the ICMP message of the reply.
+        bytes: usize,
+    },
+    /// No answer came within the timeout.
+    Timeout { target: [u8; 4], sequence: u64 },
+    /// A router or the target reported the target unreachable.
+    Unreachable {
+        target: [u8; 4],
+        sequence: u64,
+        /// Time until the error arrived.
+        rtt: Duration,
+        /// Address the error came from.
+        from: [u8; 4],
+        /// Code of the Destination Unreachable message, e.g. `CODE_HOST_UNREACHABLE`.
+        code: u8,
+        /// MTU of the next hop, reported with `CODE_FRAGMENTATION_NEEDED`.
+        next_hop_mtu: Option<u16>,
+    },
+    /// A router dropped the probe when its TTL ran out.
+    TtlExceeded {
+        target: [u8; 4],
+        sequence: u64,
+        /// Time until the error arrived.
+        rtt: Duration,
+        /// Address of the router.
+        from: [u8; 4],
+    },
+    /// The transport failed to send the echo request.
+    SendFailed {
+        target: [u8; 4],
+        sequence: u64,
+        /// Error of the transport, shared as events are cloned.
+        error: Arc<io::Error>,
+    },
+}
+
+impl PingEvent {
+    /// Address of the target the probe was sent to.
+    pub fn target(&self) -> [u8; 4] {
+        match self {
+            PingEvent::Reply { target, .. }
+            | PingEvent::Duplicate { target, .. }
+            | PingEvent::Timeout { target, .. }
+            | PingEvent::Unreachable { target, .. }
+            | PingEvent::TtlExceeded { target, .. }
+            | PingEvent::SendFailed { target, .. } => *target,
+        }
+    }
+
+    /// Number of the probe within the run.
+    pub fn sequence(&self) -> u64 {
+        match self {
+            PingEvent::Reply { sequence, .. }
+            | PingEvent::Duplicate { sequence, .. }
+            | PingEvent::Timeout { sequence, .. }
+            | PingEvent::Unreachable { sequence, .. }
+            | PingEvent::TtlExceeded { sequence, .. }
+            | PingEvent::SendFailed { sequence, .. } => *sequence,
+        }
+    }
+
+    /// Checks whether the event is a reply that failed its checksum or altered our data.
+    pub fn is_corrupted(&self) -> bool {
+        match self {
+            PingEvent::Reply {
+                corrupted, wrong_byte, ..
+            } => *corrupted || wrong_byte.is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// Options of a pinger run.
+#[derive(Clone)]
+pub struct PingerOptions {
+    /// Source address of the echo requests.
+    pub source: [u8; 4],
+    /// Number of probes per target, `None` to run until the process ends.
+    pub count: Option<u32>,
+    /// Time between two probes to the same target.
+    pub interval: Duration,
+    /// Probes sent per second over all targets.
+    pub rate: u32,
+    /// How long to wait for the answer to a probe.
+    pub timeout: Duration,
+    /// TTL of the echo requests.
+    pub ttl: u8,
+    /// ICMP identifier of the echo requests, `None` for one no other pinger of the process holds.
+    ///
+    /// Transports that force an identifier, like ICMP sockets once bound, only take that one.
+    pub identifier: Option<u16>,
+    /// Length of the echo request data, timestamp included, `None` for just the timestamp.
+    pub payload_size: Option<usize>,
+    /// Fill of the echo request data after the timestamp.
+    pub pattern: PayloadPattern,
+    /// Encoding of the send time at the start of the echo request data.
+    pub timestamp: TimestampFormat,
+    /// Sets Don't Fragment on the echo requests, so routers report MTUs too small for them.
+    pub dont_fragment: bool,
+    /// MAC address the frames are sent to, the gateway's or broadcast.
+    pub destination_mac: MacAddr,
+    /// VLAN stack the frames are tagged with, outermost first.
+    pub vlan_tags: Vec<VlanTag>,
+}
+
+impl Default for PingerOptions {
+    fn default() -> PingerOptions {
+        PingerOptions {
+            source: [0; 4],
+            count: None,
+            interval: Duration::from_secs(1),
+            rate: 1000,
+            timeout: Duration::from_secs(2),
+            ttl: 64,
+            identifier: None,
+            payload_size: None,
+            pattern: PayloadPattern::default(),
+            timestamp: TimestampFormat::default(),
+            dont_fragment: false,
+            destination_mac: MacAddr::BROADCAST,
+            vlan_tags: Vec::new(),
+        }
+    }
+}
+
+/// A probe waiting for its answer.
+struct Outstanding {
+    /// Index of the target in `Pinger::targets`.
+    target: usize,
+    /// Time the probe was sent.
+    sent_at: Instant,
+    /// Full number of the probe, telling its timer apart from a reused sequence number's.
+    sequence: u64,
+    /// Timestamp the probe carried, to check the echoed data against.
+    timestamp: Vec<u8>,
+}
+
+/// A probe that was answered, kept to recognize copies of its reply.
+struct Answered {
+    /// Index of the target in `Pinger::targets`.
+    target: usize,
+    /// Time the probe was sent.
+    sent_at: Instant,
+    /// Full number of the probe.
+    sequence: u64,
+}
+
+/// Represents a pinger sending echo requests to a set of targets.
+pub struct Pinger {
+    /// Transport the probes are sent and received on.
+    transport: Arc<dyn Transport>,
+    /// Options of the run.
+    options: PingerOptions,
+    /// Addresses to ping.
+    targets: Vec<[u8; 4]>,
+    /// ICMP identifier of our echo requests, which replies must carry, held until the pinger is dropped.
+    identifier: u16,
+    /// Layout of the echo request data.
+    payload: EchoPayload,
+    /// Flag ending the run once set.
+    stop: Arc<AtomicBool>,
+}
+
+impl Pinger {
+    /// Creates a pinger without targets.
+    ///
+    /// Fails with `AddrInUse` if another pinger of the process holds the identifier
+    /// asked for in the options or forced by the transport, or if every identifier is
+    /// held, and with `InvalidInput` if the two differ.
+    pub fn new(transport: Arc<dyn Transport>, options: PingerOptions) -> io::Result<Pinger> {
+        let payload = EchoPayload::new(options.payload_size, &options.pattern, options.timestamp);
+        let wanted = match (transport.echo_identifier(), options.identifier) {
+            (Some(forced), Some(asked)) if forced != asked => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("the transport sends with ICMP identifier {}, not {}", forced, asked),
+                ))
+            }
+            (forced, asked) => forced.or(asked),
+        };
+        let identifier = take_identifier(wanted)?;
+        Ok(Pinger {
+            transport,
+            options,
+            targets: Vec::new(),
+            identifier,
+            payload,
+            stop: Arc::new(AtomicBool::new(false)),
+        })
+    }
+
+    /// ICMP identifier of the echo requests, telling the pinger's replies apart from others'.
+    pub fn identifier(&self) -> u16 {
+        self.identifier
+    }
+
+    /// Flag stopping the run once set, from any thread or a signal handler.
+    ///
+    /// No more probes are sent and the probes in flight are given up, without events.
+    pub fn stop_flag(&self) -> Arc<AtomicBool> {
+        Arc::clone(&self.stop)
+    }
+
+    /// Adds a target to ping.
+    pub fn add_target(&mut self, target: [u8; 4]) {
+        self.targets.push(target);
+    }
+
+    /// Runs the pinger on a thread of its own, returning the channel its events arrive on.
+    ///
+    /// The channel closes when every probe has ended.
+    pub fn spawn(self) -> (JoinHandle<io::Result<()>>, Receiver<PingEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            self.run(|event| {
+                // A closed channel means nobody listens anymore; the probes just end.
+                let _ = sender.send(event);
+            })
+        });
+        (handle, receiver)
+    }
+
+    /// Runs the pinger until every probe has ended, handing each event to `on_event`.
+    pub fn run(&self, mut on_event: impl FnMut(PingEvent)) -> io::Result<()> {
+        let transport = self.transport.as_ref();
+        let mut session = Session::new(self, transport.now());
+        loop {
+            let now = transport.now();
+            session.send_due(now).into_iter().for_each(&mut on_event);
+            session.expire(now).into_iter().for_each(&mut on_event);
+            if session.is_done() {
+                return Ok(());
+            }
+
+            // Wait for a packet until the next probe or timeout falls due.
+            let wait = session.next_deadline(now).saturating_duration_since(now).min(RECV_POLL);
+            if let Some(frame) = transport.recv_frame(wait)? {
+                if let Some(event) = session.receive(&frame, transport.now()) {
+                    on_event(event);
+                }
+            }
+        }
+    }
+
+    /// Builds the frame of an echo request carrying the given timestamp.
+    fn echo_request(&self, target: [u8; 4], sequence: u16, timestamp: &[u8]) -> EthernetFrame {
+        let icmp_req = ICMPPacket::new_echo_request_with_data(self.identifier, sequence, self.payload.data(timestamp));
+        let mut ipv4_packet = IPV4::new_icmp_from_ip(icmp_req, self.options.ttl, self.options.source, target);
+        if self.options.dont_fragment {
+            ipv4_packet.flags = FragmentFlags::DF;
+            ipv4_packet.update_checksum();
+        }
+        let mut frame = EthernetFrame::new_ether(ipv4_packet).with_vlan_tags(self.options.vlan_tags.clone());
+        frame.destination_address = self.options.destination_mac;
+        if let Some(mac) = self.transport.mac_address() {
+            frame.source_address = mac;
+        }
+        frame
+    }
+}
+
+impl Drop for Pinger {
+    fn drop(&mut self) {
+        IDENTIFIERS.lock().unwrap().0.remove(&self.identifier);
+    }
+}
+
+/// State of a pinger run: the schedule of the probes and the probes in flight.
+///
+/// The run loops drive it, waiting for packets and deadlines the way their runtime does.
+pub(crate) struct Session<'a> {
+    /// Pinger whose targets are probed.
+    pinger: &'a Pinger,
+    /// Timeouts of the probes in flight, by full probe number.
+    timers: TimerWheel<u64>,
+    /// Probes in flight, by the sequence number their echo request carried.
+    outstanding: HashMap<u16, Outstanding>,
+    /// Answered probes, by sequence number, until a new probe reuses it.
+    answered: HashMap<u16, Answered>,
+    /// Next probe of each target, by due time.
+    schedule: BinaryHeap<Reverse<(Instant, usize)>>,
+    /// Number of probes sent to each target.
+    sent: Vec<u64>,
+    /// Time between two probes, from the rate.
+    gap: Duration,
+    /// Earliest time the next probe may be sent at.
+    next_slot: Instant,
+    /// Full number of the next probe.
+    next_sequence: u64,
+}
+
+impl<'a> Session<'a> {
+    /// Starts a run of the pinger, with the first probe to every target due at `start`.
+    pub(crate) fn new(pinger: &'a Pinger, start: Instant) -> Session<'a> {
+        Session {
+            pinger,
+            timers: TimerWheel::new(start, TIMER_TICK),
+            outstanding: HashMap::new(),
+            answered: HashMap::new(),
+            schedule: (0..pinger.targets.len()).map(|i| Reverse((start, i))).collect(),
+            sent: vec![0; pinger.targets.len()],
+            gap: Duration::from_secs(1) / pinger.options.rate.max(1),
+            next_slot: start,
+            next_sequence: 0,
+        }
+    }
+
+    /// Sends the due probes, as fast as the rate allows, returning the `SendFailed`
+    /// events of those the transport could not send.
+    pub(crate) fn send_due(&mut self, now: Instant) -> Vec<PingEvent> {
+        let options = &self.pinger.options;
+        let mut events = Vec::new();
+        while let Some(&Reverse((due, i))) = self.schedule.peek() {
+            if due > now
+                || self.next_slot > now
+                || self.outstanding.len() > u16::MAX as usize
+                || self.pinger.stop.load(Ordering::Relaxed)
+            {
+                break;
+            }
+            self.schedule.pop();
+
+            // Sequence numbers wrap; skip those of probes still in flight.
+            while self.outstanding.contains_key(&(self.next_sequence as u16)) {
+                self.next_sequence += 1;
+            }
+            let sequence = self.next_sequence;
+            self.next_sequence += 1;
+
+            let target = self.pinger.targets[i];
+            let timestamp = self.pinger.payload.timestamp(SystemTime::now());
+            let mut frame = self.pinger.echo_request(target, sequence as u16, &timestamp);
+            // Replies carrying the sequence number are this probe's from now on.
+            self.answered.remove(&(sequence as u16));
+            match self.pinger.transport.send_frame(&mut frame) {
+                Ok(()) => {
+                    let waiting = Outstanding {
+                        target: i,
+                        sent_at: now,
+                        sequence,
+                        timestamp,
+                    };
+                    self.outstanding.insert(sequence as u16, waiting);
+                    self.timers.insert(now + options.timeout, sequence);
+                }
+                Err(e) => events.push(PingEvent::SendFailed {
+                    target,
+                    sequence,
+                    error: Arc::new(e),
+                }),
+            }
+
+            self.sent[i] += 1;
+            if options.count.is_none_or(|count| self.sent[i] < u64::from(count)) {
+                self.schedule.push(Reverse((due + options.interval, i)));
+            }
+            self.next_slot = self.next_slot.max(now) + self.gap;
+        }
+        events
+    }
+
+    /// Ends the probes whose timeout passed, returning their `Timeout` events.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<PingEvent> {
+        let mut events = Vec::new();
+        for sequence in self.timers.expire(now) {
+            let wire = sequence as u16;
+            if self.outstanding.get(&wire).is_some_and(|waiting| waiting.sequence == sequence) {
+                let waiting = self.outstanding.remove(&wire).unwrap();
+                events.push(PingEvent::Timeout {
+                    target: self.pinger.targets[waiting.target],
+                    sequence,
+                });
+            }
+        }
+        events
+    }
+
+    /// Checks whether every probe has been sent and has ended, or the pinger was stopped.
+    pub(crate) fn is_done(&self) -> bool {
+        self.pinger.stop.load(Ordering::Relaxed) || (self.schedule.is_empty() && self.outstanding.is_empty())
+    }
+
+    /// Time the next probe or timeout falls due, at most `RECV_POLL` after `now`.
+    pub(crate) fn next_deadline(&self, now: Instant) -> Instant {
+        let next_send = self.schedule.peek().map(|Reverse((due, _))| (*due).max(self.next_slot));
+        match (next_send, self.timers.next_deadline()) {
+            (Some(send), Some(timeout)) => send.min(timeout),
+            (send, timeout) => send.or(timeout).unwrap_or(now + RECV_POLL),
+        }
+    }
+
+    /// Matches a received frame with the probe it answers, ending that probe, or with
+    /// the answered probe it repeats the reply of.
+    pub(crate) fn receive(&mut self, frame: &EthernetFrame, now: Instant) -> Option<PingEvent> {
+        let packet = frame.ipv4()?;
+        if packet.protocol != IpProtocol::ICMP {
+            return None;
+        }
+        let datagram = &packet.datagram;
+
+        // Replies carry our identifier and sequence number, errors quote our request.
+        let (identifier, sequence, target) = match datagram.packet_type {
+            ECHO_REPLY => (datagram.identifier, datagram.sequence, packet.source_add),
+            DESTINATION_UNREACHABLE | TIME_EXCEEDED => quoted_echo_request(&datagram.data)?,
+            _ => return None,
+        };
+        if identifier != self.pinger.identifier {
+            return None;
+        }
+        if datagram.packet_type == ECHO_REPLY && !self.outstanding.contains_key(&sequence) {
+            let answered = self.answered.get(&sequence)?;
+            if self.pinger.targets[answered.target] != target {
+                return None;
+            }
+            return Some(PingEvent::Duplicate {
+                target,
+                sequence: answered.sequence,
+                rtt: now.saturating_duration_since(answered.sent_at),
+                ttl: packet.ttl,
+                bytes: datagram.data.len() + 8,
+            });
+        }
+        if self.outstanding.get(&sequence).is_none_or(|waiting| self.pinger.targets[waiting.target] != target) {
+            return None;
+        }
+        let waiting = self.outstanding.remove(&sequence)?;
+        let rtt = now.saturating_duration_since(waiting.sent_at);
+        if datagram.packet_type == ECHO_REPLY {
+            let answered = Answered {
+                target: waiting.target,
+                sent_at: waiting.sent_at,
+                sequence: waiting.sequence,
+            };
+            self.answered.insert(sequence, answered);
+        }
+        let sequence = waiting.sequence;
+
+        Some(match datagram.packet_type {
+            ECHO_REPLY => PingEvent::Reply {
+                target,
+                sequence,
+                rtt,
+                ttl: packet.ttl,
+                bytes: datagram.data.len() + 8,
+                // The IPv4 header was checked by the kernel, or rebuilt by the transport.
+                corrupted: datagram.checksum_status().is_bad(false),
+                wrong_byte: self.pinger.payload.first_difference(&waiting.timestamp, &datagram.data),
+            },
+            DESTINATION_UNREACHABLE => PingEvent::Unreachable {
+                target,
+                sequence,
+                rtt,
+                from: packet.source_add,
+                code: datagram.code,
+                next_hop_mtu: match datagram.code {
+                    CODE_FRAGMENTATION_NEEDED if datagram.sequence != 0 => Some(datagram.sequence),
+                    _ => None,
+                },
+            },
+            _ => PingEvent::TtlExceeded {
+                target,
+                sequence,
+                rtt,
+                from: packet.source_add,
+            },
+        })
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Takes the wanted ICMP identifier, or one no other pinger of the process holds.
+///
+/// Identifiers are handed out in turn from one derived from the process ID, folded
+/// rather than truncated so PIDs above 65535 do not pick the same ones as small PIDs.
+/// Fails if the wanted identifier is held, as replies could not be told apart, or if
+/// all of them are, which takes 65536 pingers.
+fn take_identifier(wanted: Option<u16>) -> io::Result<u16> {
+    let mut identifiers = IDENTIFIERS.lock().unwrap();
+    let (held, next) = &mut *identifiers;
+    if let Some(identifier) = wanted {
+        if !held.insert(identifier) {
+            return Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("ICMP identifier {} is held by another pinger", identifier),
+            ));
+        }
+        return Ok(identifier);
+    }
+    let pid = std::process::id();
+    let start = next.unwrap_or((pid ^ (pid >> 16)) as u16);
+    let identifier = (0..=u16::MAX)
+        .map(|offset| start.wrapping_add(offset))
+        .find(|identifier| !held.contains(identifier))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::AddrInUse, "every ICMP identifier is held"))?;
+    *next = Some(identifier.wrapping_add(1));
+    held.insert(identifier);
+    Ok(identifier)
+}
+
+/// Reads the identifier, sequence number and destination of the echo request quoted
+/// in an ICMP error.
+fn quoted_echo_request(quoted: &[u8]) -> Option<(u16, u16, [u8; 4])> {
+    let header_len = (*quoted.first()? & 0x0f) as usize * 4;
+    if header_len < 20 {
+        return None;
+    }
+    let icmp = quoted.get(header_len..header_len + 8)?;
+    if quoted[9] != IpProtocol::ICMP.0 || icmp[0] != ECHO_REQUEST {
+        return None;
+    }
+    Some((
+        u16::from_be_bytes([icmp[4], icmp[5]]),
+        u16::from_be_bytes([icmp[6], icmp[7]]),
+        [quoted[16], quoted[17], quoted[18], quoted[19]],
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ipv4::icmp::CODE_HOST_UNREACHABLE;
+    use crate::transport::simulated::SimulatedNetwork;
+
+    /// Address of the local host of the lab network.
+    const LOCAL: [u8; 4] = [10, 0, 0, 2];
+
+    /// Host of the lab network that never answers, leaving the probes to the tests.
+    const SILENT: [u8; 4] = [203, 0, 113, 11];
+
+    /// Creates a pinger probing the silent host every second, its probes timing out after a minute.
+    fn silent_pinger() -> Pinger {
+        let options = PingerOptions {
+            source: LOCAL,
+            timeout: Duration::from_secs(60),
+            ..PingerOptions::default()
+        };
+        let mut pinger = Pinger::new(Arc::new(SimulatedNetwork::lab(1)), options).unwrap();
+        pinger.add_target(SILENT);
+        pinger
+    }
+
+    /// Builds the echo reply of the silent host to the probe in flight with the given sequence number.
+    fn reply_to(session: &Session, sequence: u16) -> EthernetFrame {
+        let pinger = session.pinger;
+        let data = pinger.payload.data(&session.outstanding[&sequence].timestamp);
+        let request = ICMPPacket::new_echo_request_with_data(pinger.identifier, sequence, data);
+        let reply = ICMPPacket::new_echo_reply(&request);
+        EthernetFrame::new_ether(IPV4::new_icmp_from_ip(reply, 64, SILENT, LOCAL))
+    }
+
+    /// Pings a target of the lab network, returning the events and the virtual time the run took.
+    fn ping_lab(seed: u64, target: [u8; 4], options: PingerOptions) -> (Vec<PingEvent>, Duration) {
+        let network = Arc::new(SimulatedNetwork::lab(seed));
+        let start = network.now();
+        let mut pinger = Pinger::new(network.clone(), PingerOptions { source: LOCAL, ..options }).unwrap();
+        pinger.add_target(target);
+        let mut events = Vec::new();
+        pinger.run(|event| events.push(event)).unwrap();
+        (events, network.now() - start)
+    }
+
+    #[test]
+    fn host_on_the_segment_answers_every_probe() {
+        let options = PingerOptions {
+            count: Some(3),
+            ..PingerOptions::default()
+        };
+        let (events, elapsed) = ping_lab(1, [10, 0, 0, 10], options);
+
+        assert_eq!(events.len(), 3);
+        for (i, event) in events.iter().enumerate() {
+            let PingEvent::Reply {
+                sequence, rtt, ttl, bytes, ..
+            } = event
+            else {
+                panic!("expected a reply, got {:?}", event);
+            };
+            assert_eq!(*sequence, i as u64);
+            // 300 µs each way, plus up to 100 µs of jitter.
+            assert!((Duration::from_micros(600)..=Duration::from_micros(800)).contains(rtt), "{:?}", rtt);
+            assert_eq!(*ttl, 64);
+            assert_eq!(*bytes, 8 + TimestampFormat::Nanos.len());
+            assert!(!event.is_corrupted());
+        }
+        // Three probes a second apart, the last answered within a millisecond.
+        assert!(elapsed >= Duration::from_secs(2) && elapsed < Duration::from_millis(2010), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn silent_host_times_out_on_the_virtual_clock() {
+        let options = PingerOptions {
+            count: Some(2),
+            timeout: Duration::from_secs(5),
+            ..PingerOptions::default()
+        };
+        let (events, elapsed) = ping_lab(1, [203, 0, 113, 11], options);
+
+        let sequences: Vec<u64> = events
+            .iter()
+            .map(|event| match event {
+                PingEvent::Timeout { sequence, .. } => *sequence,
+                _ => panic!("expected a timeout, got {:?}", event),
+            })
+            .collect();
+        assert_eq!(sequences, [0, 1]);
+        // The second probe goes out after a second and times out 5 seconds later.
+        assert!(elapsed >= Duration::from_secs(6) && elapsed < Duration::from_millis(6100), "{:?}", elapsed);
+    }
+
+    #[test]
+    fn routers_report_ttl_exceeded_hop_by_hop() {
+        let routers = [[10, 0, 0, 1], [192, 0, 2, 1], [198, 51, 100, 1]];
+        for (ttl, router) in (1..).zip(routers) {
+            let options = PingerOptions {
+                ttl,
+                count: Some(3),
+                interval: Duration::ZERO,
+                ..PingerOptions::default()
+            };
+            let (events, _) = ping_lab(7, [203, 0, 113, 10], options);
+
+            assert_eq!(events.len(), 3);
+            // The WAN link loses packets, but not every probe of a hop.
+            let mut answered = 0;
+            for event in events.iter() {
+                match event {
+                    PingEvent::TtlExceeded { from, .. } if *from == router => answered += 1,
+                    PingEvent::Timeout { .. } if ttl == 3 => {}
+                    _ => panic!("unexpected event at TTL {}: {:?}", ttl, event),
+                }
+            }
+            assert!(answered > 0, "no answer at TTL {}", ttl);
+        }
+    }
+
+    #[test]
+    fn unknown_host_is_reported_unreachable() {
+        let options = PingerOptions {
+            count: Some(1),
+            ..PingerOptions::default()
+        };
+        let (events, _) = ping_lab(1, [203, 0, 113, 99], options);
+
+        match events.as_slice() {
+            [PingEvent::Unreachable {
+                from, code, next_hop_mtu, ..
+            }] => {
+                assert_eq!(*from, [192, 0, 2, 1]);
+                assert_eq!(*code, CODE_HOST_UNREACHABLE);
+                assert_eq!(*next_hop_mtu, None);
+            }
+            _ => panic!("expected one unreachable, got {:?}", events),
+        }
+    }
+
+    #[test]
+    fn lossy_link_ends_every_probe_once_and_counts_duplicates() {
+        let options = PingerOptions {
+            count: Some(1000),
+            interval: Duration::from_millis(10),
+            timeout: Duration::from_secs(1),
+            ..PingerOptions::default()
+        };
+        let (events, _) = ping_lab(3, [203, 0, 113, 10], options);
+
+        let mut ended = vec![0; 1000];
+        let mut answered = vec![false; 1000];
+        let (mut timeouts, mut duplicates) = (0, 0);
+        for event in events.iter() {
+            let sequence = event.sequence() as usize;
+            match event {
+                PingEvent::Reply { .. } => {
+                    ended[sequence] += 1;
+                    answered[sequence] = true;
+                }
+                PingEvent::Timeout { .. } => {
+                    ended[sequence] += 1;
+                    timeouts += 1;
+                }
+                PingEvent::Duplicate { .. } => {
+                    assert!(answered[sequence], "duplicate before the reply of probe {}", sequence);
+                    duplicates += 1;
+                }
+                _ => panic!("unexpected event: {:?}", event),
+            }
+        }
+        assert!(ended.iter().all(|count| *count == 1));
+        // 5% loss each way and 1% duplication each way.
+        assert!((50..=150).contains(&timeouts), "{} timeouts", timeouts);
+        assert!((5..=40).contains(&duplicates), "{} duplicates", duplicates);
+    }
+
+    #[test]
+    fn stop_flag_ends_an_endless_run() {
+        let network = Arc::new(SimulatedNetwork::lab(1));
+        let options = PingerOptions {
+            source: LOCAL,
+            ..PingerOptions::default()
+        };
+        let mut pinger = Pinger::new(network, options).unwrap();
+        pinger.add_target([10, 0, 0, 10]);
+        let stop = pinger.stop_flag();
+
+        let mut sequences = Vec::new();
+        pinger
+            .run(|event| {
+                sequences.push(event.sequence());
+                if sequences.len() == 5 {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            })
+            .unwrap();
+        assert_eq!(sequences, [0, 1, 2, 3, 4]);
+    }
+
+    #[test]
+    fn router_reports_the_mtu_of_a_smaller_link() {
+        let probe = |size: usize| {
+            let options = PingerOptions {
+                count: Some(1),
+                payload_size: Some(size - 28),
+                dont_fragment: true,
+                ..PingerOptions::default()
+            };
+            ping_lab(1, [10, 0, 1, 10], options).0
+        };
+
+        match probe(1500).as_slice() {
+            [PingEvent::Unreachable {
+                from, code, next_hop_mtu, ..
+            }] => {
+                assert_eq!(*from, [10, 0, 0, 1]);
+                assert_eq!(*code, CODE_FRAGMENTATION_NEEDED);
+                assert_eq!(*next_hop_mtu, Some(1400));
+            }
+            events => panic!("expected fragmentation needed, got {:?}", events),
+        }
+        assert!(matches!(probe(1400).as_slice(), [PingEvent::Reply { bytes: 1380, .. }]));
+    }
+
+    #[test]
+    fn probes_the_transport_cannot_send_end_in_send_failed() {
+        let options = PingerOptions {
+            count: Some(2),
+            payload_size: Some(2000),
+            ..PingerOptions::default()
+        };
+        let (events, _) = ping_lab(1, [10, 0, 0, 10], options);
+
+        assert_eq!(events.len(), 2);
+        for (event, expected) in events.iter().zip(0..) {
+            match event {
+                PingEvent::SendFailed { sequence, error, .. } => {
+                    assert_eq!(*sequence, expected);
+                    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+                }
+                event => panic!("expected a send failure, got {:?}", event),
+            }
+        }
+    }
+
+    #[test]
+    fn identifier_held_by_another_pinger_is_refused() {
+        let network = Arc::new(SimulatedNetwork::lab(1));
+        let first = Pinger::new(network.clone(), PingerOptions::default()).unwrap();
+        let options = PingerOptions {
+            identifier: Some(first.identifier()),
+            ..PingerOptions::default()
+        };
+
+        let error = Pinger::new(network.clone(), options.clone()).err().expect("a held identifier");
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(first);
+        assert!(Pinger::new(network, options).is_ok());
+    }
+
+    #[test]
+    fn sequence_numbers_wrap_on_the_wire_but_not_in_events() {
+        let pinger = silent_pinger();
+        let start = pinger.transport.now();
+        let mut session = Session::new(&pinger, start);
+        session.next_sequence = u16::MAX as u64;
+
+        session.send_due(start);
+        session.send_due(start + Duration::from_secs(1));
+        assert_eq!(session.outstanding[&u16::MAX].sequence, 65535);
+        assert_eq!(session.outstanding[&0].sequence, 65536);
+
+        let reply = reply_to(&session, 0);
+        match session.receive(&reply, start + Duration::from_secs(2)) {
+            Some(PingEvent::Reply {
+                sequence, rtt, wrong_byte, ..
+            }) => {
+                assert_eq!(sequence, 65536);
+                assert_eq!(rtt, Duration::from_secs(1));
+                assert_eq!(wrong_byte, None);
+            }
+            event => panic!("expected a reply, got {:?}", event),
+        }
+    }
+
+    #[test]
+    fn endless_runs_count_probes_past_u32() {
+        let pinger = silent_pinger();
+        let start = pinger.transport.now();
+        let mut session = Session::new(&pinger, start);
+        session.sent[0] = u32::MAX as u64;
+
+        session.send_due(start);
+        assert_eq!(session.sent[0], 1 << 32);
+        assert!(!session.schedule.is_empty());
+    }
+
+    #[test]
+    fn sequence_numbers_of_probes_in_flight_are_skipped() {
+        let pinger = silent_pinger();
+        let start = pinger.transport.now();
+        let mut session = Session::new(&pinger, start);
+
+        session.send_due(start);
+        // The counter comes back around to the sequence number of the probe still in flight.
+        session.next_sequence = 1 << 16;
+        session.send_due(start + Duration::from_secs(1));
+
+        assert_eq!(session.outstanding.len(), 2);
+        assert_eq!(session.outstanding[&0].sequence, 0);
+        assert_eq!(session.outstanding[&1].sequence, (1 << 16) + 1);
+    }
+
+    #[test]
+    fn copies_of_a_reply_are_duplicates_until_the_sequence_is_reused() {
+        let pinger = silent_pinger();
+        let start = pinger.transport.now();
+        let mut session = Session::new(&pinger, start);
+        session.send_due(start);
+        let reply = reply_to(&session, 0);
+
+        let later = start + Duration::from_millis(10);
+        assert!(matches!(session.receive(&reply, later), Some(PingEvent::Reply { sequence: 0, .. })));
+        match session.receive(&reply, later + Duration::from_millis(5)) {
+            Some(PingEvent::Duplicate { sequence, rtt, .. }) => {
+                assert_eq!(sequence, 0);
+                assert_eq!(rtt, Duration::from_millis(15));
+            }
+            event => panic!("expected a duplicate, got {:?}", event),
+        }
+
+        // Once a new probe carries the sequence number, a copy of the old reply answers it.
+        session.next_sequence = 1 << 16;
+        session.send_due(start + Duration::from_secs(1));
+        let event = session.receive(&reply, start + Duration::from_secs(2));
+        assert!(matches!(event, Some(PingEvent::Reply { sequence: 65536, .. })));
+    }
+
+    /// Transport of the lab network sending every echo request with one identifier, as a bound ICMP socket does.
+    struct ForcedIdentifier(SimulatedNetwork, u16);
+
+    impl Transport for ForcedIdentifier {
+        fn layer(&self) -> crate::transport::Layer {
+            self.0.layer()
+        }
+
+        fn name(&self) -> &str {
+            self.0.name()
+        }
+
+        fn mac_address(&self) -> Option<MacAddr> {
+            self.0.mac_address()
+        }
+
+        fn send(&self, packet: &[u8]) -> io::Result<()> {
+            self.0.send(packet)
+        }
+
+        fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+            self.0.recv(timeout)
+        }
+
+        fn echo_identifier(&self) -> Option<u16> {
+            Some(self.1)
+        }
+    }
+
+    #[test]
+    fn identifier_forced_by_the_transport_is_held_too() {
+        let first = Pinger::new(Arc::new(SimulatedNetwork::lab(1)), PingerOptions::default()).unwrap();
+        let forced = first.identifier();
+        let transport: Arc<dyn Transport> = Arc::new(ForcedIdentifier(SimulatedNetwork::lab(1), forced));
+
+        let error = Pinger::new(transport.clone(), PingerOptions::default()).err().expect("a held identifier");
+        assert_eq!(error.kind(), io::ErrorKind::AddrInUse);
+        drop(first);
+
+        let options = PingerOptions {
+            identifier: Some(forced.wrapping_add(1)),
+            ..PingerOptions::default()
+        };
+        let error = Pinger::new(transport.clone(), options).err().expect("a conflicting identifier");
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(Pinger::new(transport, PingerOptions::default()).unwrap().identifier(), forced);
+    }
+}
@@ -0,0 +1,209 @@
+//! Data carried by echo requests.
+//!
+//! The data starts with the send time, in our encoding or in the `struct timeval`
+//! of Linux iputils ping so its tools can read it, and the rest is filled with a
+//! pattern. Targets must echo the data back unchanged; the fill is computed once per
+//! pinger and the timestamp kept with each probe, so replies can be checked byte by
+//! byte, like ping's "wrong data byte" reports.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Fill of the echo request data following the timestamp.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum PayloadPattern {
+    /// Zero bytes.
+    #[default]
+    Zeros,
+    /// Every byte holds its offset in the data, modulo 256, as iputils ping fills it.
+    Incrementing,
+    /// Random bytes, drawn once per pinger so every probe carries the same ones.
+    Random,
+    /// The given bytes, repeated from the start of the data.
+    Repeat(Vec<u8>),
+}
+
+/// Encoding of the send time at the start of the echo request data.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TimestampFormat {
+    /// Seconds since the Unix epoch as a u64 then nanoseconds as a u32, big endian: 12 bytes.
+    #[default]
+    Nanos,
+    /// A `struct timeval` of 64-bit Linux in host byte order, seconds then microseconds,
+    /// as iputils ping writes it: 16 bytes.
+    Timeval,
+    /// No timestamp; the pattern fills the whole data.
+    Omitted,
+}
+
+impl TimestampFormat {
+    /// Length of the encoded timestamp.
+    pub fn len(&self) -> usize {
+        match self {
+            TimestampFormat::Nanos => 12,
+            TimestampFormat::Timeval => 16,
+            TimestampFormat::Omitted => 0,
+        }
+    }
+
+    /// Checks whether the format encodes nothing.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Encodes a time, one before the Unix epoch as the epoch.
+    pub fn encode(&self, time: SystemTime) -> Vec<u8> {
+        let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut bytes = Vec::with_capacity(self.len());
+        match self {
+            TimestampFormat::Nanos => {
+                bytes.extend_from_slice(&since_epoch.as_secs().to_be_bytes());
+                bytes.extend_from_slice(&since_epoch.subsec_nanos().to_be_bytes());
+            }
+            TimestampFormat::Timeval => {
+                bytes.extend_from_slice(&(since_epoch.as_secs() as i64).to_ne_bytes());
+                bytes.extend_from_slice(&(since_epoch.subsec_micros() as i64).to_ne_bytes());
+            }
+            TimestampFormat::Omitted => {}
+        }
+        bytes
+    }
+}
+
+/// Layout of the echo request data of a pinger.
+pub(crate) struct EchoPayload {
+    /// Timestamp format, `Omitted` when the data is too short to hold one.
+    timestamp: TimestampFormat,
+    /// Whole data with the timestamp bytes zeroed, of the data length.
+    fill: Vec<u8>,
+}
+
+impl EchoPayload {
+    /// Lays out the data of the given length, by default just the timestamp.
+    ///
+    /// Data shorter than the timestamp carries none, as with iputils ping.
+    pub(crate) fn new(size: Option<usize>, pattern: &PayloadPattern, timestamp: TimestampFormat) -> EchoPayload {
+        let size = size.unwrap_or(timestamp.len());
+        let timestamp = if size < timestamp.len() { TimestampFormat::Omitted } else { timestamp };
+        let mut fill: Vec<u8> = match pattern {
+            PayloadPattern::Zeros => vec![0; size],
+            PayloadPattern::Incrementing => (0..size).map(|i| i as u8).collect(),
+            PayloadPattern::Random => random_bytes(size),
+            PayloadPattern::Repeat(bytes) if bytes.is_empty() => vec![0; size],
+            PayloadPattern::Repeat(bytes) => bytes.iter().copied().cycle().take(size).collect(),
+        };
+        fill[..timestamp.len()].fill(0);
+        EchoPayload { timestamp, fill }
+    }
+
+    /// Encodes the send time as the timestamp of a probe.
+    pub(crate) fn timestamp(&self, time: SystemTime) -> Vec<u8> {
+        self.timestamp.encode(time)
+    }
+
+    /// Builds the data of a probe, given its timestamp.
+    pub(crate) fn data(&self, timestamp: &[u8]) -> Vec<u8> {
+        let mut data = self.fill.clone();
+        data[..timestamp.len()].copy_from_slice(timestamp);
+        data
+    }
+
+    /// Finds the first byte of the data of a reply that differs from what the probe
+    /// carried, the end of the shorter one if the lengths differ; `None` if the data
+    /// came back unchanged.
+    pub(crate) fn first_difference(&self, timestamp: &[u8], data: &[u8]) -> Option<usize> {
+        let sent = timestamp.iter().chain(&self.fill[timestamp.len()..]);
+        match data.iter().zip(sent).position(|(received, sent)| received != sent) {
+            Some(offset) => Some(offset),
+            None if data.len() != self.fill.len() => Some(data.len().min(self.fill.len())),
+            None => None,
+        }
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Draws bytes from a xorshift generator seeded with the time and process ID.
+///
+/// Not for cryptography; the bytes only need to vary from one run to the next.
+fn random_bytes(len: usize) -> Vec<u8> {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_nanos() as u64;
+    let mut state = (nanos ^ ((std::process::id() as u64) << 32)) | 1;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 24) as u8
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    /// A send time with both whole seconds and a fraction.
+    fn send_time() -> SystemTime {
+        UNIX_EPOCH + Duration::new(1_700_000_000, 123_456_789)
+    }
+
+    #[test]
+    fn timestamps_encode_the_send_time() {
+        let nanos = TimestampFormat::Nanos.encode(send_time());
+        assert_eq!(nanos.len(), TimestampFormat::Nanos.len());
+        assert_eq!(u64::from_be_bytes(nanos[..8].try_into().unwrap()), 1_700_000_000);
+        assert_eq!(u32::from_be_bytes(nanos[8..].try_into().unwrap()), 123_456_789);
+
+        let timeval = TimestampFormat::Timeval.encode(send_time());
+        assert_eq!(timeval.len(), TimestampFormat::Timeval.len());
+        assert_eq!(i64::from_ne_bytes(timeval[..8].try_into().unwrap()), 1_700_000_000);
+        assert_eq!(i64::from_ne_bytes(timeval[8..].try_into().unwrap()), 123_456);
+
+        assert!(TimestampFormat::Omitted.encode(send_time()).is_empty());
+        assert_eq!(TimestampFormat::Nanos.encode(UNIX_EPOCH - Duration::from_secs(1)), vec![0; 12]);
+    }
+
+    #[test]
+    fn patterns_fill_the_data_after_the_timestamp() {
+        let payload = EchoPayload::new(Some(20), &PayloadPattern::Incrementing, TimestampFormat::Nanos);
+        let timestamp = payload.timestamp(send_time());
+        let data = payload.data(&timestamp);
+        assert_eq!(data[..12], timestamp[..]);
+        assert_eq!(data[12..], [12, 13, 14, 15, 16, 17, 18, 19]);
+
+        let payload = EchoPayload::new(Some(5), &PayloadPattern::Repeat(vec![0xab, 0xcd]), TimestampFormat::Omitted);
+        assert_eq!(payload.data(&[]), [0xab, 0xcd, 0xab, 0xcd, 0xab]);
+
+        let payload = EchoPayload::new(None, &PayloadPattern::Zeros, TimestampFormat::Timeval);
+        assert_eq!(payload.data(&payload.timestamp(send_time())).len(), 16);
+    }
+
+    #[test]
+    fn data_too_short_for_the_timestamp_carries_none() {
+        let payload = EchoPayload::new(Some(8), &PayloadPattern::Incrementing, TimestampFormat::Nanos);
+        let timestamp = payload.timestamp(send_time());
+        assert!(timestamp.is_empty());
+        assert_eq!(payload.data(&timestamp), [0, 1, 2, 3, 4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn first_difference_finds_the_wrong_byte() {
+        let payload = EchoPayload::new(Some(32), &PayloadPattern::Incrementing, TimestampFormat::Nanos);
+        let timestamp = payload.timestamp(send_time());
+        let data = payload.data(&timestamp);
+        assert_eq!(payload.first_difference(&timestamp, &data), None);
+
+        let mut wrong = data.clone();
+        wrong[20] ^= 0xff;
+        assert_eq!(payload.first_difference(&timestamp, &wrong), Some(20));
+        let mut wrong = data.clone();
+        wrong[3] ^= 0x01;
+        assert_eq!(payload.first_difference(&timestamp, &wrong), Some(3));
+
+        assert_eq!(payload.first_difference(&timestamp, &data[..24]), Some(24));
+        let mut longer = data.clone();
+        longer.push(32);
+        assert_eq!(payload.first_difference(&timestamp, &longer), Some(32));
+    }
+}
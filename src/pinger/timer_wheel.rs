@@ -0,0 +1,95 @@
+//! Hashed timer wheel for probe timeouts.
+//!
+//! Timers are hashed by their expiry tick into a fixed ring of slots, so adding a
+//! timer and expiring the due ones cost the same with ten or ten thousand probes in
+//! flight. Timers further away than one turn of the wheel wait in their slot for
+//! the following turns. Timers are not cancelled: the owner ignores expired timers
+//! of probes that were already answered.
+
+use std::time::{Duration, Instant};
+
+/// Number of slots of the wheel.
+const SLOTS: usize = 512;
+
+/// Represents a timer wheel holding items until their deadline.
+pub(crate) struct TimerWheel<T> {
+    /// Instant tick 0 starts at.
+    start: Instant,
+    /// Duration of one tick, the precision of the deadlines.
+    tick: Duration,
+    /// Slots of the ring, holding items with their expiry tick.
+    slots: Vec<Vec<(u64, T)>>,
+    /// Next tick to expire.
+    current: u64,
+    /// Number of items in the wheel.
+    len: usize,
+}
+
+impl<T> TimerWheel<T> {
+    /// Creates an empty wheel.
+    ///
+    /// # Arguments
+    /// * `start` - Current time.
+    /// * `tick` - Precision of the deadlines; items expire up to one tick late.
+    pub(crate) fn new(start: Instant, tick: Duration) -> TimerWheel<T> {
+        TimerWheel {
+            start,
+            tick,
+            slots: (0..SLOTS).map(|_| Vec::new()).collect(),
+            current: 0,
+            len: 0,
+        }
+    }
+
+    /// Adds an item expiring at `deadline`.
+    pub(crate) fn insert(&mut self, deadline: Instant, item: T) {
+        // Round up, so an item never expires before its deadline.
+        let expiry = self.tick_of(deadline + self.tick - Duration::from_nanos(1)).max(self.current);
+        self.slots[expiry as usize % SLOTS].push((expiry, item));
+        self.len += 1;
+    }
+
+    /// Removes and returns the items whose deadline is at or before `now`.
+    pub(crate) fn expire(&mut self, now: Instant) -> Vec<T> {
+        let mut expired = Vec::new();
+        let now = self.tick_of(now);
+        while self.current <= now && self.len > 0 {
+            let current = self.current;
+            let slot = &mut self.slots[current as usize % SLOTS];
+            let mut i = 0;
+            while i < slot.len() {
+                if slot[i].0 <= current {
+                    expired.push(slot.swap_remove(i).1);
+                    self.len -= 1;
+                } else {
+                    i += 1;
+                }
+            }
+            self.current += 1;
+        }
+        // An empty wheel skips ahead, so the next insert does not walk idle ticks.
+        if self.len == 0 {
+            self.current = self.current.max(now + 1);
+        }
+        expired
+    }
+
+    /// Earliest instant at which an item may be due, `None` if the wheel is empty.
+    ///
+    /// Only the next turn of the wheel is searched; later items are reported as the
+    /// end of that turn, when the wheel is looked at again.
+    pub(crate) fn next_deadline(&self) -> Option<Instant> {
+        if self.len == 0 {
+            return None;
+        }
+        let next = (self.current..self.current + SLOTS as u64)
+            .find(|tick| self.slots[*tick as usize % SLOTS].iter().any(|(expiry, _)| expiry <= tick))
+            .unwrap_or(self.current + SLOTS as u64);
+        Some(self.start + Duration::from_nanos((self.tick.as_nanos() * next as u128) as u64))
+    }
+
+    /// Converts an instant into the tick it falls in.
+    fn tick_of(&self, instant: Instant) -> u64 {
+        (instant.saturating_duration_since(self.start).as_nanos() / self.tick.as_nanos()) as u64
+    }
+}
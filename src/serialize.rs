@@ -0,0 +1,41 @@
+//! Serde support of the packet types, with the `serde` feature.
+//!
+//! Addresses serialize in their usual notation and raw bytes as hex strings, so
+//! decodes read the same in JSON as in the `Display` output.
+
+use crate::ipv4::internet_packet::format_ipv4_address;
+use crate::mac_address::MacAddr;
+
+use serde::{Serialize, Serializer};
+
+/// Serializes a MAC address as `aa:bb:cc:dd:ee:ff`.
+impl Serialize for MacAddr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Serializes an IPv4 address as a dotted quad.
+pub(crate) fn ipv4_address<S: Serializer>(address: &[u8; 4], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format_ipv4_address(address))
+}
+
+/// Serializes bytes as a string of hex digits.
+pub(crate) fn hex<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(bytes))
+}
+
+/// Serializes optional bytes as a string of hex digits, or null.
+pub(crate) fn optional_hex<S: Serializer>(bytes: &Option<Vec<u8>>, serializer: S) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => serializer.serialize_some(&to_hex(bytes)),
+        None => serializer.serialize_none(),
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Formats bytes as lowercase hex digits without separators.
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
@@ -0,0 +1,171 @@
+//! Transport on a real interface, receiving through pcap.
+//!
+//! Frames are written to a BPF device on macOS, as the original send path did, and
+//! injected through pcap elsewhere.
+
+extern crate libc;
+
+use crate::interface::lookup_interface;
+use crate::mac_address::MacAddr;
+use crate::transport::{Layer, Transport};
+
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "macos")]
+use libc::{ifreq, ioctl};
+#[cfg(target_os = "macos")]
+use std::fs::File;
+#[cfg(target_os = "macos")]
+use std::io::Write;
+#[cfg(target_os = "macos")]
+use std::os::unix::io::{AsRawFd, RawFd};
+
+/// Read timeout of the capture, the granularity at which `recv` notices its timeout.
+const CAPTURE_POLL_MS: i32 = 10;
+
+/// Represents a link layer transport on a real interface.
+pub struct PcapTransport {
+    /// Name of the interface.
+    name: String,
+    /// MAC address of the interface.
+    mac_address: Option<MacAddr>,
+    /// BPF device bound to the interface, for sending.
+    #[cfg(target_os = "macos")]
+    bpf_device: File,
+    /// Capture used only for sending, so sends never wait on a pending receive.
+    #[cfg(not(target_os = "macos"))]
+    sender: Mutex<pcap::Capture<pcap::Active>>,
+    /// Capture used for receiving.
+    receiver: Mutex<pcap::Capture<pcap::Active>>,
+}
+
+impl PcapTransport {
+    /// Opens a transport on the given interface.
+    ///
+    /// # Arguments
+    /// * `interface_name` - Interface to send and receive on, e.g. `en0`.
+    pub fn open(interface_name: &str) -> io::Result<PcapTransport> {
+        let mac_address = lookup_interface(interface_name).and_then(|interface| interface.mac);
+        Ok(PcapTransport {
+            name: interface_name.to_string(),
+            mac_address,
+            #[cfg(target_os = "macos")]
+            bpf_device: open_bpf_device(interface_name)?,
+            #[cfg(not(target_os = "macos"))]
+            sender: Mutex::new(open_capture(interface_name)?),
+            receiver: Mutex::new(open_capture(interface_name)?),
+        })
+    }
+}
+
+impl Transport for PcapTransport {
+    fn layer(&self) -> Layer {
+        Layer::Link
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        self.mac_address
+    }
+
+    #[cfg(target_os = "macos")]
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        (&self.bpf_device).write_all(packet)
+    }
+
+    #[cfg(not(target_os = "macos"))]
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        self.sender
+            .lock()
+            .unwrap()
+            .sendpacket(packet)
+            .map_err(pcap_error)
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let deadline = Instant::now() + timeout;
+        let mut cap = self.receiver.lock().unwrap();
+        loop {
+            match cap.next_packet() {
+                Ok(packet) => return Ok(Some(packet.data.to_vec())),
+                Err(pcap::Error::TimeoutExpired) => {
+                    if Instant::now() >= deadline {
+                        return Ok(None);
+                    }
+                }
+                Err(e) => return Err(pcap_error(e)),
+            }
+        }
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Opens a capture on the given interface.
+fn open_capture(interface_name: &str) -> io::Result<pcap::Capture<pcap::Active>> {
+    pcap::Capture::from_device(interface_name)
+        .and_then(|cap| cap.immediate_mode(true).timeout(CAPTURE_POLL_MS).open())
+        .map_err(pcap_error)
+}
+
+/// Converts a pcap error into an I/O error.
+///
+/// pcap reports missing privileges only in its message, which is turned into
+/// `PermissionDenied` so callers can fall back to an unprivileged transport.
+fn pcap_error(e: pcap::Error) -> io::Error {
+    let message = e.to_string();
+    if message.contains("ermission") || message.contains("not permitted") {
+        return io::Error::new(io::ErrorKind::PermissionDenied, e);
+    }
+    io::Error::other(e)
+}
+
+/// Opens the first free BPF device for writing and binds it to the specified interface.
+#[cfg(target_os = "macos")]
+fn open_bpf_device(interface_name: &str) -> io::Result<File> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no BPF device available");
+    for i in 0..256 {
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .open(format!("/dev/bpf{}", i))
+        {
+            Ok(bpf_device) => {
+                // Bind to an interface
+                bind_bpf_to_interface(bpf_device.as_raw_fd(), interface_name)?;
+                return Ok(bpf_device);
+            }
+            // Busy devices are in use by another process, try the next one.
+            Err(e) if e.raw_os_error() == Some(libc::EBUSY) => last_error = e,
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_error)
+}
+
+/// Binds the BPF device to the specified interface.
+#[cfg(target_os = "macos")]
+fn bind_bpf_to_interface(fd: RawFd, interface_name: &str) -> io::Result<()> {
+    unsafe {
+        let mut ifr = ifreq {
+            ifr_name: [0; libc::IFNAMSIZ],
+            ifr_ifru: std::mem::zeroed(),
+        };
+
+        // Copy the interface name into the ifreq structure
+        let bytes = interface_name.as_bytes();
+        for (i, &byte) in bytes.iter().take(libc::IFNAMSIZ - 1).enumerate() {
+            ifr.ifr_name[i] = byte as i8;
+        }
+
+        // Perform the ioctl operation to bind the BPF device to the interface
+        if ioctl(fd, libc::BIOCSETIF, &ifr) == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
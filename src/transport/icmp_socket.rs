@@ -0,0 +1,196 @@
+//! Unprivileged ICMP datagram socket transport on Linux.
+//!
+//! `socket(AF_INET, SOCK_DGRAM, IPPROTO_ICMP)` needs no root, only a group ID
+//! inside `net.ipv4.ping_group_range`. The kernel builds the IPv4 header and routes
+//! the packet itself, so only the ICMP message of each packet is sent, and the
+//! replies are wrapped back into IPv4 packets for the commands. The kernel also
+//! owns the echo identifier, which is the socket's port, so the socket is bound to
+//! the identifier of the first echo request. Only echo replies are received;
+//! errors such as Time Exceeded are not delivered on these sockets.
+
+extern crate libc;
+
+use crate::ipv4::fields::IpProtocol;
+use crate::ipv4::icmp::ICMPPacket;
+use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
+use crate::transport::socket::{poll_readable, set_socket_option, socket_address};
+use crate::transport::{Layer, Transport};
+
+use std::io;
+use std::mem;
+use std::os::fd::RawFd;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Largest ICMP message read from the socket.
+const MAX_PACKET_LEN: usize = 65536;
+
+/// TTL assumed for replies whose TTL the kernel did not report.
+const UNKNOWN_TTL: u8 = 64;
+
+/// Represents a transport on an ICMP datagram socket.
+pub struct IcmpSocketTransport {
+    /// File descriptor of the socket.
+    fd: libc::c_int,
+    /// Source address, TTL and echo identifier of the last packet sent, `None` before the first.
+    ///
+    /// The source address is the destination of the wrapped replies; the socket is
+    /// bound on the first send, to the identifier every later packet carries.
+    last_sent: Mutex<Option<([u8; 4], u8, u16)>>,
+}
+
+impl IcmpSocketTransport {
+    /// Opens an ICMP datagram socket.
+    ///
+    /// Fails with `PermissionDenied` if our group is outside `net.ipv4.ping_group_range`.
+    pub fn open() -> io::Result<IcmpSocketTransport> {
+        let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, libc::IPPROTO_ICMP) };
+        if fd == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        let transport = IcmpSocketTransport {
+            fd,
+            last_sent: Mutex::new(None),
+        };
+        // Have the kernel report the TTL of every reply.
+        set_socket_option(fd, libc::IPPROTO_IP, libc::IP_RECVTTL, 1)?;
+        Ok(transport)
+    }
+}
+
+impl Drop for IcmpSocketTransport {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+impl Transport for IcmpSocketTransport {
+    fn layer(&self) -> Layer {
+        Layer::Network
+    }
+
+    fn name(&self) -> &str {
+        "icmp"
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        if packet.len() < 28 || packet[0] >> 4 != 4 || packet[9] != IpProtocol::ICMP.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "ICMP sockets only carry ICMP messages",
+            ));
+        }
+        let ipv4_packet = IPV4::from_bytes(packet)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "truncated ICMP message"))?;
+
+        let mut last_sent = self.last_sent.lock().unwrap();
+        match *last_sent {
+            None => {
+                // Binding to the identifier keeps it, instead of the kernel picking one.
+                let address = socket_address([0; 4], ipv4_packet.datagram.identifier);
+                let bound = unsafe {
+                    libc::bind(
+                        self.fd,
+                        &address as *const libc::sockaddr_in as *const libc::sockaddr,
+                        mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+                    )
+                };
+                if bound == -1 {
+                    return Err(io::Error::last_os_error());
+                }
+                set_socket_option(self.fd, libc::IPPROTO_IP, libc::IP_TTL, ipv4_packet.ttl as libc::c_int)?;
+            }
+            Some((_, ttl, _)) if ttl != ipv4_packet.ttl => {
+                set_socket_option(self.fd, libc::IPPROTO_IP, libc::IP_TTL, ipv4_packet.ttl as libc::c_int)?;
+            }
+            Some(_) => {}
+        }
+        let identifier = last_sent.map_or(ipv4_packet.datagram.identifier, |(_, _, identifier)| identifier);
+        *last_sent = Some((ipv4_packet.source_add, ipv4_packet.ttl, identifier));
+
+        let destination = socket_address(ipv4_packet.destination_add, 0);
+        let message = ipv4_packet.datagram.to_bytes();
+        let sent = unsafe {
+            libc::sendto(
+                self.fd,
+                message.as_ptr() as *const libc::c_void,
+                message.len(),
+                0,
+                &destination as *const libc::sockaddr_in as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        };
+        if sent == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        if !poll_readable(self.fd, timeout)? {
+            return Ok(None);
+        }
+
+        let mut buffer = vec![0u8; MAX_PACKET_LEN];
+        let mut source: libc::sockaddr_in = unsafe { mem::zeroed() };
+        let mut control = [0u8; 64];
+        let mut iov = libc::iovec {
+            iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+            iov_len: buffer.len(),
+        };
+        let mut message: libc::msghdr = unsafe { mem::zeroed() };
+        message.msg_name = &mut source as *mut libc::sockaddr_in as *mut libc::c_void;
+        message.msg_namelen = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+        message.msg_iov = &mut iov;
+        message.msg_iovlen = 1;
+        message.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+        message.msg_controllen = control.len() as _;
+
+        let len = unsafe { libc::recvmsg(self.fd, &mut message, 0) };
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        buffer.truncate(len as usize);
+        // Anything shorter than an ICMP header is not worth decoding.
+        let Some(datagram) = ICMPPacket::from_bytes(&buffer) else {
+            return Ok(None);
+        };
+
+        let mut ttl = UNKNOWN_TTL;
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(&message);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::IPPROTO_IP && (*cmsg).cmsg_type == libc::IP_TTL {
+                    ttl = *(libc::CMSG_DATA(cmsg) as *const libc::c_int) as u8;
+                }
+                cmsg = libc::CMSG_NXTHDR(&message, cmsg);
+            }
+        }
+
+        let local = self.last_sent.lock().unwrap().map_or([0; 4], |(address, _, _)| address);
+        let ipv4_packet = IPV4::new(
+            datagram,
+            IpProtocol::ICMP,
+            ttl,
+            source.sin_addr.s_addr.to_ne_bytes(),
+            local,
+            None,
+        );
+        Ok(Some(ipv4_packet.to_bytes()))
+    }
+
+    fn readable_fd(&self) -> Option<RawFd> {
+        Some(self.fd)
+    }
+
+    fn echo_identifier(&self) -> Option<u16> {
+        self.last_sent.lock().unwrap().map(|(_, _, identifier)| identifier)
+    }
+}
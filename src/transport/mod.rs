@@ -0,0 +1,118 @@
+//! Transports exchanging raw packets with the network.
+//!
+//! The commands build and parse whole Ethernet frames; a transport carries them to
+//! and from a real interface, a TAP device, or, for network layer transports such as
+//! TUN, ICMP sockets and raw IP sockets, only the IPv4 packet inside them.
+
+// Module declarations for each transport
+pub mod capture;
+#[cfg(target_os = "linux")]
+pub mod icmp_socket;
+pub mod raw_socket;
+pub mod simulated;
+pub(crate) mod socket;
+#[cfg(target_os = "linux")]
+pub mod tuntap;
+
+use crate::ethernet_frame::{EtherPayload, EthernetFrame};
+use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
+
+use std::io;
+use std::os::fd::RawFd;
+use std::time::{Duration, Instant};
+
+/// Layer at which a transport exchanges packets.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layer {
+    /// Whole Ethernet frames.
+    Link,
+    /// Bare IPv4 packets, without an Ethernet header.
+    Network,
+}
+
+/// A way to send and receive raw packets.
+///
+/// Methods take `&self` so one transport can be shared by a sending and a receiving thread.
+pub trait Transport: Send + Sync {
+    /// Layer at which packets are exchanged.
+    fn layer(&self) -> Layer;
+
+    /// Name of the interface the transport is attached to.
+    fn name(&self) -> &str;
+
+    /// MAC address frames are sent from, for link layer transports.
+    fn mac_address(&self) -> Option<MacAddr>;
+
+    /// Address packets should be sent from, if the transport dictates one.
+    fn source_address(&self) -> Option<[u8; 4]> {
+        None
+    }
+
+    /// Sends one raw packet.
+    fn send(&self, packet: &[u8]) -> io::Result<()>;
+
+    /// Receives one raw packet, waiting at most `timeout`.
+    ///
+    /// Returns `None` if nothing arrived in time.
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>>;
+
+    /// File descriptor that turns readable when a packet arrives, for event loops.
+    ///
+    /// Returns `None` if the transport cannot be polled, e.g. a simulated one.
+    fn readable_fd(&self) -> Option<RawFd> {
+        None
+    }
+
+    /// Identifier the transport puts in every echo request it sends, whatever the request held.
+    ///
+    /// Returns `None` if echo requests go out as built; an ICMP socket is bound to the
+    /// identifier of its first request.
+    fn echo_identifier(&self) -> Option<u16> {
+        None
+    }
+
+    /// Current time on the transport's clock, used to time probes and replies.
+    ///
+    /// This is the wall clock, except on simulated transports which keep a virtual one.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    /// Sends an Ethernet frame, or only its IPv4 packet on a network layer transport.
+    fn send_frame(&self, frame: &mut EthernetFrame) -> io::Result<()> {
+        match self.layer() {
+            Layer::Link => self.send(&frame.to_bytes()),
+            Layer::Network => match &mut frame.packet {
+                EtherPayload::Ipv4(packet) => self.send(&packet.to_bytes()),
+                _ => Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    format!("{} only carries IPv4 packets", self.name()),
+                )),
+            },
+        }
+    }
+
+    /// Receives an Ethernet frame, waiting at most `timeout`.
+    ///
+    /// On a network layer transport the IPv4 packet is wrapped in a frame with zero MAC addresses.
+    /// Returns `None` if nothing arrived in time, or if what arrived cannot be decoded.
+    fn recv_frame(&self, timeout: Duration) -> io::Result<Option<EthernetFrame>> {
+        let bytes = match self.recv(timeout)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        match self.layer() {
+            Layer::Link => Ok(EthernetFrame::from_bytes(&bytes)),
+            Layer::Network if bytes.len() >= 20 && bytes[0] >> 4 == 4 => {
+                let Some(packet) = IPV4::from_bytes(&bytes) else { return Ok(None) };
+                let mut frame = EthernetFrame::new_ether(packet);
+                frame.destination_address = MacAddr::ZERO;
+                frame.source_address = MacAddr::ZERO;
+                Ok(Some(frame))
+            }
+            // Truncated and non-IPv4 packets are skipped, as runt frames are above.
+            _ => Ok(None),
+        }
+    }
+}
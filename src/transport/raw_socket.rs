@@ -0,0 +1,149 @@
+//! Raw IP socket transport.
+//!
+//! Packets are written to a `SOCK_RAW` socket with `IP_HDRINCL`, so our own IPv4
+//! header goes out verbatim, TTL, DSCP, options, DF and identification included,
+//! while the kernel routes the packet and resolves the next hop's MAC address. A
+//! second raw socket receives the ICMP packets addressed to the host, header and all.
+
+extern crate libc;
+
+use crate::mac_address::MacAddr;
+use crate::transport::socket::{poll_readable, set_socket_option, socket_address};
+use crate::transport::{Layer, Transport};
+
+use std::io;
+use std::mem;
+use std::os::fd::RawFd;
+use std::time::Duration;
+
+/// Largest packet read from the socket.
+const MAX_PACKET_LEN: usize = 65536;
+
+/// Represents a network layer transport on raw IP sockets.
+pub struct RawSocketTransport {
+    /// Socket sending whole IPv4 packets.
+    sender: libc::c_int,
+    /// Socket receiving ICMP packets.
+    receiver: libc::c_int,
+}
+
+impl RawSocketTransport {
+    /// Opens the raw sockets; needs root or `CAP_NET_RAW`.
+    pub fn open() -> io::Result<RawSocketTransport> {
+        let sender = open_raw_socket(libc::IPPROTO_RAW)?;
+        let receiver = match open_raw_socket(libc::IPPROTO_ICMP) {
+            Ok(receiver) => receiver,
+            Err(e) => {
+                unsafe { libc::close(sender) };
+                return Err(e);
+            }
+        };
+        let transport = RawSocketTransport { sender, receiver };
+        set_socket_option(sender, libc::IPPROTO_IP, libc::IP_HDRINCL, 1)?;
+        Ok(transport)
+    }
+}
+
+impl Drop for RawSocketTransport {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.sender);
+            libc::close(self.receiver);
+        }
+    }
+}
+
+impl Transport for RawSocketTransport {
+    fn layer(&self) -> Layer {
+        Layer::Network
+    }
+
+    fn name(&self) -> &str {
+        "raw"
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        if packet.len() < 20 || packet[0] >> 4 != 4 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not an IPv4 packet"));
+        }
+        #[allow(unused_mut)]
+        let mut packet = packet.to_vec();
+        // BSD raw sockets take the total length and fragment field in host byte order.
+        #[cfg(target_os = "macos")]
+        for field in [2, 6] {
+            let value = u16::from_be_bytes([packet[field], packet[field + 1]]);
+            packet[field..field + 2].copy_from_slice(&value.to_ne_bytes());
+        }
+
+        let destination = socket_address([packet[16], packet[17], packet[18], packet[19]], 0);
+        let sent = unsafe {
+            libc::sendto(
+                self.sender,
+                packet.as_ptr() as *const libc::c_void,
+                packet.len(),
+                0,
+                &destination as *const libc::sockaddr_in as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        };
+        if sent == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        if !poll_readable(self.receiver, timeout)? {
+            return Ok(None);
+        }
+        let mut buffer = vec![0u8; MAX_PACKET_LEN];
+        let len = unsafe {
+            libc::recv(
+                self.receiver,
+                buffer.as_mut_ptr() as *mut libc::c_void,
+                buffer.len(),
+                0,
+            )
+        };
+        if len == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        buffer.truncate(len as usize);
+        // BSD raw sockets hand out the total length in host byte order, less the header.
+        #[cfg(target_os = "macos")]
+        if buffer.len() >= 20 {
+            let total_length = buffer.len() as u16;
+            buffer[2..4].copy_from_slice(&total_length.to_be_bytes());
+        }
+        Ok(Some(buffer))
+    }
+
+    fn readable_fd(&self) -> Option<RawFd> {
+        Some(self.receiver)
+    }
+}
+
+// ---------------HELPER FUNCTIONS----------------
+
+/// Opens a raw IPv4 socket for the given protocol.
+fn open_raw_socket(protocol: libc::c_int) -> io::Result<libc::c_int> {
+    #[cfg(target_os = "linux")]
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, protocol) };
+    #[cfg(not(target_os = "linux"))]
+    let fd = unsafe { libc::socket(libc::AF_INET, libc::SOCK_RAW, protocol) };
+    if fd == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    // BSDs have no SOCK_CLOEXEC, the flag is set once the socket is open.
+    #[cfg(not(target_os = "linux"))]
+    if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+        let e = io::Error::last_os_error();
+        unsafe { libc::close(fd) };
+        return Err(e);
+    }
+    Ok(fd)
+}
@@ -0,0 +1,429 @@
+//! Simulated network for deterministic runs without a NIC.
+//!
+//! Hosts and routers form a tree rooted at the local host. Packets sent on the
+//! transport walk the tree hop by hop: links add latency and jitter and may lose,
+//! duplicate or reorder packets, routers decrement the TTL and answer with ICMP
+//! errors, and hosts answer echo requests. All randomness comes from a seeded
+//! generator and time from a virtual clock that only moves when the transport is
+//! waited on, so a single thread driving it sees the same run every time.
+
+use crate::ipv4::fields::{FragmentFlags, IpProtocol};
+use crate::ipv4::icmp::{
+    ICMPPacket, CODE_FRAGMENTATION_NEEDED, CODE_HOST_UNREACHABLE, DESTINATION_UNREACHABLE, ECHO_REQUEST,
+    TIME_EXCEEDED,
+};
+use crate::ipv4::internet_packet::IPV4;
+use crate::mac_address::MacAddr;
+use crate::transport::{Layer, Transport};
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// TTL of the packets sent by simulated hosts and routers.
+const DEFAULT_TTL: u8 = 64;
+
+/// Properties of a simulated link.
+#[derive(Clone)]
+pub struct LinkConfig {
+    /// One-way delay of every packet.
+    pub latency: Duration,
+    /// Largest extra delay, drawn uniformly for each packet.
+    pub jitter: Duration,
+    /// Probability that a packet is lost.
+    pub loss: f64,
+    /// Probability that a packet is delivered twice.
+    pub duplication: f64,
+    /// Probability that a packet is held back by one more latency, behind later packets.
+    pub reordering: f64,
+    /// Probability that a packet on its way back arrives with a bit flipped in its payload.
+    pub corruption: f64,
+    /// Largest packet the link carries.
+    pub mtu: usize,
+}
+
+impl Default for LinkConfig {
+    fn default() -> LinkConfig {
+        LinkConfig {
+            latency: Duration::from_millis(1),
+            jitter: Duration::ZERO,
+            loss: 0.0,
+            duplication: 0.0,
+            reordering: 0.0,
+            corruption: 0.0,
+            mtu: 1500,
+        }
+    }
+}
+
+/// A router of the simulated network.
+struct Router {
+    /// Address the router answers and sends ICMP errors from.
+    address: [u8; 4],
+    /// Router one hop closer to the local host, `None` if directly connected.
+    parent: Option<usize>,
+    /// Link from the parent, or from the local host, to this router.
+    link: LinkConfig,
+}
+
+/// A host of the simulated network.
+struct Host {
+    /// Address of the host.
+    address: [u8; 4],
+    /// Router the host sits behind, `None` if on the local segment.
+    router: Option<usize>,
+    /// Link from the router, or from the local host, to this host.
+    link: LinkConfig,
+    /// Whether the host answers echo requests, false for firewalled hosts.
+    responds_to_echo: bool,
+}
+
+/// One node on the way to a destination, with the link leading into it.
+struct Hop<'a> {
+    address: [u8; 4],
+    link: &'a LinkConfig,
+    is_router: bool,
+}
+
+/// Mutable state of the simulation.
+struct State {
+    /// Virtual time elapsed since the network was created.
+    clock: Duration,
+    /// State of the xorshift random generator.
+    rng: u64,
+    /// Counter keeping packets due at the same time in the order they were scheduled.
+    next_id: u64,
+    /// Packets on their way to the local host, by arrival time.
+    queue: BinaryHeap<Reverse<(Duration, u64, Vec<u8>)>>,
+}
+
+impl State {
+    /// Returns a uniformly distributed number in `[0, 1)`.
+    fn random(&mut self) -> f64 {
+        self.rng ^= self.rng >> 12;
+        self.rng ^= self.rng << 25;
+        self.rng ^= self.rng >> 27;
+        (self.rng.wrapping_mul(0x2545_f491_4f6c_dd1d) >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Sends a packet over a link.
+    ///
+    /// Returns `None` if the packet was lost, otherwise its delay on the link and
+    /// whether it was duplicated.
+    fn cross(&mut self, link: &LinkConfig) -> Option<(Duration, bool)> {
+        if self.random() < link.loss {
+            return None;
+        }
+        let mut delay = link.latency + link.jitter.mul_f64(self.random());
+        if self.random() < link.reordering {
+            delay += link.latency;
+        }
+        Some((delay, self.random() < link.duplication))
+    }
+
+    /// Queues a packet for the local host.
+    fn deliver(&mut self, at: Duration, packet: Vec<u8>) {
+        self.next_id += 1;
+        self.queue.push(Reverse((at, self.next_id, packet)));
+    }
+}
+
+/// Represents a simulated network, seen from the local host as a network layer transport.
+pub struct SimulatedNetwork {
+    /// Address of the local host.
+    local_address: [u8; 4],
+    /// Routers, indexed by the ids returned from `add_router`.
+    routers: Vec<Router>,
+    /// Hosts.
+    hosts: Vec<Host>,
+    /// Router unknown destinations are sent to, which answers host unreachable.
+    default_router: Option<usize>,
+    /// Instant the virtual clock started at.
+    epoch: Instant,
+    /// Mutable state, shared by senders and receivers.
+    state: Mutex<State>,
+}
+
+impl SimulatedNetwork {
+    /// Creates an empty network around the local host.
+    ///
+    /// # Arguments
+    /// * `local_address` - Address of the local host.
+    /// * `seed` - Seed of the random generator; the same seed gives the same run.
+    pub fn new(local_address: [u8; 4], seed: u64) -> SimulatedNetwork {
+        SimulatedNetwork {
+            local_address,
+            routers: Vec::new(),
+            hosts: Vec::new(),
+            default_router: None,
+            epoch: Instant::now(),
+            state: Mutex::new(State {
+                clock: Duration::ZERO,
+                // Xorshift never leaves zero, so a zero seed is replaced.
+                rng: if seed == 0 { 0x9e37_79b9_7f4a_7c15 } else { seed },
+                next_id: 0,
+                queue: BinaryHeap::new(),
+            }),
+        }
+    }
+
+    /// Adds a router and returns its id.
+    ///
+    /// # Arguments
+    /// * `address` - Address of the router.
+    /// * `parent` - Router one hop closer to the local host, `None` if directly connected.
+    /// * `link` - Link from the parent to the new router.
+    pub fn add_router(&mut self, address: [u8; 4], parent: Option<usize>, link: LinkConfig) -> usize {
+        self.routers.push(Router { address, parent, link });
+        self.routers.len() - 1
+    }
+
+    /// Adds a host.
+    ///
+    /// # Arguments
+    /// * `address` - Address of the host.
+    /// * `router` - Router the host sits behind, `None` if on the local segment.
+    /// * `link` - Link from the router to the host.
+    /// * `responds_to_echo` - Whether the host answers echo requests.
+    pub fn add_host(&mut self, address: [u8; 4], router: Option<usize>, link: LinkConfig, responds_to_echo: bool) {
+        self.hosts.push(Host {
+            address,
+            router,
+            link,
+            responds_to_echo,
+        });
+    }
+
+    /// Sets the router that unknown destinations are sent to.
+    pub fn set_default_router(&mut self, router: Option<usize>) {
+        self.default_router = router;
+    }
+
+    /// Builds the lab network used by the `--sim` option.
+    ///
+    /// ```text
+    /// 10.0.0.2 (local) -- 10.0.0.1 -- 192.0.2.1 (MTU 1400) -- 198.51.100.1 (lossy WAN)
+    ///       |                 |              |                      |
+    ///   10.0.0.10-20      (gateway)    10.0.1.10-20           203.0.113.10, .11 (firewalled)
+    /// ```
+    pub fn lab(seed: u64) -> SimulatedNetwork {
+        let mut network = SimulatedNetwork::new([10, 0, 0, 2], seed);
+        let lan = LinkConfig {
+            latency: Duration::from_micros(300),
+            jitter: Duration::from_micros(100),
+            ..LinkConfig::default()
+        };
+        let gateway = network.add_router([10, 0, 0, 1], None, lan.clone());
+        let core = network.add_router(
+            [192, 0, 2, 1],
+            Some(gateway),
+            LinkConfig {
+                latency: Duration::from_millis(5),
+                jitter: Duration::from_millis(1),
+                mtu: 1400,
+                ..LinkConfig::default()
+            },
+        );
+        let wan = network.add_router(
+            [198, 51, 100, 1],
+            Some(core),
+            LinkConfig {
+                latency: Duration::from_millis(20),
+                jitter: Duration::from_millis(5),
+                loss: 0.05,
+                duplication: 0.01,
+                reordering: 0.02,
+                corruption: 0.01,
+                ..LinkConfig::default()
+            },
+        );
+        for i in 10..=20 {
+            network.add_host([10, 0, 0, i], None, lan.clone(), true);
+            network.add_host([10, 0, 1, i], Some(core), lan.clone(), true);
+        }
+        network.add_host([203, 0, 113, 10], Some(wan), lan.clone(), true);
+        network.add_host([203, 0, 113, 11], Some(wan), lan, false);
+        network.set_default_router(Some(core));
+        network
+    }
+
+    /// Lists the nodes from the local host to a router, with the links leading into them.
+    fn path_to_router(&self, router: Option<usize>) -> Vec<Hop<'_>> {
+        let mut path = Vec::new();
+        let mut next = router;
+        while let Some(id) = next {
+            let router = &self.routers[id];
+            path.push(Hop {
+                address: router.address,
+                link: &router.link,
+                is_router: true,
+            });
+            next = router.parent;
+        }
+        path.reverse();
+        path
+    }
+
+    /// Lists the nodes from the local host to a destination.
+    ///
+    /// Returns the path and whether the destination is a node of the network. For an
+    /// unknown destination the path ends at the default router, and is empty without one.
+    fn path_to(&self, destination: [u8; 4]) -> (Vec<Hop<'_>>, bool) {
+        if let Some(host) = self.hosts.iter().find(|host| host.address == destination) {
+            let mut path = self.path_to_router(host.router);
+            path.push(Hop {
+                address: host.address,
+                link: &host.link,
+                is_router: false,
+            });
+            return (path, true);
+        }
+        if let Some(id) = self.routers.iter().position(|router| router.address == destination) {
+            let mut path = self.path_to_router(self.routers[id].parent);
+            path.push(Hop {
+                address: self.routers[id].address,
+                link: &self.routers[id].link,
+                is_router: false,
+            });
+            return (path, true);
+        }
+        (self.path_to_router(self.default_router), false)
+    }
+
+    /// Sends an ICMP message from the node at `index` of `path` back to the local host.
+    fn send_back(&self, state: &mut State, path: &[Hop], index: usize, at: Duration, datagram: ICMPPacket) {
+        // Routers between the node and us decrement the TTL on the way back.
+        let routers = path[..index].iter().filter(|hop| hop.is_router).count();
+        let packet = IPV4::new(
+            datagram,
+            IpProtocol::ICMP,
+            DEFAULT_TTL - routers as u8,
+            path[index].address,
+            self.local_address,
+            None,
+        );
+
+        let mut at = at;
+        let mut duplicated = false;
+        let mut corrupted = false;
+        for hop in path[..=index].iter().rev() {
+            match state.cross(hop.link) {
+                Some((delay, duplicate)) => {
+                    at += delay;
+                    duplicated |= duplicate;
+                }
+                None => return,
+            }
+            corrupted |= hop.link.corruption > 0.0 && state.random() < hop.link.corruption;
+        }
+        let mut bytes = packet.to_bytes();
+        if corrupted {
+            // The last byte always belongs to the ICMP message, leaving the IPv4 header intact.
+            *bytes.last_mut().unwrap() ^= 0x01;
+        }
+        if duplicated {
+            state.deliver(at, bytes.clone());
+        }
+        state.deliver(at, bytes);
+    }
+}
+
+impl Transport for SimulatedNetwork {
+    fn layer(&self) -> Layer {
+        Layer::Network
+    }
+
+    fn name(&self) -> &str {
+        "sim0"
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        None
+    }
+
+    fn source_address(&self) -> Option<[u8; 4]> {
+        Some(self.local_address)
+    }
+
+    fn send(&self, bytes: &[u8]) -> io::Result<()> {
+        let packet = match IPV4::from_bytes(bytes) {
+            Some(packet) if bytes[0] >> 4 == 4 => packet,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not an IPv4 packet")),
+        };
+        let (path, known) = self.path_to(packet.destination_add);
+        let mut state = self.state.lock().unwrap();
+
+        if let Some(first) = path.first() {
+            if bytes.len() > first.link.mtu {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "message too long"));
+            }
+        }
+
+        let mut at = state.clock;
+        let mut ttl = packet.ttl;
+        for (i, hop) in path.iter().enumerate() {
+            // The previous router cannot forward a packet larger than the next link with DF set.
+            if i > 0 && bytes.len() > hop.link.mtu && packet.flags.contains(FragmentFlags::DF) {
+                let error = ICMPPacket::new_error(
+                    DESTINATION_UNREACHABLE,
+                    CODE_FRAGMENTATION_NEEDED,
+                    hop.link.mtu as u16,
+                    bytes,
+                );
+                self.send_back(&mut state, &path, i - 1, at, error);
+                return Ok(());
+            }
+            match state.cross(hop.link) {
+                Some((delay, _)) => at += delay,
+                None => return Ok(()),
+            }
+            if hop.is_router && i + 1 < path.len() {
+                ttl = ttl.saturating_sub(1);
+                if ttl == 0 {
+                    let error = ICMPPacket::new_error(TIME_EXCEEDED, 0, 0, bytes);
+                    self.send_back(&mut state, &path, i, at, error);
+                    return Ok(());
+                }
+            }
+        }
+
+        if !known {
+            // The default router has no route to the destination.
+            if !path.is_empty() {
+                let error = ICMPPacket::new_error(DESTINATION_UNREACHABLE, CODE_HOST_UNREACHABLE, 0, bytes);
+                self.send_back(&mut state, &path, path.len() - 1, at, error);
+            }
+            return Ok(());
+        }
+
+        let responds = self
+            .hosts
+            .iter()
+            .find(|host| host.address == packet.destination_add)
+            .is_none_or(|host| host.responds_to_echo);
+        if responds && packet.protocol == IpProtocol::ICMP && packet.datagram.packet_type == ECHO_REQUEST {
+            let reply = ICMPPacket::new_echo_reply(&packet.datagram);
+            self.send_back(&mut state, &path, path.len() - 1, at, reply);
+        }
+        Ok(())
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        let mut state = self.state.lock().unwrap();
+        let deadline = state.clock + timeout;
+        let due = matches!(state.queue.peek(), Some(Reverse((at, _, _))) if *at <= deadline);
+        if !due {
+            state.clock = deadline;
+            return Ok(None);
+        }
+        let Reverse((at, _, packet)) = state.queue.pop().unwrap();
+        state.clock = state.clock.max(at);
+        Ok(Some(packet))
+    }
+
+    fn now(&self) -> Instant {
+        self.epoch + self.state.lock().unwrap().clock
+    }
+}
@@ -0,0 +1,61 @@
+//! Helpers shared by the socket based transports.
+
+extern crate libc;
+
+use std::io;
+use std::mem;
+use std::time::Duration;
+
+/// Builds an IPv4 socket address.
+pub(crate) fn socket_address(address: [u8; 4], port: u16) -> libc::sockaddr_in {
+    let mut socket_address: libc::sockaddr_in = unsafe { mem::zeroed() };
+    socket_address.sin_family = libc::AF_INET as libc::sa_family_t;
+    socket_address.sin_port = port.to_be();
+    // `s_addr` is in network byte order, the order of the octets.
+    socket_address.sin_addr.s_addr = u32::from_ne_bytes(address);
+    socket_address
+}
+
+/// Sets an integer socket option.
+pub(crate) fn set_socket_option(
+    fd: libc::c_int,
+    level: libc::c_int,
+    option: libc::c_int,
+    value: libc::c_int,
+) -> io::Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            level,
+            option,
+            &value as *const libc::c_int as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if result == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+/// Waits at most `timeout` for a file descriptor to become readable.
+///
+/// Returns `false` if it did not in time, or if a signal interrupted the wait.
+pub(crate) fn poll_readable(fd: libc::c_int, timeout: Duration) -> io::Result<bool> {
+    let mut pollfd = libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    let timeout_ms = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+    let ready = unsafe { libc::poll(&mut pollfd, 1, timeout_ms) };
+    if ready == -1 {
+        let e = io::Error::last_os_error();
+        // A signal, e.g. Ctrl-C, cut the wait short; the caller checks whether to go on.
+        if e.kind() == io::ErrorKind::Interrupted {
+            return Ok(false);
+        }
+        return Err(e);
+    }
+    Ok(ready > 0)
+}
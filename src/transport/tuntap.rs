@@ -0,0 +1,117 @@
+//! Linux TUN/TAP transports.
+//!
+//! A TAP device exchanges whole Ethernet frames with the kernel and a TUN device
+//! bare IPv4 packets, so the commands can talk to the kernel's own stack over a
+//! virtual link without a real NIC. The device should exist beforehand, e.g.
+//! `ip tuntap add dev tap0 mode tap`, so its addresses can be configured.
+
+extern crate libc;
+
+use crate::mac_address::MacAddr;
+use crate::transport::socket::poll_readable;
+use crate::transport::{Layer, Transport};
+
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::time::Duration;
+
+/// `_IOW('T', 202, int)`, attaches a file descriptor of `/dev/net/tun` to a device.
+const TUNSETIFF: libc::c_ulong = 0x400454ca;
+
+/// Largest packet read from the device.
+const MAX_PACKET_LEN: usize = 65536;
+
+/// Represents a transport on a TUN or TAP device.
+pub struct TunTapTransport {
+    /// Name of the device, as assigned by the kernel.
+    name: String,
+    /// `Layer::Link` for TAP, `Layer::Network` for TUN.
+    layer: Layer,
+    /// MAC address our end of a TAP link sends from.
+    mac_address: Option<MacAddr>,
+    /// Open `/dev/net/tun` attached to the device.
+    device: File,
+}
+
+impl TunTapTransport {
+    /// Opens the TAP device with the given name, exchanging Ethernet frames.
+    ///
+    /// Our end of the link uses a locally administered MAC address, distinct from the
+    /// kernel's end, so the kernel treats us as another host on the segment.
+    pub fn open_tap(name: &str) -> io::Result<TunTapTransport> {
+        let mut transport = TunTapTransport::open(name, libc::IFF_TAP, Layer::Link)?;
+        let pid = std::process::id().to_be_bytes();
+        transport.mac_address = Some(MacAddr([0x02, 0x00, pid[0], pid[1], pid[2], pid[3]]));
+        Ok(transport)
+    }
+
+    /// Opens the TUN device with the given name, exchanging IPv4 packets.
+    pub fn open_tun(name: &str) -> io::Result<TunTapTransport> {
+        TunTapTransport::open(name, libc::IFF_TUN, Layer::Network)
+    }
+
+    /// Opens `/dev/net/tun` and attaches it to the device.
+    fn open(name: &str, kind: libc::c_int, layer: Layer) -> io::Result<TunTapTransport> {
+        let device = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")?;
+
+        let name = unsafe {
+            let mut ifr: libc::ifreq = std::mem::zeroed();
+            for (i, &byte) in name.as_bytes().iter().take(libc::IFNAMSIZ - 1).enumerate() {
+                ifr.ifr_name[i] = byte as libc::c_char;
+            }
+            // Without packet information, reads and writes are the bare frame or packet.
+            ifr.ifr_ifru.ifru_flags = (kind | libc::IFF_NO_PI) as libc::c_short;
+
+            if libc::ioctl(device.as_raw_fd(), TUNSETIFF, &mut ifr) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+            CStr::from_ptr(ifr.ifr_name.as_ptr()).to_string_lossy().into_owned()
+        };
+
+        Ok(TunTapTransport {
+            name,
+            layer,
+            mac_address: None,
+            device,
+        })
+    }
+}
+
+impl Transport for TunTapTransport {
+    fn layer(&self) -> Layer {
+        self.layer
+    }
+
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mac_address(&self) -> Option<MacAddr> {
+        self.mac_address
+    }
+
+    fn send(&self, packet: &[u8]) -> io::Result<()> {
+        // Each write is one frame or packet, it is never split.
+        (&self.device).write_all(packet)
+    }
+
+    fn recv(&self, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+        if !poll_readable(self.device.as_raw_fd(), timeout)? {
+            return Ok(None);
+        }
+
+        let mut buffer = vec![0; MAX_PACKET_LEN];
+        let len = (&self.device).read(&mut buffer)?;
+        buffer.truncate(len);
+        Ok(Some(buffer))
+    }
+
+    fn readable_fd(&self) -> Option<RawFd> {
+        Some(self.device.as_raw_fd())
+    }
+}